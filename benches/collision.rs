@@ -0,0 +1,164 @@
+// Benchmarks for the collision pipeline.
+//
+// Run with `cargo bench`. To compare a change against a reference, save a baseline on the
+// reference commit with `cargo bench -- --save-baseline before`, then run
+// `cargo bench -- --baseline before` with the change applied.
+use balls::{
+    collision::{adaptive_cell_size, CollisionDetectionData, CELL_SIZE},
+    simulation::{init_simulation, SimulationConfig},
+    world_gen::{init_world, GenerationConfig},
+};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use legion::{Resources, Schedule, World};
+
+const BALL_COUNTS: [usize; 4] = [100, 500, 1000, 2000];
+// Balls per unit of area of the default scene, 150 balls in 1600x800.
+const DEFAULT_DENSITY: f64 = 150. / (1600. * 800.);
+
+fn init_headless(n_balls: usize) -> (World, Resources) {
+    init_headless_with(n_balls, |_| CELL_SIZE)
+}
+
+fn init_headless_with(n_balls: usize, cell_size: fn(&World) -> f64) -> (World, Resources) {
+    // Keep the density of the default scene.
+    let side = (n_balls as f64 / DEFAULT_DENSITY).sqrt() as u32;
+    init_headless_in(n_balls, side, side, |world| {
+        let cell_size = cell_size(world);
+        (cell_size, cell_size)
+    })
+}
+
+fn init_headless_in(
+    n_balls: usize,
+    width: u32,
+    height: u32,
+    cell_sizes: impl Fn(&World) -> (f64, f64),
+) -> (World, Resources) {
+    let mut world = World::default();
+    init_world(
+        &mut world,
+        GenerationConfig::builder()
+            .width(width)
+            .height(height)
+            .n_balls(n_balls)
+            .build(),
+    );
+    let mut resources = Resources::default();
+    init_simulation(
+        &mut resources,
+        SimulationConfig::builder().cap_frame_time(false).build(),
+    );
+    let (cell_size_x, cell_size_y) = cell_sizes(&world);
+    resources.insert(CollisionDetectionData::with_cell_sizes(
+        width as f64,
+        height as f64,
+        cell_size_x,
+        cell_size_y,
+    ));
+    (world, resources)
+}
+
+// Broad and narrow phase, then the despawns of merged balls, as in the main schedule.
+fn collision_schedule() -> Schedule {
+    Schedule::builder()
+        .add_system(balls::collision::collision_system())
+        .add_system(balls::collision::collision_handle_system())
+        .add_system(balls::lifecycle::apply_lifecycle_system())
+        .flush()
+        .build()
+}
+
+fn bench_collision(c: &mut Criterion) {
+    let mut group = c.benchmark_group("collision");
+    for n_balls in BALL_COUNTS.iter() {
+        let mut schedule = collision_schedule();
+        group.bench_with_input(BenchmarkId::from_parameter(n_balls), n_balls, |b, n| {
+            b.iter_batched(
+                || init_headless(*n),
+                |(mut world, mut resources)| schedule.execute(&mut world, &mut resources),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// Fixed cell size against the one picked from the generated radii.
+fn bench_cell_size(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cell_size");
+    let cell_sizes: [(&str, fn(&World) -> f64); 2] =
+        [("fixed", |_| CELL_SIZE), ("adaptive", adaptive_cell_size)];
+    for (name, cell_size) in cell_sizes.iter() {
+        let mut schedule = collision_schedule();
+        group.bench_function(BenchmarkId::new(*name, 1000), |b| {
+            b.iter_batched(
+                || init_headless_with(1000, *cell_size),
+                |(mut world, mut resources)| schedule.execute(&mut world, &mut resources),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// Square cells against wider ones, on a wide and short world.
+fn bench_anisotropic_cells(c: &mut Criterion) {
+    let mut group = c.benchmark_group("anisotropic_cells");
+    let (width, height) = (4000, 500);
+    let n_balls = (DEFAULT_DENSITY * width as f64 * height as f64) as usize;
+    let cell_sizes = [
+        ("square", (CELL_SIZE, CELL_SIZE)),
+        ("2x1", (2. * CELL_SIZE, CELL_SIZE)),
+        ("4x1", (4. * CELL_SIZE, CELL_SIZE)),
+    ];
+    for (name, cell_size) in cell_sizes.iter() {
+        let mut schedule = collision_schedule();
+        group.bench_function(BenchmarkId::new(*name, n_balls), |b| {
+            b.iter_batched(
+                || init_headless_in(n_balls, width, height, |_| *cell_size),
+                |(mut world, mut resources)| schedule.execute(&mut world, &mut resources),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_advance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("advance_balls");
+    for n_balls in BALL_COUNTS.iter() {
+        let mut schedule = Schedule::builder()
+            .add_system(balls::advance::clear_trails_system(0))
+            .add_system(balls::advance::advance_balls_system())
+            .build();
+        group.bench_with_input(BenchmarkId::from_parameter(n_balls), n_balls, |b, n| {
+            b.iter_batched(
+                || init_headless(*n),
+                |(mut world, mut resources)| schedule.execute(&mut world, &mut resources),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_world_gen(c: &mut Criterion) {
+    let mut group = c.benchmark_group("init_world");
+    group.sample_size(10);
+    for n_balls in [1000, 10000].iter() {
+        group.bench_with_input(BenchmarkId::from_parameter(n_balls), n_balls, |b, n| {
+            b.iter(|| init_headless(*n))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_collision,
+    bench_cell_size,
+    bench_anisotropic_cells,
+    bench_advance,
+    bench_world_gen
+);
+criterion_main!(benches);
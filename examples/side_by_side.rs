@@ -0,0 +1,147 @@
+// Runs two simulations of the same scene with different parameters, drawn side by side.
+//
+// Run with `cargo run --example side_by_side`. The left one bounces elastically, the right one
+// loses energy in every bounce.
+use balls::collision::{CollisionDetectionData, PhysicsConfig};
+use balls::render::{init_graphics, push_scene, render_frame, DisplayConfig, Tile};
+use balls::simulation::{init_simulation, Simulation, SimulationConfig, SimulationData};
+use balls::wall::Wall;
+use balls::world_gen::{init_world, GenerationConfig};
+use legion::*;
+use std::time::Instant;
+use winit::event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::ControlFlow;
+
+const WORLD_WIDTH: u32 = 800;
+const WORLD_HEIGHT: u32 = 800;
+
+// Restitution of the bounces between balls and off the walls.
+fn init_instance(restitution: f64, cap_frame_time: bool) -> Simulation {
+    let mut world = World::default();
+    init_world(
+        &mut world,
+        GenerationConfig::builder()
+            .width(WORLD_WIDTH)
+            .height(WORLD_HEIGHT)
+            .n_balls(75)
+            .build(),
+    );
+    for wall in <&mut Wall>::query().iter_mut(&mut world) {
+        wall.restitution = restitution;
+    }
+    let mut resources = Resources::default();
+    init_simulation(
+        &mut resources,
+        SimulationConfig::builder()
+            .cap_frame_time(cap_frame_time)
+            .build(),
+    );
+    resources.insert(PhysicsConfig {
+        restitution,
+        ..PhysicsConfig::default()
+    });
+    resources.insert(CollisionDetectionData::new(
+        WORLD_WIDTH as f64,
+        WORLD_HEIGHT as f64,
+    ));
+
+    // Merged balls are gone before drawing.
+    let schedule = Schedule::builder()
+        .add_system(balls::advance::clear_trails_system(0))
+        .add_system(balls::collision::collision_system())
+        .add_system(balls::collision::collision_handle_system())
+        .add_system(balls::advance::advance_balls_system())
+        .add_system(balls::lifecycle::apply_lifecycle_system())
+        .flush()
+        .build();
+    Simulation::new(world, resources, schedule)
+}
+
+pub fn main() {
+    // Both worlds share the window, so the graphics are not a resource of either.
+    let (mut graphics, event_loop) = init_graphics(
+        DisplayConfig::builder()
+            .width(2 * WORLD_WIDTH)
+            .height(WORLD_HEIGHT)
+            .world_width(WORLD_WIDTH as f64)
+            .world_height(WORLD_HEIGHT as f64)
+            .title("Balls side by side".to_string())
+            .build(),
+    );
+    // Only the first one caps the frame time, the frame is shared.
+    let mut simulations = vec![init_instance(1., true), init_instance(0.9, false)];
+    let tiles = [
+        Tile {
+            x: 0.,
+            y: 0.,
+            width: 0.5,
+            height: 1.,
+        },
+        Tile {
+            x: 0.5,
+            y: 0.,
+            width: 0.5,
+            height: 1.,
+        },
+    ];
+
+    // Time advances after drawing, once the frame shows every world.
+    let mut advance_time = Schedule::builder()
+        .add_system(balls::simulation::advance_time_system())
+        .build();
+    let mut last_frame = Instant::now();
+
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent {
+            event: WindowEvent::CloseRequested,
+            ..
+        }
+        | Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => {
+            *control_flow = ControlFlow::Exit;
+        }
+        Event::RedrawEventsCleared => {
+            if let Some(deadline) = simulations[0].frame_deadline(last_frame) {
+                if Instant::now() < deadline {
+                    if *control_flow != ControlFlow::Exit {
+                        *control_flow = ControlFlow::WaitUntil(deadline);
+                    }
+                    return;
+                }
+            }
+            last_frame = Instant::now();
+            for simulation in simulations.iter_mut() {
+                simulation.step();
+            }
+            render_frame(&mut graphics, |quads, config| {
+                for (simulation, tile) in simulations.iter().zip(tiles.iter()) {
+                    push_scene(
+                        quads,
+                        config,
+                        tile,
+                        &simulation.world,
+                        &simulation.resources.get::<SimulationData>().unwrap(),
+                        &simulation
+                            .resources
+                            .get::<CollisionDetectionData>()
+                            .unwrap(),
+                    );
+                }
+            });
+            for simulation in simulations.iter_mut() {
+                advance_time.execute(&mut simulation.world, &mut simulation.resources);
+            }
+        }
+        _ => (),
+    });
+}
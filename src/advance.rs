@@ -1,22 +1,59 @@
-use legion::*;
+use legion::{query::component, world::SubWorld, *};
+use nalgebra::Vector2;
+use std::time::Instant;
 
 use crate::{
-    ball::{Ball, Trail, Trails},
+    ball::{Ball, Kinematic, Pinned, Trail, Trails},
+    collision::PhysicsConfig,
+    profile::{ProfileData, Stage},
     simulation::SimulationData,
 };
 
+// Keeps the last max_trail_history trails of previous frames, for long exposure streaks.
 #[system(par_for_each)]
-pub fn clear_trails(trails: &mut Trails) {
-    trails.trails.clear();
+pub fn clear_trails(trails: &mut Trails, #[state] max_trail_history: &usize) {
+    let excess = trails.trails.len().saturating_sub(*max_trail_history);
+    trails.trails.drain(..excess);
 }
 
+// Slows balls down by the drag of the physics config. Kinematic balls keep their velocity.
 #[system(par_for_each)]
-pub fn advance_balls(
+#[filter(!component::<Kinematic>())]
+pub fn apply_drag(
     ball: &mut Ball,
-    trails: &mut Trails,
     #[resource] simulation_data: &SimulationData,
+    #[resource] physics_config: &PhysicsConfig,
+) {
+    if physics_config.drag == 0. {
+        return;
+    }
+    let time_delta = simulation_data.next_time - simulation_data.time;
+    ball.velocity *= (1. - physics_config.drag).powf(time_delta);
+}
+
+// Runs right before collision detection, after the systems changing velocities. The solvers then
+// predict pinned balls at rest, and advancing them leaves them where they are.
+#[system(par_for_each)]
+#[filter(component::<Pinned>())]
+pub fn hold_pinned_balls(ball: &mut Ball) {
+    ball.velocity = Vector2::new(0., 0.);
+}
+
+#[system]
+#[write_component(Ball)]
+#[write_component(Trails)]
+pub fn advance_balls(
+    world: &mut SubWorld,
+    #[resource] simulation_data: &SimulationData,
+    #[resource] profile_data: &mut ProfileData,
 ) {
-    advance_single_ball(ball, trails, simulation_data.next_time);
+    let start = Instant::now();
+    // Each ball only reads its own state, so the parallel order does not matter. Systems where
+    // balls depend on each other read them all before writing, see interaction_forces.
+    <(&mut Ball, &mut Trails)>::query().par_for_each_mut(world, |(ball, trails)| {
+        advance_single_ball(ball, trails, simulation_data.next_time)
+    });
+    profile_data.record(Stage::Advance, start.elapsed());
 }
 
 pub fn advance_single_ball(ball: &mut Ball, trails: &mut Trails, next_time: f64) {
@@ -30,5 +67,6 @@ pub fn advance_single_ball(ball: &mut Ball, trails: &mut Trails, next_time: f64)
         });
     }
     ball.position = new_position;
+    ball.radius += ball.growth_rate * (next_time - ball.initial_time);
     ball.initial_time = next_time;
 }
@@ -5,10 +5,39 @@ pub struct Ball {
     pub position: Vector2<f64>,
     pub velocity: Vector2<f64>,
     pub radius: f64,
+    // Change of radius per unit of time.
+    pub growth_rate: f64,
     pub initial_time: f64,
     pub color: Vector3<f32>,
+    // Color the ball has when fully cooled down.
+    pub base_color: Vector3<f32>,
+    // Grows with each collision and decays over time.
+    pub heat: f32,
 }
 
+// Balls smaller than this are point particles. They weigh as much as a ball of this radius, so
+// collisions with them do not produce huge impulses.
+pub const POINT_RADIUS: f64 = 0.5;
+
+impl Ball {
+    pub fn is_point(&self) -> bool {
+        self.radius < POINT_RADIUS
+    }
+
+    pub fn mass(&self) -> f64 {
+        let radius = self.radius.max(POINT_RADIUS);
+        radius * radius
+    }
+}
+
+pub const HEAT_PER_COLLISION: f32 = 1.;
+
+// Identifies a ball for its whole life, across frames and snapshots, unlike entities, which legion
+// may reuse. Unique within a world. Generated worlds number their balls in order of placement,
+// and balls spawned while running are numbered by Lifecycle, so the same run gives the same ids.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BallId(pub u64);
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Trail {
     pub position0: Vector2<f64>,
@@ -21,3 +50,32 @@ pub struct Trail {
 pub struct Trails {
     pub trails: Vec<Trail>,
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct CollisionCount {
+    pub count: u64,
+}
+
+// Change of momentum from the last impulse the ball received, for effects scaled to how hard it
+// was hit. Effects may decay the magnitude themselves, collisions overwrite it.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct LastImpulse {
+    pub magnitude: f64,
+    pub time: f64,
+}
+
+// Angular velocity of a ball, counterclockwise, in radians per unit of time. Changed by contacts
+// between balls with friction, see PhysicsConfig::ball_friction.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Spin {
+    pub angular_velocity: f64,
+}
+
+// Marks balls that move along their velocity regardless of collisions, pushing others away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Kinematic;
+
+// Marks balls that never move but still collide, like round obstacles. They are not affected by
+// impulses, as if their mass was infinite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Pinned;
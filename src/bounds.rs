@@ -0,0 +1,42 @@
+use legion::*;
+use log::warn;
+
+use crate::ball::Ball;
+
+// Safety net for balls tunneling out of the world rectangle, which is [0, width] x [0, height].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeepInBounds {
+    pub width: f64,
+    pub height: f64,
+}
+
+// Mirrors a coordinate back inside [min, max], pointing the velocity inwards.
+fn reflect_into(position: &mut f64, velocity: &mut f64, min: f64, max: f64) {
+    if *position < min {
+        *position = (2. * min - *position).min(max);
+        *velocity = velocity.abs();
+    } else if *position > max {
+        *position = (2. * max - *position).max(min);
+        *velocity = -velocity.abs();
+    }
+}
+
+// Runs after advance_balls. Only balls entirely outside are moved, the walls handle the rest.
+#[system(for_each)]
+pub fn keep_in_bounds(entity: &Entity, ball: &mut Ball, #[resource] bounds: &KeepInBounds) {
+    let outside = ball.position.x < -ball.radius
+        || ball.position.x > bounds.width + ball.radius
+        || ball.position.y < -ball.radius
+        || ball.position.y > bounds.height + ball.radius;
+    if !outside {
+        return;
+    }
+    warn!(
+        "Ball {:?} escaped the world at {:?}, moving it back.",
+        entity, ball.position
+    );
+    let (x_min, x_max) = (ball.radius, (bounds.width - ball.radius).max(ball.radius));
+    let (y_min, y_max) = (ball.radius, (bounds.height - ball.radius).max(ball.radius));
+    reflect_into(&mut ball.position.x, &mut ball.velocity.x, x_min, x_max);
+    reflect_into(&mut ball.position.y, &mut ball.velocity.y, y_min, y_max);
+}
@@ -1,11 +1,148 @@
+// Default solver tolerance, see PhysicsConfig.
 pub const EPSILON: f64 = 1e-5;
 
+// What colliding balls do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "config_file", derive(serde::Deserialize))]
+pub enum CollisionResponse {
+    // Elastic bounce.
+    Bounce,
+    // Both balls become one, keeping their total area and momentum. Kinematic and pinned balls,
+    // and simultaneous contacts grouped together, still bounce.
+    Merge,
+}
+
+// Tolerances of the collision solver and response to collisions, a resource.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "config_file", derive(serde::Deserialize), serde(default))]
+pub struct PhysicsConfig {
+    // Margin of the broad phase boxes and of the time windows of collisions. Larger values catch
+    // more grazing and near simultaneous collisions, smaller ones resolve them more precisely.
+    // Should grow with the scale of the world.
+    pub epsilon: f64,
+    // Bounce by default.
+    pub collision_response: CollisionResponse,
+    // Of ball-ball bounces, from 0 for perfectly inelastic to 1 for elastic. Walls have their own.
+    pub restitution: f64,
+    // Coulomb friction between the surfaces of balls in contact, which makes them exchange spin.
+    // 0 for frictionless contacts, the default.
+    pub ball_friction: f64,
+    // Multiplies the pull of gravity wells.
+    pub gravity_scale: f64,
+    // Fraction of their velocity balls lose per unit of time, see apply_drag.
+    pub drag: f64,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> PhysicsConfig {
+        PhysicsConfig {
+            epsilon: EPSILON,
+            collision_response: CollisionResponse::Bounce,
+            restitution: 1.,
+            ball_friction: 0.,
+            gravity_scale: 1.,
+            drag: 0.,
+        }
+    }
+}
+
+// Fields of PhysicsConfig that can be tuned while running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PhysicsParameter {
+    Restitution,
+    GravityScale,
+    Drag,
+}
+
+impl PhysicsParameter {
+    // Name in logs and recordings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PhysicsParameter::Restitution => "restitution",
+            PhysicsParameter::GravityScale => "gravity_scale",
+            PhysicsParameter::Drag => "drag",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PhysicsParameter> {
+        match name {
+            "restitution" => Some(PhysicsParameter::Restitution),
+            "gravity_scale" => Some(PhysicsParameter::GravityScale),
+            "drag" => Some(PhysicsParameter::Drag),
+            _ => None,
+        }
+    }
+
+    // Change per key press.
+    pub fn step(&self) -> f64 {
+        match self {
+            PhysicsParameter::Restitution => 0.05,
+            PhysicsParameter::GravityScale => 0.1,
+            PhysicsParameter::Drag => 0.01,
+        }
+    }
+
+    // Values the parameter is clamped to.
+    pub fn range(&self) -> (f64, f64) {
+        match self {
+            PhysicsParameter::Restitution => (0., 1.),
+            PhysicsParameter::GravityScale => (0., 10.),
+            PhysicsParameter::Drag => (0., 1.),
+        }
+    }
+}
+
+impl PhysicsConfig {
+    fn parameter_mut(&mut self, parameter: PhysicsParameter) -> &mut f64 {
+        match parameter {
+            PhysicsParameter::Restitution => &mut self.restitution,
+            PhysicsParameter::GravityScale => &mut self.gravity_scale,
+            PhysicsParameter::Drag => &mut self.drag,
+        }
+    }
+
+    // Changes a parameter by delta, within its range. Returns the new value.
+    pub fn adjust(&mut self, parameter: PhysicsParameter, delta: f64) -> f64 {
+        let (min, max) = parameter.range();
+        let value = self.parameter_mut(parameter);
+        *value = (*value + delta).max(min).min(max);
+        *value
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
 pub enum CollidableType {
     Ball,
     Wall,
+    Polygon,
+    Rect,
+    // Any shape with a CustomCollider component, see shapes.rs.
+    Custom,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
 pub struct Generation {
     pub generation: i64,
 }
+
+// Which collidables may collide. Two collide when the mask of each has a bit of the category of
+// the other. Collidables without a filter use the default one, which collides with everything.
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+pub struct CollisionFilter {
+    pub category: u32,
+    pub mask: u32,
+}
+
+impl Default for CollisionFilter {
+    fn default() -> CollisionFilter {
+        CollisionFilter {
+            category: 1,
+            mask: u32::MAX,
+        }
+    }
+}
+
+impl CollisionFilter {
+    pub fn collides_with(&self, other: &CollisionFilter) -> bool {
+        self.mask & other.category != 0 && other.mask & self.category != 0
+    }
+}
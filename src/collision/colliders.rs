@@ -1,16 +1,25 @@
-use super::collidable::{CollidableType, Generation};
+use super::{
+    collidable::{CollidableType, Generation, PhysicsConfig},
+    shapes::{shape_collider, Collider},
+};
 use legion::{
     world::{EntryRef, SubWorld},
     Entity, EntityStore,
 };
+use nalgebra::Vector2;
 
 use crate::{
     advance::advance_single_ball,
-    ball::{Ball, Trails},
+    ball::{
+        Ball, CollisionCount, Kinematic, LastImpulse, Pinned, Spin, Trails, HEAT_PER_COLLISION,
+        POINT_RADIUS,
+    },
+    polygon::Polygon,
+    rect::Rect,
     wall::Wall,
 };
 
-#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, PartialOrd, Ord)]
 pub struct GenerationalCollisionEntity {
     pub entity: Entity,
     pub generation: i64,
@@ -30,44 +39,93 @@ impl EntityAndRef<'_> {
     }
 }
 
-//get_component_unchecked
+// Closer centers give no usable direction.
+const MIN_CENTER_DISTANCE: f64 = 1e-12;
+
+// Unit normal from the second center to the first. Concentric balls have no line of centers, they
+// are pushed apart along x rather than getting a NaN normal.
+pub fn contact_normal(position0: &Vector2<f64>, position1: &Vector2<f64>) -> Vector2<f64> {
+    (position0 - position1)
+        .try_normalize(MIN_CENTER_DISTANCE)
+        .unwrap_or_else(|| Vector2::new(1., 0.))
+}
+
+// Kinematic and pinned balls are not affected by impulses, as if their mass was infinite.
+fn inverse_mass(entry: &EntityAndRef, ball: &Ball) -> f64 {
+    if entry.entry.get_component::<Kinematic>().is_ok()
+        || entry.entry.get_component::<Pinned>().is_ok()
+    {
+        return 0.;
+    }
+    1. / ball.mass()
+}
+
+// Balls are uniform discs, with a moment of inertia of m*r^2/2.
+fn inverse_inertia(entry: &EntityAndRef, ball: &Ball) -> f64 {
+    let radius = ball.radius.max(POINT_RADIUS);
+    2. * inverse_mass(entry, ball) / (radius * radius)
+}
+
+// Safety: as for the other components written by colliders, the entry must not be borrowed
+// elsewhere.
+unsafe fn record_impulse(entry: &EntityAndRef, magnitude: f64, time: f64) {
+    *entry
+        .entry
+        .get_component_unchecked::<LastImpulse>()
+        .unwrap() = LastImpulse { magnitude, time };
+}
+
+// Restitution and friction of the physics config apply to ball-ball collisions, shapes have their
+// own.
 pub fn collide<'a>(
     world: &SubWorld,
     entry0: &EntityAndRef,
     entry1: &EntityAndRef,
     t: f64,
+    physics_config: &PhysicsConfig,
 ) -> Vec<GenerationalCollisionEntity> {
-    let collidable_type0 = entry0.entry.get_component::<CollidableType>().unwrap();
-    let collidable_type1 = entry1.entry.get_component::<CollidableType>().unwrap();
-    match collidable_type0 {
-        CollidableType::Ball => match collidable_type1 {
-            CollidableType::Ball => collide_ball_ball(world, entry0, entry1, t),
-            CollidableType::Wall => collide_ball_wall(world, entry0, entry1, t),
+    let is_ball = |entry: &EntityAndRef| entry.entry.get_component::<Ball>().is_ok();
+    match (is_ball(entry0), is_ball(entry1)) {
+        (true, true) => collide_ball_ball(world, entry0, entry1, t, physics_config),
+        (true, false) => match shape_collider(&entry1.entry) {
+            Some(collider) => collide_ball_shape(entry0, collider, t),
+            None => vec![],
         },
-        CollidableType::Wall => match collidable_type1 {
-            CollidableType::Ball => collide_ball_wall(world, entry1, entry0, t),
-            CollidableType::Wall => vec![],
+        (false, true) => match shape_collider(&entry0.entry) {
+            Some(collider) => collide_ball_shape(entry1, collider, t),
+            None => vec![],
         },
+        (false, false) => vec![],
     }
 }
 
-fn collide_ball_wall<'a>(
-    world: &SubWorld,
+// Kinematic balls follow their path through shapes. Pinned ones stay put, they are only reached
+// when growing.
+fn collide_ball_shape(
     entry0: &EntityAndRef,
-    entry1: &EntityAndRef,
+    collider: &dyn Collider,
     t: f64,
 ) -> Vec<GenerationalCollisionEntity> {
+    if entry0.entry.get_component::<Kinematic>().is_ok()
+        || entry0.entry.get_component::<Pinned>().is_ok()
+    {
+        return vec![];
+    }
     unsafe {
         let mut ball = entry0.entry.get_component_unchecked::<Ball>().unwrap();
-        let wall = entry1.entry.get_component::<Wall>().unwrap();
-        // Wall does not move.
+        // Shapes do not move.
         let mut trails = entry0.entry.get_component_unchecked::<Trails>().unwrap();
         advance_single_ball(&mut ball, &mut trails, t);
 
-        let normal = wall.normal();
-        let proj = ball.velocity.dot(&normal);
-        if proj < 0. {
-            ball.velocity -= proj * normal * 2.;
+        if let Some(delta_v) = collider.respond(&ball, t) {
+            ball.velocity -= delta_v;
+            ball.heat += HEAT_PER_COLLISION;
+            record_impulse(entry0, ball.mass() * delta_v.norm(), t);
+            entry0
+                .entry
+                .get_component_unchecked::<CollisionCount>()
+                .unwrap()
+                .count += 1;
             let mut generation = entry0
                 .entry
                 .get_component_unchecked::<Generation>()
@@ -82,11 +140,79 @@ fn collide_ball_wall<'a>(
     }
 }
 
+// Whether a ball can be merged into another, see CollisionResponse::Merge.
+pub fn is_mergeable(entry: &EntityAndRef) -> bool {
+    entry.entry.get_component::<Ball>().is_ok()
+        && entry.entry.get_component::<Kinematic>().is_err()
+        && entry.entry.get_component::<Pinned>().is_err()
+}
+
+// Turns the first ball into the merger of both, with their total area at their center of mass,
+// moving with their total momentum. The second ball is left out of the collision data by bumping
+// its generation, and must be despawned by the caller.
+pub fn merge_ball_ball(
+    entry0: &EntityAndRef,
+    entry1: &EntityAndRef,
+    t: f64,
+) -> Vec<GenerationalCollisionEntity> {
+    unsafe {
+        let mut ball0 = entry0.entry.get_component_unchecked::<Ball>().unwrap();
+        let mut ball1 = entry1.entry.get_component_unchecked::<Ball>().unwrap();
+        let mut trails0 = entry0.entry.get_component_unchecked::<Trails>().unwrap();
+        let mut trails1 = entry1.entry.get_component_unchecked::<Trails>().unwrap();
+        advance_single_ball(&mut ball0, &mut trails0, t);
+        advance_single_ball(&mut ball1, &mut trails1, t);
+
+        let (mass0, mass1) = (ball0.mass(), ball1.mass());
+        let mass = mass0 + mass1;
+        let velocity = (mass0 * ball0.velocity + mass1 * ball1.velocity) / mass;
+        record_impulse(entry0, mass0 * (velocity - ball0.velocity).norm(), t);
+        ball0.position = (mass0 * ball0.position + mass1 * ball1.position) / mass;
+        ball0.velocity = velocity;
+        ball0.radius = (ball0.radius * ball0.radius + ball1.radius * ball1.radius).sqrt();
+        ball0.heat += HEAT_PER_COLLISION;
+        entry0
+            .entry
+            .get_component_unchecked::<CollisionCount>()
+            .unwrap()
+            .count += 1;
+
+        entry1
+            .entry
+            .get_component_unchecked::<Generation>()
+            .unwrap()
+            .generation += 1;
+        let mut generation0 = entry0
+            .entry
+            .get_component_unchecked::<Generation>()
+            .unwrap();
+        generation0.generation += 1;
+        vec![GenerationalCollisionEntity {
+            entity: entry0.entity,
+            generation: generation0.generation,
+        }]
+    }
+}
+
+// Closest boundary point of a filled static obstacle, None for other collidables.
+pub fn obstacle_closest_point(entry: &EntityAndRef, point: &Vector2<f64>) -> Option<Vector2<f64>> {
+    if let Ok(polygon) = entry.entry.get_component::<Polygon>() {
+        return Some(polygon.closest_point(point));
+    }
+    if let Ok(rect) = entry.entry.get_component::<Rect>() {
+        return Some(rect.closest_point(point));
+    }
+    None
+}
+
+// Rigid disc contact. With friction, the tangential impulse changes the spins of the balls, and
+// their velocities along the contact.
 fn collide_ball_ball<'a>(
     world: &SubWorld,
     entry0: &EntityAndRef,
     entry1: &EntityAndRef,
     t: f64,
+    physics_config: &PhysicsConfig,
 ) -> Vec<GenerationalCollisionEntity> {
     unsafe {
         let mut ball0 = entry0.entry.get_component_unchecked::<Ball>().unwrap();
@@ -105,37 +231,315 @@ fn collide_ball_ball<'a>(
         advance_single_ball(&mut ball0, &mut trails0, t);
         advance_single_ball(&mut ball1, &mut trails1, t);
 
-        let mass0 = ball0.radius * ball0.radius;
-        let mass1 = ball1.radius * ball1.radius;
-        let dx = ball0.position - ball1.position;
+        let normal = contact_normal(&ball0.position, &ball1.position);
         let dv = ball0.velocity - ball1.velocity;
-        // Check if they are moving towards each other.
-        let proj = dv.dot(&dx);
-        if proj < 0. {
-            let d2 = dx.dot(&dx);
-            let a = 2. / (mass0 + mass1) * proj / d2 * dx;
-            ball0.velocity -= mass1 * a;
-            if ball0.velocity.norm() > 1000. {
-                ball0.velocity *= 1000. / ball0.velocity.norm();
+        let distance = (ball0.position - ball1.position).norm();
+        // Check if they are moving towards each other, counting the growth of the surfaces.
+        let proj = dv.dot(&normal) - (ball0.growth_rate + ball1.growth_rate);
+        let inverse_mass0 = inverse_mass(entry0, &ball0);
+        let inverse_mass1 = inverse_mass(entry1, &ball1);
+        // Two immovable balls exchange nothing. Generations are kept, so the pair is not predicted
+        // again.
+        if proj < 0. && inverse_mass0 + inverse_mass1 > 0. {
+            let a =
+                (1. + physics_config.restitution) / (inverse_mass0 + inverse_mass1) * proj * normal;
+            let tangential_impulse = friction_impulse(
+                entry0,
+                entry1,
+                &ball0,
+                &ball1,
+                &normal,
+                a.norm() * physics_config.ball_friction,
+            );
+            ball0.heat += HEAT_PER_COLLISION;
+            ball1.heat += HEAT_PER_COLLISION;
+            // Both balls receive the same impulse, in opposite directions.
+            for entry in [entry0, entry1].iter() {
+                entry
+                    .entry
+                    .get_component_unchecked::<CollisionCount>()
+                    .unwrap()
+                    .count += 1;
+                record_impulse(entry, a.norm(), t);
             }
-            ball1.velocity += mass0 * a;
-            if ball1.velocity.norm() > 1000. {
-                ball1.velocity *= 1000. / ball1.velocity.norm();
-            }
-            generation0.generation += 1;
-            generation1.generation += 1;
 
-            return vec![
-                GenerationalCollisionEntity {
+            // Kinematic and pinned balls keep their velocity, so their predicted collisions stay
+            // valid.
+            let mut new_entities = vec![];
+            if inverse_mass0 > 0. {
+                ball0.velocity -= inverse_mass0 * (a - tangential_impulse);
+                if ball0.velocity.norm() > 1000. {
+                    ball0.velocity *= 1000. / ball0.velocity.norm();
+                }
+                generation0.generation += 1;
+                new_entities.push(GenerationalCollisionEntity {
                     entity: entry0.entity.clone(),
                     generation: generation0.generation,
-                },
-                GenerationalCollisionEntity {
+                });
+            }
+            if inverse_mass1 > 0. {
+                ball1.velocity += inverse_mass1 * (a - tangential_impulse);
+                if ball1.velocity.norm() > 1000. {
+                    ball1.velocity *= 1000. / ball1.velocity.norm();
+                }
+                generation1.generation += 1;
+                new_entities.push(GenerationalCollisionEntity {
                     entity: entry1.entity.clone(),
                     generation: generation1.generation,
-                },
-            ];
+                });
+            }
+            return new_entities;
+        }
+        // Overlapping without approaching, see solve_collision_ball_ball. Push them apart along
+        // their line of centers until they touch, shared by inverse mass like an impulse.
+        let overlap = ball0.radius + ball1.radius - distance;
+        if overlap > 0. && inverse_mass0 + inverse_mass1 > 0. {
+            let correction = overlap / (inverse_mass0 + inverse_mass1) * normal;
+            let mut new_entities = vec![];
+            if inverse_mass0 > 0. {
+                ball0.position += inverse_mass0 * correction;
+                generation0.generation += 1;
+                new_entities.push(GenerationalCollisionEntity {
+                    entity: entry0.entity.clone(),
+                    generation: generation0.generation,
+                });
+            }
+            if inverse_mass1 > 0. {
+                ball1.position -= inverse_mass1 * correction;
+                generation1.generation += 1;
+                new_entities.push(GenerationalCollisionEntity {
+                    entity: entry1.entity.clone(),
+                    generation: generation1.generation,
+                });
+            }
+            return new_entities;
         }
         vec![]
     }
 }
+
+// Impulse on the first ball along the contact, from the friction between the surfaces, at most
+// max_impulse. Applies it to the spins, the caller applies it to the velocities. The normal points
+// from the second ball to the first.
+unsafe fn friction_impulse(
+    entry0: &EntityAndRef,
+    entry1: &EntityAndRef,
+    ball0: &Ball,
+    ball1: &Ball,
+    normal: &Vector2<f64>,
+    max_impulse: f64,
+) -> Vector2<f64> {
+    if max_impulse <= 0. {
+        return Vector2::new(0., 0.);
+    }
+    let mut spin0 = entry0.entry.get_component_unchecked::<Spin>().unwrap();
+    let mut spin1 = entry1.entry.get_component_unchecked::<Spin>().unwrap();
+    let tangent = Vector2::new(-normal.y, normal.x);
+    let (radius0, radius1) = (
+        ball0.radius.max(POINT_RADIUS),
+        ball1.radius.max(POINT_RADIUS),
+    );
+    // The contact point is at -radius0*normal from the first center, at radius1*normal from the
+    // second one.
+    let sliding = ball0.velocity.dot(&tangent)
+        - spin0.angular_velocity * radius0
+        - ball1.velocity.dot(&tangent)
+        - spin1.angular_velocity * radius1;
+    let (inverse_inertia0, inverse_inertia1) = (
+        inverse_inertia(entry0, ball0),
+        inverse_inertia(entry1, ball1),
+    );
+    let effective_inverse_mass = inverse_mass(entry0, ball0)
+        + inverse_mass(entry1, ball1)
+        + inverse_inertia0 * radius0 * radius0
+        + inverse_inertia1 * radius1 * radius1;
+    if effective_inverse_mass == 0. {
+        return Vector2::new(0., 0.);
+    }
+    // Stops the sliding if friction allows.
+    let impulse = (-sliding / effective_inverse_mass)
+        .max(-max_impulse)
+        .min(max_impulse);
+    spin0.angular_velocity -= inverse_inertia0 * impulse * radius0;
+    spin1.angular_velocity -= inverse_inertia1 * impulse * radius1;
+    impulse * tangent
+}
+
+// Projected Gauss-Seidel sweeps used to resolve a group of simultaneous contacts.
+const GROUP_ITERATIONS: usize = 10;
+
+struct Contact {
+    ball0: usize,
+    // None for static objects.
+    ball1: Option<usize>,
+    // Points from the second object to the first.
+    normal: Vector2<f64>,
+    // Target for the relative normal velocity, the approach speed scaled by the restitution.
+    target: f64,
+    impulse: f64,
+}
+
+fn group_ball_index<'a, 'b>(
+    balls: &mut Vec<&'b EntityAndRef<'a>>,
+    entry: &'b EntityAndRef<'a>,
+) -> usize {
+    if let Some(index) = balls.iter().position(|ball| ball.entity == entry.entity) {
+        return index;
+    }
+    balls.push(entry);
+    balls.len() - 1
+}
+
+// Resolves contacts happening at the same time together, instead of one pair after the other.
+// Sequential resolution of a packed cluster can inject energy, since each pair ignores the rest.
+pub fn collide_group(
+    pairs: &[(EntityAndRef, EntityAndRef)],
+    t: f64,
+    ball_restitution: f64,
+) -> Vec<GenerationalCollisionEntity> {
+    unsafe {
+        // Collect the balls involved and advance each of them to the collision time.
+        let mut balls = Vec::<&EntityAndRef>::new();
+        for (entry0, entry1) in pairs {
+            for entry in [entry0, entry1].iter() {
+                if *entry.entry.get_component::<CollidableType>().unwrap() == CollidableType::Ball {
+                    group_ball_index(&mut balls, *entry);
+                }
+            }
+        }
+        let mut positions = Vec::with_capacity(balls.len());
+        let mut velocities = Vec::with_capacity(balls.len());
+        let mut inverse_masses = Vec::with_capacity(balls.len());
+        for entry in balls.iter() {
+            let mut ball = entry.entry.get_component_unchecked::<Ball>().unwrap();
+            let mut trails = entry.entry.get_component_unchecked::<Trails>().unwrap();
+            advance_single_ball(&mut ball, &mut trails, t);
+            positions.push(ball.position);
+            velocities.push(ball.velocity);
+            inverse_masses.push(inverse_mass(entry, &ball));
+        }
+
+        // Build the contacts that are still approaching.
+        let mut contacts = Vec::<Contact>::new();
+        // Custom shapes only tell how one ball bounces off them, they are resolved after the group.
+        let mut custom_pairs = vec![];
+        for (entry0, entry1) in pairs {
+            let (ball_entry, other_entry) =
+                match *entry0.entry.get_component::<CollidableType>().unwrap() {
+                    CollidableType::Ball => (entry0, entry1),
+                    _ => (entry1, entry0),
+                };
+            let ball0 = group_ball_index(&mut balls, ball_entry);
+            let (ball1, normal, restitution) =
+                match *other_entry.entry.get_component::<CollidableType>().unwrap() {
+                    CollidableType::Ball => {
+                        let ball1 = group_ball_index(&mut balls, other_entry);
+                        (
+                            Some(ball1),
+                            contact_normal(&positions[ball0], &positions[ball1]),
+                            ball_restitution,
+                        )
+                    }
+                    CollidableType::Wall => {
+                        if inverse_masses[ball0] == 0. {
+                            continue;
+                        }
+                        let wall = other_entry.entry.get_component::<Wall>().unwrap();
+                        let normal = wall.normal();
+                        let d = normal.dot(&(positions[ball0] - wall.p0));
+                        if !wall.blocks_from(d) {
+                            continue;
+                        }
+                        (None, normal * d.signum(), wall.restitution)
+                    }
+                    CollidableType::Polygon | CollidableType::Rect => {
+                        if inverse_masses[ball0] == 0. {
+                            continue;
+                        }
+                        let closest =
+                            obstacle_closest_point(other_entry, &positions[ball0]).unwrap();
+                        (None, contact_normal(&positions[ball0], &closest), 1.)
+                    }
+                    CollidableType::Custom => {
+                        custom_pairs.push((ball_entry, other_entry));
+                        continue;
+                    }
+                };
+            let relative_velocity =
+                velocities[ball0] - ball1.map_or(Vector2::new(0., 0.), |ball1| velocities[ball1]);
+            let approach = relative_velocity.dot(&normal);
+            let inverse_mass1 = ball1.map_or(0., |ball1| inverse_masses[ball1]);
+            if approach >= 0. || inverse_masses[ball0] + inverse_mass1 == 0. {
+                continue;
+            }
+            contacts.push(Contact {
+                ball0,
+                ball1,
+                normal,
+                target: -approach * restitution,
+                impulse: 0.,
+            });
+        }
+
+        // Accumulate non negative impulses until all contacts are satisfied together.
+        for _ in 0..GROUP_ITERATIONS {
+            for contact in contacts.iter_mut() {
+                let (velocity1, inverse_mass1) =
+                    contact.ball1.map_or((Vector2::new(0., 0.), 0.), |ball1| {
+                        (velocities[ball1], inverse_masses[ball1])
+                    });
+                let normal_velocity = (velocities[contact.ball0] - velocity1).dot(&contact.normal);
+                let delta = (contact.target - normal_velocity)
+                    / (inverse_masses[contact.ball0] + inverse_mass1);
+                let impulse = (contact.impulse + delta).max(0.);
+                let applied = impulse - contact.impulse;
+                contact.impulse = impulse;
+                velocities[contact.ball0] +=
+                    inverse_masses[contact.ball0] * applied * contact.normal;
+                if let Some(ball1) = contact.ball1 {
+                    velocities[ball1] -= inverse_mass1 * applied * contact.normal;
+                }
+            }
+        }
+
+        // Write back the balls that received an impulse.
+        let mut hit = vec![false; balls.len()];
+        for contact in contacts.iter().filter(|contact| contact.impulse > 0.) {
+            hit[contact.ball0] = true;
+            if let Some(ball1) = contact.ball1 {
+                hit[ball1] = true;
+            }
+        }
+        let mut new_entities = vec![];
+        for (index, entry) in balls.iter().enumerate() {
+            if !hit[index] || inverse_masses[index] == 0. {
+                continue;
+            }
+            let mut ball = entry.entry.get_component_unchecked::<Ball>().unwrap();
+            let magnitude = ball.mass() * (velocities[index] - ball.velocity).norm();
+            ball.velocity = velocities[index];
+            ball.heat += HEAT_PER_COLLISION;
+            record_impulse(entry, magnitude, t);
+            entry
+                .entry
+                .get_component_unchecked::<CollisionCount>()
+                .unwrap()
+                .count += 1;
+            let mut generation = entry.entry.get_component_unchecked::<Generation>().unwrap();
+            generation.generation += 1;
+            new_entities.push(GenerationalCollisionEntity {
+                entity: entry.entity,
+                generation: generation.generation,
+            });
+        }
+        for (ball_entry, other_entry) in custom_pairs {
+            if let Some(collider) = shape_collider(&other_entry.entry) {
+                for entity in collide_ball_shape(ball_entry, collider, t) {
+                    new_entities.retain(|other| other.entity != entity.entity);
+                    new_entities.push(entity);
+                }
+            }
+        }
+        new_entities
+    }
+}
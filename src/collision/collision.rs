@@ -1,90 +1,440 @@
 use super::{
-    collidable::{CollidableType, Generation, EPSILON},
-    colliders::{collide, EntityAndRef, GenerationalCollisionEntity},
+    collidable::{CollidableType, CollisionFilter, CollisionResponse, Generation, PhysicsConfig},
+    colliders::{
+        collide, collide_group, contact_normal, is_mergeable, merge_ball_ball,
+        obstacle_closest_point, EntityAndRef, GenerationalCollisionEntity,
+    },
+    shapes::CustomCollider,
     solvers::{get_movement_bounding_box, solve_collision},
 };
-use crate::{ball::Ball, ball::Trails, simulation::SimulationData, wall::Wall};
+use crate::{
+    ball::Ball, ball::BallId, ball::CollisionCount, ball::Kinematic, ball::LastImpulse,
+    ball::Pinned, ball::Spin, ball::Trails, lifecycle::Lifecycle, polygon::Polygon,
+    profile::ProfileData, profile::Stage, rect::Rect, simulation::PerfStats,
+    simulation::SimulationData, wall::Wall,
+};
 use fnv::FnvHashMap;
 use fnv::FnvHashSet;
 use legion::{
     query::View,
     system,
     world::{EntryRef, SubWorld},
-    Entity,
+    Entity, World,
 };
 use legion::{EntityStore, IntoQuery};
-use log::debug;
+use log::{debug, error, warn};
+use nalgebra::Vector2;
 use ordered_float::OrderedFloat;
 use priority_queue::PriorityQueue;
+use std::{cmp::Reverse, time::Instant};
+
+pub const CELL_SIZE: f64 = 20.;
+// Cells span this many mean ball diameters with adaptive_cell_size.
+const CELLS_PER_DIAMETER: f64 = 2.;
+// Default max_bucketed_radius, in cells.
+const MAX_BUCKETED_RADIUS_CELLS: f64 = 4.;
+// Overlap beyond which a resolved collision is reported as an invariant violation.
+const MAX_OVERLAP: f64 = 0.1;
+
+type CollisionPair = (GenerationalCollisionEntity, GenerationalCollisionEntity);
+// Earliest time first. Ties are broken by the stable keys of the pair, lowest first, so the order
+// is reproducible across runs and restores.
+type CollisionPriority = (OrderedFloat<f64>, Reverse<(StableKey, StableKey)>);
+
+// Order of collidables that does not depend on their entities, which differ between runs. Balls
+// are ordered by id, shapes by their order in the world, which never changes since they are not
+// spawned or despawned while running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum StableKey {
+    Shape(usize),
+    Ball(BallId),
+}
 
-const CELL_SIZE: f64 = 20.;
+// A resolved collision, as seen just before the impulse.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CollisionRecord {
+    pub time: f64,
+    pub entity0: Entity,
+    pub entity1: Entity,
+    // Contact point.
+    pub position: Vector2<f64>,
+    // Approach speed along the contact normal.
+    pub impact_speed: f64,
+    // Radii of the entities at the time of the collision, None for those that are not balls.
+    pub radius0: Option<f64>,
+    pub radius1: Option<f64>,
+}
+
+// What the broad phase does with movements reaching outside the grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClampPolicy {
+    // Ignore the cells outside the grid. Objects entirely outside collide with nothing.
+    Discard,
+    // Put the cells outside the grid into the nearest edge cells, so objects outside still
+    // collide with those near the edge.
+    ClampToEdge,
+    // Grow the grid to include the cells outside.
+    Expand,
+}
+
+// Objects outside the grid keep colliding, at the cost of crowding the edge cells. Discard
+// trades those collisions for speed, and counts them in SkippedCollisions::out_of_grid.
+impl Default for ClampPolicy {
+    fn default() -> ClampPolicy {
+        ClampPolicy::ClampToEdge
+    }
+}
+
+// Collisions that may have been missed in a frame, and why.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct SkippedCollisions {
+    // Events left for the next frame by max_collisions_per_frame.
+    pub deferred: usize,
+    // Movements cut by the edge of the grid with ClampPolicy::Discard.
+    pub out_of_grid: usize,
+    // Pairs sharing a cell whose collision filters exclude each other. Intended, unlike the others.
+    pub filtered: usize,
+}
+
+impl SkippedCollisions {
+    // Collisions lost to the limits of the collision detection, not counting filtered pairs.
+    pub fn lost(&self) -> usize {
+        self.deferred + self.out_of_grid
+    }
+}
 
 // This is ugly.
-#[derive(Default)]
+#[derive(Clone)]
 pub struct CollisionDetectionData {
     spatial_buckets: FnvHashMap<(i32, i32), FnvHashSet<GenerationalCollisionEntity>>,
     last_box: FnvHashMap<GenerationalCollisionEntity, (i32, i32, i32, i32)>,
-    collisions_events: PriorityQueue<
-        (GenerationalCollisionEntity, GenerationalCollisionEntity),
-        OrderedFloat<f64>,
-    >,
+    // Balls larger than max_bucketed_radius. They are kept out of the buckets, and tested against
+    // every other collidable instead.
+    oversized: FnvHashSet<GenerationalCollisionEntity>,
+    collisions_events: PriorityQueue<CollisionPair, CollisionPriority>,
     // TODO: Set that remembers?
-}
-
-fn get_cell_range_for_movement(
-    world: &SubWorld,
-    entry: &EntryRef,
-    next_time: f64,
-) -> (i32, i32, i32, i32) {
-    let (min_coords, max_coords) = get_movement_bounding_box(world, &entry, next_time);
-    return (
-        std::cmp::max(0, (min_coords.x / CELL_SIZE).floor() as i32),
-        std::cmp::min(100, (max_coords.x / CELL_SIZE).ceil() as i32) + 1,
-        std::cmp::max(0, (min_coords.y / CELL_SIZE).floor() as i32),
-        std::cmp::min(100, (max_coords.y / CELL_SIZE).ceil() as i32) + 1,
-    );
+    // Width and height of a cell, which differ for anisotropic grids.
+    cell_size: Vector2<f64>,
+    // First cell of the grid, and number of cells along each axis. The grid covers the world
+    // unless expanded by ClampPolicy::Expand.
+    grid_origin: (i32, i32),
+    grid_width: i32,
+    grid_height: i32,
+    // Handling of movements outside the grid. ClampToEdge by default.
+    pub clamp_policy: ClampPolicy,
+    // Radius above which balls are not put in the buckets. A ball spanning many cells would fill
+    // them all, and gather most of the world as candidates. MAX_BUCKETED_RADIUS_CELLS cells by
+    // default.
+    pub max_bucketed_radius: f64,
+    // Check that every resolved collision leaves the objects separating. Off by default.
+    pub debug_invariants: bool,
+    // Resolve collisions happening at the same time together. Off by default.
+    pub group_simultaneous_contacts: bool,
+    // Keep a record of the collisions resolved in the last frame. Off by default.
+    pub record_collisions: bool,
+    // Collisions resolved per frame at most, which bounds the cost of dense scenes. The others
+    // are deferred to the next frame, and resolved at its start. None for no limit, the default.
+    pub max_collisions_per_frame: Option<usize>,
+    // Collisions left over by the last frame, with their times.
+    deferred: Vec<(CollisionPair, f64)>,
+    skipped: SkippedCollisions,
+    records: Vec<CollisionRecord>,
+    // Time each pair of entities was last resolved in this frame, whatever their generations.
+    last_resolved: FnvHashMap<(Entity, Entity), f64>,
+    // Of every collidable of the frame.
+    stable_keys: FnvHashMap<Entity, StableKey>,
 }
 
 impl CollisionDetectionData {
+    pub fn new(world_width: f64, world_height: f64) -> CollisionDetectionData {
+        CollisionDetectionData::with_cell_size(world_width, world_height, CELL_SIZE)
+    }
+
+    pub fn with_cell_size(
+        world_width: f64,
+        world_height: f64,
+        cell_size: f64,
+    ) -> CollisionDetectionData {
+        CollisionDetectionData::with_cell_sizes(world_width, world_height, cell_size, cell_size)
+    }
+
+    // Cells of a different width and height. Wide cells suit wide and short worlds, where square
+    // cells would make a long and thin grid.
+    pub fn with_cell_sizes(
+        world_width: f64,
+        world_height: f64,
+        cell_size_x: f64,
+        cell_size_y: f64,
+    ) -> CollisionDetectionData {
+        CollisionDetectionData {
+            spatial_buckets: Default::default(),
+            last_box: Default::default(),
+            oversized: Default::default(),
+            collisions_events: Default::default(),
+            cell_size: Vector2::new(cell_size_x, cell_size_y),
+            grid_width: (world_width / cell_size_x).ceil() as i32,
+            grid_height: (world_height / cell_size_y).ceil() as i32,
+            grid_origin: (0, 0),
+            clamp_policy: ClampPolicy::default(),
+            max_bucketed_radius: MAX_BUCKETED_RADIUS_CELLS * cell_size_x.min(cell_size_y),
+            debug_invariants: false,
+            group_simultaneous_contacts: false,
+            record_collisions: false,
+            max_collisions_per_frame: None,
+            deferred: vec![],
+            skipped: SkippedCollisions::default(),
+            records: vec![],
+            last_resolved: Default::default(),
+            stable_keys: Default::default(),
+        }
+    }
+
+    pub fn cell_size(&self) -> Vector2<f64> {
+        self.cell_size
+    }
+
+    pub fn grid_size(&self) -> (i32, i32) {
+        (self.grid_width, self.grid_height)
+    }
+
+    pub fn grid_origin(&self) -> (i32, i32) {
+        self.grid_origin
+    }
+
+    // Cells holding collidables, with their number of entries.
+    pub fn occupied_cells(&self) -> impl Iterator<Item = ((i32, i32), usize)> + '_ {
+        self.spatial_buckets
+            .iter()
+            .filter(|(_, cell_set)| !cell_set.is_empty())
+            .map(|(cell, cell_set)| (*cell, cell_set.len()))
+    }
+
+    // Pairs sharing a cell of the grid, which the broad phase tests against each other. Each pair
+    // comes once, from the first cell both occupy. Entries left behind by earlier generations of
+    // an entity are skipped, and so are oversized balls, which are in no cell.
+    pub fn candidate_pairs(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+        self.spatial_buckets
+            .iter()
+            .flat_map(move |(cell, cell_set)| {
+                cell_set
+                    .iter()
+                    .flat_map(move |entity0| cell_set.iter().map(move |entity1| (entity0, entity1)))
+                    .filter(move |(entity0, entity1)| {
+                        entity0 < entity1
+                            && self.is_latest_generation(entity0)
+                            && self.is_latest_generation(entity1)
+                            && self.first_shared_cell(entity0, entity1) == *cell
+                    })
+                    .map(|(entity0, entity1)| (entity0.entity, entity1.entity))
+            })
+    }
+
+    fn is_latest_generation(&self, entity: &GenerationalCollisionEntity) -> bool {
+        !self.last_box.contains_key(&GenerationalCollisionEntity {
+            entity: entity.entity,
+            generation: entity.generation + 1,
+        })
+    }
+
+    // Lowest cell of the overlap of the cell ranges of two entries sharing a cell.
+    fn first_shared_cell(
+        &self,
+        entity0: &GenerationalCollisionEntity,
+        entity1: &GenerationalCollisionEntity,
+    ) -> (i32, i32) {
+        let (i0, _, j0, _) = self.last_box[entity0];
+        let (k0, _, l0, _) = self.last_box[entity1];
+        (i0.max(k0), j0.max(l0))
+    }
+
+    // Collisions of the last frame that may have been missed.
+    pub fn skipped(&self) -> SkippedCollisions {
+        self.skipped
+    }
+
+    // Collisions resolved during the last frame, in order, if record_collisions is set.
+    pub fn records(&self) -> &[CollisionRecord] {
+        &self.records
+    }
+
+    // Time of the earliest collision found by the last broad phase and not resolved yet.
+    pub fn peek_next_collision_time(&self) -> Option<f64> {
+        // The queue pops the largest priority first, so times are stored negated.
+        self.collisions_events
+            .peek()
+            .map(|(_, (ordered_t, _))| -ordered_t.0)
+    }
+
+    // Drops all per frame state, and the collisions deferred from the last frame.
+    pub fn reset(&mut self) {
+        self.spatial_buckets.clear();
+        self.last_box.clear();
+        self.oversized.clear();
+        self.collisions_events.clear();
+        self.deferred.clear();
+        self.skipped = SkippedCollisions::default();
+        self.records.clear();
+        self.last_resolved.clear();
+        self.stable_keys.clear();
+    }
+
+    fn priority(&self, (entity0, entity1): &CollisionPair, time: f64) -> CollisionPriority {
+        let key0 = self.stable_keys[&entity0.entity];
+        let key1 = self.stable_keys[&entity1.entity];
+        (
+            OrderedFloat(-time),
+            Reverse((key0.min(key1), key0.max(key1))),
+        )
+    }
+
+    // Whether the pair was already resolved at this time. Rounding can make a pair predict its
+    // collision again right where it was resolved, which would apply the impulse twice.
+    fn resolved_at(&self, pair: &CollisionPair, time: f64, epsilon: f64) -> bool {
+        self.last_resolved
+            .get(&entity_pair(pair))
+            .map_or(false, |last_time| (time - last_time).abs() <= epsilon)
+    }
+
+    // Points the collisions deferred to the next frame at other entities, for objects recreated
+    // by a restore. The per frame state is rebuilt by the next broad phase.
+    pub fn replace_entities(&mut self, replaced: &FnvHashMap<Entity, Entity>) {
+        let replace = |collision_entity: &mut GenerationalCollisionEntity| {
+            if let Some(entity) = replaced.get(&collision_entity.entity) {
+                collision_entity.entity = *entity;
+            }
+        };
+        for ((entity0, entity1), _) in self.deferred.iter_mut() {
+            replace(entity0);
+            replace(entity1);
+        }
+    }
+
+    // Moves the times kept from one frame to the next by -shift, see rebase_time.
+    pub fn shift_times(&mut self, shift: f64) {
+        for (_, time) in self.deferred.iter_mut() {
+            *time -= shift;
+        }
+        for record in self.records.iter_mut() {
+            record.time -= shift;
+        }
+    }
+
+    // Ranges of cells, end excluded, touched by the movement. Includes one cell past the last
+    // one of the grid, for objects lying on its far edge.
+    fn get_cell_range_for_movement(
+        &mut self,
+        world: &SubWorld,
+        entry: &EntryRef,
+        next_time: f64,
+        epsilon: f64,
+    ) -> (i32, i32, i32, i32) {
+        let (min_coords, max_coords) = get_movement_bounding_box(world, &entry, next_time, epsilon);
+        let cell_size = self.cell_size;
+        let i0 = (min_coords.x / cell_size.x).floor() as i32;
+        let i1 = (max_coords.x / cell_size.x).ceil() as i32;
+        let j0 = (min_coords.y / cell_size.y).floor() as i32;
+        let j1 = (max_coords.y / cell_size.y).ceil() as i32;
+        let (x0, y0) = self.grid_origin;
+        let (x1, y1) = (x0 + self.grid_width, y0 + self.grid_height);
+        match self.clamp_policy {
+            // Empty ranges when entirely outside.
+            ClampPolicy::Discard => {
+                // Ranges reach one cell past the grid, see above. Walls on the near edges of the
+                // grid only leave it by their margin, which loses nothing.
+                let inner_i0 = ((min_coords.x + epsilon) / cell_size.x).floor() as i32;
+                let inner_j0 = ((min_coords.y + epsilon) / cell_size.y).floor() as i32;
+                let cut_off = inner_i0 < x0 || i1 > x1 + 1 || inner_j0 < y0 || j1 > y1 + 1;
+                if cut_off {
+                    self.skipped.out_of_grid += 1;
+                }
+                (i0.max(x0), i1.min(x1) + 1, j0.max(y0), j1.min(y1) + 1)
+            }
+            ClampPolicy::ClampToEdge => (
+                i0.max(x0).min(x1),
+                i1.max(x0).min(x1) + 1,
+                j0.max(y0).min(y1),
+                j1.max(y0).min(y1) + 1,
+            ),
+            ClampPolicy::Expand => {
+                self.grid_origin = (i0.min(x0), j0.min(y0));
+                self.grid_width = i1.max(x1) - self.grid_origin.0;
+                self.grid_height = j1.max(y1) - self.grid_origin.1;
+                (i0, i1 + 1, j0, j1 + 1)
+            }
+        }
+    }
+
     fn add(
         &mut self,
         world: &SubWorld,
         entity: GenerationalCollisionEntity,
         time: f64,
         next_time: f64,
+        epsilon: f64,
     ) {
         let entry = world.entry_ref(entity.entity).unwrap();
-        let (i0, i1, j0, j1) = get_cell_range_for_movement(world, &entry, next_time);
-        self.last_box.insert(entity, (i0, i1, j0, j1));
-        // Find candidates using spatial hash mapping.
         let mut results = FnvHashSet::<GenerationalCollisionEntity>::default();
-
-        for i in i0..i1 {
-            for j in j0..j1 {
-                if let Some(cell_set) = self.spatial_buckets.get_mut(&(i, j)) {
-                    results.extend(cell_set.iter());
-                    cell_set.insert(entity);
-                } else {
-                    self.spatial_buckets
-                        .insert((i, j), [entity].iter().cloned().collect());
+        let oversized = entry
+            .get_component::<Ball>()
+            .map_or(false, |ball| ball.radius > self.max_bucketed_radius);
+        if oversized {
+            // Everything added so far. Those added later find it in the oversized list.
+            results.extend(
+                self.last_box.keys().filter(|other| {
+                    other.entity != entity.entity && self.is_latest_generation(other)
+                }),
+            );
+            // An empty range, so it is known without being in any cell.
+            self.last_box.insert(entity, (0, 0, 0, 0));
+            self.oversized.insert(entity);
+        } else {
+            let (i0, i1, j0, j1) =
+                self.get_cell_range_for_movement(world, &entry, next_time, epsilon);
+            self.last_box.insert(entity, (i0, i1, j0, j1));
+            // Find candidates using spatial hash mapping.
+            for i in i0..i1 {
+                for j in j0..j1 {
+                    if let Some(cell_set) = self.spatial_buckets.get_mut(&(i, j)) {
+                        results.extend(cell_set.iter());
+                        cell_set.insert(entity);
+                    } else {
+                        self.spatial_buckets
+                            .insert((i, j), [entity].iter().cloned().collect());
+                    }
                 }
             }
+            results.extend(
+                self.oversized.iter().filter(|other| {
+                    other.entity != entity.entity && self.is_latest_generation(other)
+                }),
+            );
         }
 
         // Solve collisions.
+        let filter = collision_filter(&entry);
+        let immovable = is_immovable_ball(&entry);
         for candidate_entity in results {
             let candidate_entry = world.entry_ref(candidate_entity.entity).unwrap();
-            let collisions_sol = solve_collision(world, &entry, &candidate_entry);
+            if !filter.collides_with(&collision_filter(&candidate_entry)) {
+                self.skipped.filtered += 1;
+                continue;
+            }
+            if immovable && is_immovable_ball(&candidate_entry) {
+                continue;
+            }
+            let collisions_sol = solve_collision(world, &entry, &candidate_entry, epsilon);
             if let Some((t0, t1)) = collisions_sol {
-                if segments_intersect((t0, t1), (time - EPSILON, next_time)) {
-                    self.collisions_events
-                        .push((entity, candidate_entity), OrderedFloat(-t0));
+                if segments_intersect((t0, t1), (time - epsilon, next_time)) {
+                    let pair = (entity, candidate_entity);
+                    let priority = self.priority(&pair, t0);
+                    self.collisions_events.push(pair, priority);
                 }
             }
         }
     }
 
     fn remove(&mut self, entity: GenerationalCollisionEntity) {
+        self.oversized.remove(&entity);
         if let Some((i0, i1, j0, j1)) = self.last_box.remove(&entity) {
             for i in i0..i1 {
                 for j in j0..j1 {
@@ -97,24 +447,177 @@ impl CollisionDetectionData {
     }
 }
 
+// Cell size suited to the balls of a world, from their mean diameter. Small cells make large
+// balls span many cells, large ones put many small balls in each cell.
+pub fn adaptive_cell_size(world: &World) -> f64 {
+    let (count, radius_sum) = <&Ball>::query()
+        .iter(world)
+        .fold((0, 0.), |(count, radius_sum), ball| {
+            (count + 1, radius_sum + ball.radius)
+        });
+    if count == 0 {
+        return CELL_SIZE;
+    }
+    CELLS_PER_DIAMETER * 2. * radius_sum / count as f64
+}
+
+// Logs an error if the objects of a just resolved collision are approaching or deeply overlapping.
+fn check_collision_invariants(entry0: &EntityAndRef, entry1: &EntityAndRef, collision_time: f64) {
+    let ball0 = entry0.entry.get_component::<Ball>().ok();
+    let ball1 = entry1.entry.get_component::<Ball>().ok();
+    let wall0 = entry0.entry.get_component::<Wall>().ok();
+    let wall1 = entry1.entry.get_component::<Wall>().ok();
+    let (separating, overlap) = match (ball0, ball1, wall0, wall1) {
+        (Some(ball0), Some(ball1), _, _) => {
+            let dx = ball0.position - ball1.position;
+            let dv = ball0.velocity - ball1.velocity;
+            (dv.dot(&dx) >= 0., ball0.radius + ball1.radius - dx.norm())
+        }
+        (Some(ball), _, _, Some(wall)) | (_, Some(ball), Some(wall), _) => {
+            let normal = wall.normal();
+            let d = normal.dot(&(ball.position - wall.p0));
+            (ball.velocity.dot(&normal) * d >= 0., ball.radius - d.abs())
+        }
+        _ => return,
+    };
+    if !separating || overlap > MAX_OVERLAP {
+        error!(
+            "Collision invariant violated at {}: separating: {}, overlap: {}. {:?}: {:?} {:?}, {:?}: {:?} {:?}",
+            collision_time,
+            separating,
+            overlap,
+            entry0.entity,
+            ball0,
+            wall0,
+            entry1.entity,
+            ball1,
+            wall1,
+        );
+    }
+}
+
+// Contact point and impact speed of a collision about to be resolved at the given time.
+fn collision_record(
+    entry0: &EntityAndRef,
+    entry1: &EntityAndRef,
+    collision_time: f64,
+) -> Option<CollisionRecord> {
+    let ball_at = |entry: &EntityAndRef| {
+        entry.entry.get_component::<Ball>().ok().map(|ball| {
+            let time_delta = collision_time - ball.initial_time;
+            (
+                ball.position + ball.velocity * time_delta,
+                ball.velocity,
+                ball.radius + ball.growth_rate * time_delta,
+                ball.growth_rate,
+            )
+        })
+    };
+    let (ball_entry, other_entry) = if ball_at(entry0).is_some() {
+        (entry0, entry1)
+    } else {
+        (entry1, entry0)
+    };
+    let (position, velocity, radius, growth_rate) = ball_at(ball_entry)?;
+    let (contact, impact_speed) =
+        if let Some((position1, velocity1, _, growth_rate1)) = ball_at(other_entry) {
+            let normal = contact_normal(&position, &position1);
+            let proj = (velocity - velocity1).dot(&normal) - (growth_rate + growth_rate1);
+            (position - normal * radius, proj.abs())
+        } else if let Ok(wall) = other_entry.entry.get_component::<Wall>() {
+            let normal = wall.normal();
+            let d = normal.dot(&(position - wall.p0));
+            let proj = velocity.dot(&normal) - d.signum() * growth_rate;
+            (position - normal * d, proj.abs())
+        } else {
+            let closest = obstacle_closest_point(other_entry, &position)?;
+            let normal = (position - closest).normalize();
+            (closest, (velocity.dot(&normal) - growth_rate).abs())
+        };
+    Some(CollisionRecord {
+        time: collision_time,
+        entity0: entry0.entity,
+        entity1: entry1.entity,
+        position: contact,
+        impact_speed,
+        radius0: ball_at(entry0).map(|(_, _, radius, _)| radius),
+        radius1: ball_at(entry1).map(|(_, _, radius, _)| radius),
+    })
+}
+
+// Kinematic and pinned balls. Two of them never exchange an impulse, so their collisions are not
+// predicted at all. Otherwise overlapping ones would be found colliding again every frame.
+fn is_immovable_ball(entry: &EntryRef) -> bool {
+    entry.get_component::<Ball>().is_ok()
+        && (entry.get_component::<Kinematic>().is_ok() || entry.get_component::<Pinned>().is_ok())
+}
+
+fn collision_filter(entry: &EntryRef) -> CollisionFilter {
+    entry
+        .get_component::<CollisionFilter>()
+        .map_or(CollisionFilter::default(), |filter| *filter)
+}
+
+// The entities of a pair, in a fixed order.
+fn entity_pair((entity0, entity1): &CollisionPair) -> (Entity, Entity) {
+    if entity0.entity <= entity1.entity {
+        (entity0.entity, entity1.entity)
+    } else {
+        (entity1.entity, entity0.entity)
+    }
+}
+
+// Whether the entity did not collide since the collision was computed.
+// Despawned entities, such as balls absorbed by a merge, are not current.
+fn is_current(world: &SubWorld, entity: &GenerationalCollisionEntity) -> bool {
+    world.entry_ref(entity.entity).map_or(false, |entry| {
+        entry
+            .get_component::<Generation>()
+            .map_or(false, |generation| {
+                generation.generation == entity.generation
+            })
+    })
+}
+
 fn segments_intersect((x0, x1): (f64, f64), (y0, y1): (f64, f64)) -> bool {
     return x1 >= y0 && y1 >= x0;
 }
 
 #[system]
 #[read_component(Ball)]
+#[read_component(BallId)]
 #[read_component(CollidableType)]
+#[read_component(CollisionFilter)]
+#[read_component(CustomCollider)]
 #[read_component(Entity)]
 #[read_component(Generation)]
+#[read_component(Kinematic)]
+#[read_component(Pinned)]
+#[read_component(Polygon)]
+#[read_component(Rect)]
 #[read_component(Wall)]
 pub fn collision(
     world: &mut SubWorld,
     #[resource] simulation_data: &SimulationData,
     #[resource] collision_detection_data: &mut CollisionDetectionData,
+    #[resource] physics_config: &PhysicsConfig,
+    #[resource] profile_data: &mut ProfileData,
 ) {
-    // Clear data.
-    collision_detection_data.spatial_buckets.clear();
-    collision_detection_data.collisions_events.clear();
+    let start = Instant::now();
+    let deferred = std::mem::take(&mut collision_detection_data.deferred);
+    // Clear data, including the boxes of balls despawned since the last frame.
+    collision_detection_data.reset();
+    let mut shapes = 0;
+    for (entity, id, _) in <(Entity, Option<&BallId>, &CollidableType)>::query().iter(world) {
+        let key = match id {
+            Some(id) => StableKey::Ball(*id),
+            None => {
+                shapes += 1;
+                StableKey::Shape(shapes - 1)
+            }
+        };
+        collision_detection_data.stable_keys.insert(*entity, key);
+    }
 
     // Iterate collidables.
     for (entity, generation, _) in <(Entity, &Generation, &CollidableType)>::query().iter(world) {
@@ -126,25 +629,78 @@ pub fn collision(
             },
             simulation_data.time,
             simulation_data.next_time,
+            physics_config.epsilon,
         );
     }
+
+    // Collisions deferred by the last frame, unless their objects changed since. They happen at
+    // the start of this frame at the latest, and replace later predictions of the same pair.
+    for &(pair, t) in deferred.iter() {
+        if !is_current(world, &pair.0) || !is_current(world, &pair.1) {
+            continue;
+        }
+        let priority = collision_detection_data.priority(&pair, t.max(simulation_data.time));
+        let events = &mut collision_detection_data.collisions_events;
+        if events
+            .get_priority(&pair)
+            .map_or(true, |predicted| *predicted < priority)
+        {
+            events.push(pair, priority);
+        }
+    }
+    // Kept until collision_handle takes over the queue, so a broad phase run only to peek at the
+    // next collision does not lose them.
+    collision_detection_data.deferred = deferred;
+    profile_data.record(Stage::BroadPhase, start.elapsed());
 }
 
 #[system]
+#[read_component(BallId)]
 #[read_component(CollidableType)]
+#[read_component(CollisionFilter)]
+#[read_component(CustomCollider)]
 #[read_component(Entity)]
+#[read_component(Kinematic)]
+#[read_component(Pinned)]
+#[read_component(Polygon)]
+#[read_component(Rect)]
 #[read_component(Wall)]
 #[write_component(Ball)]
+#[write_component(CollisionCount)]
 #[write_component(Generation)]
+#[write_component(LastImpulse)]
+#[write_component(Spin)]
 #[write_component(Trails)]
 pub fn collision_handle(
     world: &mut SubWorld,
+    #[resource] lifecycle: &mut Lifecycle,
     #[resource] simulation_data: &SimulationData,
     #[resource] collision_detection_data: &mut CollisionDetectionData,
+    #[resource] physics_config: &PhysicsConfig,
+    #[resource] perf_stats: &mut PerfStats,
+    #[resource] profile_data: &mut ProfileData,
 ) {
+    let start = Instant::now();
+    let epsilon = physics_config.epsilon;
+    let mut resolved = 0;
+    // Queued again by the broad phase.
+    collision_detection_data.deferred.clear();
     // Clear data.
     while !collision_detection_data.collisions_events.is_empty() {
-        let ((collision_entity0, collision_entity1), ordered_t) = collision_detection_data
+        if collision_detection_data
+            .max_collisions_per_frame
+            .map_or(false, |max| resolved >= max)
+        {
+            // Over budget, leave the rest to the next frame. Stale events are dropped there.
+            while let Some((pair, (ordered_t, _))) =
+                collision_detection_data.collisions_events.pop()
+            {
+                collision_detection_data.deferred.push((pair, -ordered_t.0));
+            }
+            collision_detection_data.skipped.deferred = collision_detection_data.deferred.len();
+            break;
+        }
+        let ((collision_entity0, collision_entity1), (ordered_t, _)) = collision_detection_data
             .collisions_events
             .pop()
             .expect("Impossible");
@@ -154,30 +710,253 @@ pub fn collision_handle(
             collision_entity0, collision_entity1, collision_time
         );
 
-        let entry0 = EntityAndRef::get(world, collision_entity0.entity);
-        let entry1 = EntityAndRef::get(world, collision_entity1.entity);
-        if collision_entity0.generation
-            != entry0
-                .entry
-                .get_component::<Generation>()
-                .unwrap()
-                .generation
-        {
+        if !is_current(world, &collision_entity0) || !is_current(world, &collision_entity1) {
+            continue;
+        }
+        if collision_detection_data.resolved_at(
+            &(collision_entity0, collision_entity1),
+            collision_time,
+            epsilon,
+        ) {
+            debug!("Skipping collision resolved already");
             continue;
         }
-        if collision_entity1.generation
-            != entry1
-                .entry
+
+        // Gather the other valid collisions happening at the same time.
+        let mut group = vec![(collision_entity0, collision_entity1)];
+        if collision_detection_data.group_simultaneous_contacts {
+            while let Some((_, (next_ordered_t, _))) =
+                collision_detection_data.collisions_events.peek()
+            {
+                if -next_ordered_t.0 - collision_time > epsilon {
+                    break;
+                }
+                let ((next_entity0, next_entity1), _) =
+                    collision_detection_data.collisions_events.pop().unwrap();
+                if is_current(world, &next_entity0)
+                    && is_current(world, &next_entity1)
+                    && !collision_detection_data.resolved_at(
+                        &(next_entity0, next_entity1),
+                        collision_time,
+                        epsilon,
+                    )
+                {
+                    group.push((next_entity0, next_entity1));
+                }
+            }
+        }
+        let entries = group
+            .iter()
+            .map(|(entity0, entity1)| {
+                (
+                    EntityAndRef::get(world, entity0.entity),
+                    EntityAndRef::get(world, entity1.entity),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        resolved += entries.len();
+        let records = if collision_detection_data.record_collisions {
+            entries
+                .iter()
+                .filter_map(|(entry0, entry1)| collision_record(entry0, entry1, collision_time))
+                .collect()
+        } else {
+            vec![]
+        };
+        let merge = physics_config.collision_response == CollisionResponse::Merge
+            && entries.len() == 1
+            && is_mergeable(&entries[0].0)
+            && is_mergeable(&entries[0].1);
+        let new_entities = if merge {
+            // The heavier ball absorbs the other one. Of equal masses, the one with the lower id
+            // does, whatever the order of the pair, which depends on entities.
+            let (entry0, entry1) = &entries[0];
+            let key = |entry: &EntityAndRef| {
+                (
+                    OrderedFloat(entry.entry.get_component::<Ball>().unwrap().mass()),
+                    Reverse(entry.entry.get_component::<BallId>().ok().copied()),
+                )
+            };
+            let (kept, absorbed, absorbed_entity) = if key(entry1) > key(entry0) {
+                (entry1, entry0, collision_entity0)
+            } else {
+                (entry0, entry1, collision_entity1)
+            };
+            collision_detection_data.remove(absorbed_entity);
+            if let Ok(id) = absorbed.entry.get_component::<BallId>() {
+                lifecycle.despawn(*id);
+            }
+            merge_ball_ball(kept, absorbed, collision_time)
+        } else if entries.len() == 1 {
+            collide(
+                world,
+                &entries[0].0,
+                &entries[0].1,
+                collision_time,
+                physics_config,
+            )
+        } else {
+            collide_group(&entries, collision_time, physics_config.restitution)
+        };
+        if !new_entities.is_empty() {
+            collision_detection_data.records.extend(records);
+            for pair in group.iter() {
+                collision_detection_data
+                    .last_resolved
+                    .insert(entity_pair(pair), collision_time);
+            }
+        }
+        // Merged balls overlap until the absorbed one is despawned.
+        if collision_detection_data.debug_invariants && !merge && !new_entities.is_empty() {
+            for (entry0, entry1) in entries.iter() {
+                check_collision_invariants(entry0, entry1, collision_time);
+            }
+        }
+        for entity in new_entities.iter() {
+            collision_detection_data.add(
+                world,
+                *entity,
+                collision_time,
+                simulation_data.next_time,
+                epsilon,
+            );
+        }
+    }
+    perf_stats.collisions_last_frame = resolved;
+    let skipped = collision_detection_data.skipped;
+    perf_stats.skipped_collisions = skipped;
+    if skipped.lost() > 0 {
+        warn!(
+            "Collisions skipped this frame: {} over max_collisions_per_frame, {} outside the grid",
+            skipped.deferred, skipped.out_of_grid
+        );
+    }
+    profile_data.record(Stage::NarrowPhase, start.elapsed());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        simulation::{init_simulation, SimulationConfig},
+        spawn::ball_components,
+    };
+    use legion::{Resources, Schedule};
+    use nalgebra::Vector3;
+
+    fn ball(x: f64, velocity: f64) -> Ball {
+        Ball {
+            position: Vector2::new(x, 100.),
+            velocity: Vector2::new(velocity, 0.),
+            radius: 10.,
+            growth_rate: 0.,
+            initial_time: 0.,
+            color: Vector3::new(1., 1., 1.),
+            base_color: Vector3::new(1., 1., 1.),
+            heat: 0.,
+        }
+    }
+
+    fn current(world: &World, entity: Entity) -> GenerationalCollisionEntity {
+        GenerationalCollisionEntity {
+            entity,
+            generation: world
+                .entry_ref(entity)
+                .unwrap()
                 .get_component::<Generation>()
                 .unwrap()
-                .generation
+                .generation,
+        }
+    }
+
+    #[test]
+    fn pair_is_not_resolved_twice_at_the_same_time() {
+        let mut world = World::default();
+        // Surfaces 1 apart, closing at 20: contact at t = 0.05.
+        let entity0 = world.push(ball_components(ball(100., 10.), BallId(0)));
+        let entity1 = world.push(ball_components(ball(121., -10.), BallId(1)));
+        let mut resources = Resources::default();
+        init_simulation(&mut resources, SimulationConfig::default());
+        resources.insert(CollisionDetectionData::new(200., 200.));
+        let mut broad_phase = Schedule::builder().add_system(collision_system()).build();
+        let mut handle = Schedule::builder()
+            .add_system(collision_handle_system())
+            .build();
+        broad_phase.execute(&mut world, &mut resources);
+        let time = resources
+            .get::<CollisionDetectionData>()
+            .unwrap()
+            .peek_next_collision_time()
+            .unwrap();
+        handle.execute(&mut world, &mut resources);
+
+        // Rounding can leave a resolved pair still approaching, and predict its collision again
+        // at the same time with the new generations. A handler going by generations only would
+        // resolve it a second time.
+        for entity in [entity0, entity1].iter() {
+            let mut entry = world.entry(*entity).unwrap();
+            let ball = entry.get_component_mut::<Ball>().unwrap();
+            ball.velocity = -ball.velocity;
+        }
         {
-            continue;
+            let mut collision_detection_data =
+                resources.get_mut::<CollisionDetectionData>().unwrap();
+            let pair = (current(&world, entity0), current(&world, entity1));
+            let priority = collision_detection_data.priority(&pair, time);
+            collision_detection_data
+                .collisions_events
+                .push(pair, priority);
         }
+        handle.execute(&mut world, &mut resources);
 
-        let new_entities = collide(world, &entry0, &entry1, collision_time);
-        for entity in new_entities.iter() {
-            collision_detection_data.add(world, *entity, collision_time, simulation_data.next_time);
+        assert_eq!(
+            resources.get::<PerfStats>().unwrap().collisions_last_frame,
+            0
+        );
+        for (entity, velocity) in [(entity0, 10.), (entity1, -10.)].iter() {
+            let entry = world.entry_ref(*entity).unwrap();
+            assert_eq!(entry.get_component::<CollisionCount>().unwrap().count, 1);
+            assert_eq!(entry.get_component::<Ball>().unwrap().velocity.x, *velocity);
         }
     }
+
+    // Whether the broad phase finds a grazing pair, for a frame starting at t = 1. The balls were
+    // last updated at t = 0, and are closest 5e-6 before the frame, overlapping by 0.01.
+    fn finds_grazing_collision(epsilon: f64) -> bool {
+        let closest = 1. - 5e-6;
+        let mut world = World::default();
+        world.push(ball_components(ball(100., 10.), BallId(0)));
+        let other_ball = Ball {
+            position: Vector2::new(100. + 10. * closest, 119.99),
+            ..ball(0., 0.)
+        };
+        world.push(ball_components(other_ball, BallId(1)));
+        let mut resources = Resources::default();
+        init_simulation(&mut resources, SimulationConfig::default());
+        {
+            let mut simulation_data = resources.get_mut::<SimulationData>().unwrap();
+            simulation_data.time = 1.;
+            simulation_data.next_time = 1.1;
+        }
+        resources.insert(PhysicsConfig {
+            epsilon,
+            ..PhysicsConfig::default()
+        });
+        resources.insert(CollisionDetectionData::new(200., 200.));
+        Schedule::builder()
+            .add_system(collision_system())
+            .build()
+            .execute(&mut world, &mut resources);
+        let collision_detection_data = resources.get::<CollisionDetectionData>().unwrap();
+        collision_detection_data
+            .peek_next_collision_time()
+            .is_some()
+    }
+
+    #[test]
+    fn small_epsilon_misses_a_grazing_collision() {
+        assert!(!finds_grazing_collision(1e-7));
+        assert!(finds_grazing_collision(1e-5));
+    }
 }
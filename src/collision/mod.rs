@@ -1,7 +1,19 @@
 pub mod collidable;
 pub mod colliders;
 pub mod collision;
+pub mod shapes;
 pub mod solvers;
+pub use collidable::CollisionFilter;
+pub use collidable::CollisionResponse;
+pub use collidable::PhysicsConfig;
+pub use collidable::PhysicsParameter;
+pub use collision::adaptive_cell_size;
 pub use collision::collision_handle_system;
 pub use collision::collision_system;
+pub use collision::ClampPolicy;
 pub use collision::CollisionDetectionData;
+pub use collision::CollisionRecord;
+pub use collision::SkippedCollisions;
+pub use collision::CELL_SIZE;
+pub use shapes::Collider;
+pub use shapes::CustomCollider;
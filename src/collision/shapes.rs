@@ -0,0 +1,121 @@
+use legion::world::EntryRef;
+use nalgebra::Vector2;
+
+use crate::{ball::Ball, polygon::Polygon, rect::Rect, wall::Wall};
+
+use super::{
+    collidable::CollidableType,
+    solvers::{solve_collision_ball_polygon, solve_collision_ball_wall},
+};
+
+// A shape balls bounce off. Collisions between balls are handled separately, since both move and
+// exchange momentum. Shapes are not moved by collisions.
+pub trait Collider {
+    // Box covering the shape from the current time to next_time. The caller adds the margin.
+    fn bounding_box(&self, next_time: f64) -> (Vector2<f64>, Vector2<f64>);
+    // Interval of time during which the ball overlaps the shape, starting at the contact. None if
+    // the ball never touches it, or moves away from it.
+    fn solve_against(&self, ball: &Ball, epsilon: f64) -> Option<(f64, f64)>;
+    // Change of velocity of a ball advanced to its contact at time t. None if it does not bounce,
+    // like when it moves away.
+    fn respond(&self, ball: &Ball, t: f64) -> Option<Vector2<f64>>;
+}
+
+// Shapes other than those of the crate. Spawned with CollidableType::Custom and a Generation like
+// walls.
+pub struct CustomCollider {
+    pub collider: Box<dyn Collider + Send + Sync>,
+}
+
+// The collider of a collidable that is not a ball.
+pub fn shape_collider<'a>(entry: &'a EntryRef) -> Option<&'a dyn Collider> {
+    match entry.get_component::<CollidableType>().ok()? {
+        CollidableType::Ball => None,
+        CollidableType::Wall => entry
+            .get_component::<Wall>()
+            .ok()
+            .map(|wall| wall as &dyn Collider),
+        CollidableType::Polygon => entry
+            .get_component::<Polygon>()
+            .ok()
+            .map(|polygon| polygon as &dyn Collider),
+        CollidableType::Rect => entry
+            .get_component::<Rect>()
+            .ok()
+            .map(|rect| rect as &dyn Collider),
+        CollidableType::Custom => entry
+            .get_component::<CustomCollider>()
+            .ok()
+            .map(|custom| custom.collider.as_ref() as &dyn Collider),
+    }
+}
+
+// Bounce off the normal, pointing from the closest point of the boundary to the ball center, if
+// moving towards it.
+fn bounce_off(ball: &Ball, closest: Vector2<f64>) -> Option<Vector2<f64>> {
+    let normal = (ball.position - closest).normalize();
+    let proj = ball.velocity.dot(&normal) - ball.growth_rate;
+    if proj < 0. {
+        Some(proj * normal * 2.)
+    } else {
+        None
+    }
+}
+
+impl Collider for Wall {
+    fn bounding_box(&self, _next_time: f64) -> (Vector2<f64>, Vector2<f64>) {
+        (self.p0.inf(&self.p1), self.p0.sup(&self.p1))
+    }
+
+    fn solve_against(&self, ball: &Ball, _epsilon: f64) -> Option<(f64, f64)> {
+        solve_collision_ball_wall(ball, self)
+    }
+
+    fn respond(&self, ball: &Ball, _t: f64) -> Option<Vector2<f64>> {
+        let normal = self.normal();
+        let d = normal.dot(&(ball.position - self.p0));
+        // Normal speed relative to the ball surface, which moves outwards as the ball grows.
+        let proj = ball.velocity.dot(&normal) - d.signum() * ball.growth_rate;
+        // Only bounce if moving towards the wall, from a side it blocks.
+        if proj * d < 0. && self.blocks_from(d) {
+            let tangential = ball.velocity - ball.velocity.dot(&normal) * normal;
+            Some(proj * normal * (1. + self.restitution) + tangential * self.friction)
+        } else {
+            None
+        }
+    }
+}
+
+impl Collider for Polygon {
+    fn bounding_box(&self, _next_time: f64) -> (Vector2<f64>, Vector2<f64>) {
+        let first = self.vertices[0];
+        self.vertices
+            .iter()
+            .fold((first, first), |(min, max), vertex| {
+                (min.inf(vertex), max.sup(vertex))
+            })
+    }
+
+    fn solve_against(&self, ball: &Ball, epsilon: f64) -> Option<(f64, f64)> {
+        solve_collision_ball_polygon(ball, self, epsilon)
+    }
+
+    // Away from the contacted edge, or from the vertex for corner hits.
+    fn respond(&self, ball: &Ball, _t: f64) -> Option<Vector2<f64>> {
+        bounce_off(ball, self.closest_point(&ball.position))
+    }
+}
+
+impl Collider for Rect {
+    fn bounding_box(&self, _next_time: f64) -> (Vector2<f64>, Vector2<f64>) {
+        (self.min, self.max)
+    }
+
+    fn solve_against(&self, ball: &Ball, epsilon: f64) -> Option<(f64, f64)> {
+        solve_collision_ball_polygon(ball, &self.polygon(), epsilon)
+    }
+
+    fn respond(&self, ball: &Ball, _t: f64) -> Option<Vector2<f64>> {
+        bounce_off(ball, self.closest_point(&ball.position))
+    }
+}
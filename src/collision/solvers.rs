@@ -4,15 +4,19 @@ use legion::{
 };
 use nalgebra::Vector2;
 
-use crate::{ball::Ball, wall::Wall};
+use crate::{ball::Ball, polygon::Polygon, wall::Wall};
 
-use super::collidable::CollidableType;
-use super::collidable::EPSILON;
+use super::{collidable::CollidableType, shapes::shape_collider};
+
+// Tolerance of the coefficients of the ball-ball equation, relative to the size of their terms.
+// They are squared speeds and distances, so the distance epsilon does not apply to them.
+const RELATIVE_TOLERANCE: f64 = 1e-9;
 
 pub fn get_movement_bounding_box(
     world: &SubWorld,
     entry: &EntryRef,
     next_time: f64,
+    epsilon: f64,
 ) -> (Vector2<f64>, Vector2<f64>) {
     let collidable_type = entry.get_component::<CollidableType>().unwrap();
     match collidable_type {
@@ -21,74 +25,113 @@ pub fn get_movement_bounding_box(
             // Compute bounding box.
             let time_delta = next_time - ball.initial_time;
             let new_position = ball.position + ball.velocity * time_delta;
+            let max_radius = ball.radius + (ball.growth_rate * time_delta).max(0.);
             (
                 ball.position
                     .inf(&new_position)
-                    .add_scalar(-ball.radius - EPSILON),
+                    .add_scalar(-max_radius - epsilon),
                 ball.position
                     .sup(&new_position)
-                    .add_scalar(ball.radius + EPSILON),
+                    .add_scalar(max_radius + epsilon),
             )
         }
-        CollidableType::Wall => {
-            let wall = entry.get_component::<Wall>().unwrap();
-            (
-                wall.p0.inf(&wall.p1).add_scalar(-EPSILON),
-                wall.p0.sup(&wall.p1.add_scalar(EPSILON)),
-            )
+        _ => {
+            let (min, max) = shape_collider(entry).unwrap().bounding_box(next_time);
+            (min.add_scalar(-epsilon), max.add_scalar(epsilon))
         }
     }
 }
 
+// Balls collide with balls and with shapes, shapes do not collide with each other.
 pub fn solve_collision(
     world: &SubWorld,
     entry0: &EntryRef,
     entry1: &EntryRef,
+    epsilon: f64,
 ) -> Option<(f64, f64)> {
-    let collidable_type0 = entry0.get_component::<CollidableType>().unwrap();
-    let collidable_type1 = entry1.get_component::<CollidableType>().unwrap();
-    match collidable_type0 {
-        CollidableType::Ball => {
-            let ball0 = entry0.get_component::<Ball>().unwrap();
-            match collidable_type1 {
-                CollidableType::Ball => {
-                    solve_collision_ball_ball(ball0, entry1.get_component::<Ball>().unwrap())
-                }
-                CollidableType::Wall => {
-                    solve_collision_ball_wall(ball0, entry1.get_component::<Wall>().unwrap())
-                }
-            }
-        }
-        CollidableType::Wall => {
-            let wall0 = entry0.get_component::<Wall>().unwrap();
-            match collidable_type1 {
-                CollidableType::Ball => {
-                    solve_collision_ball_wall(entry1.get_component::<Ball>().unwrap(), wall0)
-                }
-                CollidableType::Wall => None,
-            }
-        }
+    match (
+        entry0.get_component::<Ball>().ok(),
+        entry1.get_component::<Ball>().ok(),
+    ) {
+        (Some(ball0), Some(ball1)) => solve_collision_ball_ball(ball0, ball1, epsilon),
+        (Some(ball), None) => shape_collider(entry1)?.solve_against(ball, epsilon),
+        (None, Some(ball)) => shape_collider(entry0)?.solve_against(ball, epsilon),
+        (None, None) => None,
     }
 }
 
-fn solve_collision_ball_wall(ball: &Ball, wall: &Wall) -> Option<(f64, f64)> {
+pub fn solve_collision_ball_wall(ball: &Ball, wall: &Wall) -> Option<(f64, f64)> {
     // TODO: segments;
     let normal = wall.normal();
-    // normal*(pb-pw+vt)=r.
+    // normal*(pb-pw+vt)=r+gt.
     let a = normal.dot(&ball.velocity);
     let d = normal.dot(&(ball.position - wall.p0));
-    if d * a >= 0. {
+    let side = d.signum();
+    // Rate at which the gap between the ball surface and the wall shrinks.
+    let closing = ball.growth_rate - side * a;
+    if closing <= 0. {
         // If relative position and relative speed are at the same direction, then the ball is moving away.
         // No collision here.
         return None;
     }
+    if !wall.blocks_from(d) {
+        return None;
+    }
 
-    let b0 = d - ball.radius;
-    let b1 = d;
-    return Some((-b0 / a + ball.initial_time, -b1 / a + ball.initial_time));
+    // The ball overlaps the line from when its center is one radius away on the side it comes
+    // from, to when it is one radius away on the other side. A ball crossing a wall within a frame
+    // overlaps it during part of the frame only.
+    let t0 = (d.abs() - ball.radius) / closing + ball.initial_time;
+    // Rate at which the far side of the ball moves towards the line. Never crosses it for a ball
+    // growing faster than it moves.
+    let crossing = -side * a - ball.growth_rate;
+    let t1 = if crossing > 0. {
+        (d.abs() + ball.radius) / crossing + ball.initial_time
+    } else {
+        f64::INFINITY
+    };
+    return Some((t0, t1));
 }
 
-fn solve_collision_ball_ball(ball: &Ball, other_ball: &Ball) -> Option<(f64, f64)> {
+// Earliest contact with an edge or a vertex. For rectangles, this is the ball against the rectangle
+// expanded by the radius, with rounded corners.
+pub fn solve_collision_ball_polygon(
+    ball: &Ball,
+    polygon: &Polygon,
+    epsilon: f64,
+) -> Option<(f64, f64)> {
+    let mut solutions = vec![];
+    for edge in polygon.edges() {
+        if let Some((t0, t1)) = solve_collision_ball_wall(ball, &edge) {
+            // Contacts beyond the ends of the edge hit a vertex first.
+            let center = ball.position + ball.velocity * (t0 - ball.initial_time);
+            let diff = edge.p1 - edge.p0;
+            let s = (center - edge.p0).dot(&diff) / diff.norm_squared();
+            if s >= 0. && s <= 1. {
+                solutions.push((t0, t1));
+            }
+        }
+    }
+    for vertex in polygon.vertices.iter() {
+        // A vertex is a static ball of radius zero.
+        let point = Ball {
+            position: *vertex,
+            velocity: Vector2::new(0., 0.),
+            radius: 0.,
+            growth_rate: 0.,
+            initial_time: ball.initial_time,
+            color: ball.color,
+            base_color: ball.base_color,
+            heat: 0.,
+        };
+        solutions.extend(solve_collision_ball_ball(ball, &point, epsilon));
+    }
+    solutions
+        .into_iter()
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+}
+
+fn solve_collision_ball_ball(ball: &Ball, other_ball: &Ball, epsilon: f64) -> Option<(f64, f64)> {
     // Shift to start at the same time.
     // d(p0+v0(t-t0), p1+v1(t-t1)) <= r0+r1.
     // || p0-v0t0-p1+v1t1 +t(v0-v1) ||^2 <= (r0+r1)^2.
@@ -99,16 +142,48 @@ fn solve_collision_ball_ball(ball: &Ball, other_ball: &Ball) -> Option<(f64, f64
     let affine1 = other_ball.position - other_ball.velocity * other_ball.initial_time;
     let affine = affine0 - affine1;
 
-    let proj = dv.dot(&dx);
-    if proj > -EPSILON {
-        // Balls are moving away.
+    // Radii change linearly: r0+r1 = radius_affine + growth*t.
+    let growth = ball.growth_rate + other_ball.growth_rate;
+    let radius_affine = ball.radius - ball.growth_rate * ball.initial_time + other_ball.radius
+        - other_ball.growth_rate * other_ball.initial_time;
+
+    // Approach speed of the surfaces, along the line of centers like in the colliders, so it
+    // compares with epsilon whatever the distance. Concentric balls count as not approaching.
+    let distance = dx.norm();
+    let proj = if distance > 0. {
+        dv.dot(&dx) / distance - growth
+    } else {
+        0.
+    };
+    if proj > -epsilon {
+        // Balls are moving away, or too slowly to tell. If they already overlap, numeric error left
+        // them there and nothing would push them apart, so they collide right away and the handler
+        // separates them. They only touch afterwards, so the pair is not predicted again.
+        let t = ball.initial_time.max(other_ball.initial_time);
+        let distance = (ball.position + ball.velocity * (t - ball.initial_time)
+            - other_ball.position
+            - other_ball.velocity * (t - other_ball.initial_time))
+            .norm();
+        if radius_affine + growth * t - distance > epsilon {
+            return Some((t, t));
+        }
         return None;
     }
 
-    let a = dv.dot(&dv);
-    let b = (dv.dot(&affine) * 2.);
-    let c = (affine.dot(&affine)
-        - (ball.radius + other_ball.radius) * (ball.radius + other_ball.radius));
+    let a = dv.dot(&dv) - growth * growth;
+    let b = ((dv.dot(&affine) - radius_affine * growth) * 2.);
+    let c = (affine.dot(&affine) - radius_affine * radius_affine);
+
+    if a.abs() <= RELATIVE_TOLERANCE * (dv.dot(&dv) + growth * growth) {
+        // Growth cancels the relative speed, so the equation is linear. Without a linear term
+        // either, the distance between the surfaces never changes.
+        let b_scale = 2. * (dv.norm() * affine.norm() + radius_affine.abs() * growth.abs());
+        if b.abs() <= RELATIVE_TOLERANCE * b_scale {
+            return None;
+        }
+        let root = -c / b;
+        return Some((root, root));
+    }
 
     let disc = b * b - 4. * a * c;
     if disc < 0.0 {
@@ -117,26 +192,206 @@ fn solve_collision_ball_ball(ball: &Ball, other_ball: &Ball) -> Option<(f64, f64
 
     let sqrt_disc = disc.sqrt();
 
-    // Entry time is the first root.
+    // Entry time is the first root. When growth outpaces the relative speed (a < 0), it is the
+    // second one, and the balls overlap from then on.
     let root0 = ((-b - sqrt_disc) / (2. * a)) as f64;
-    let mid = (-b / (2. * a)) as f64;
-
-    let delta = (ball.position + (root0 - ball.initial_time) * ball.velocity
-        - other_ball.position
-        - (root0 - other_ball.initial_time) * other_ball.velocity)
-        .norm()
-        - ball.radius
-        - other_ball.radius;
-    if delta > 0.1 {
-        println!(
-            "delta2: {}, a: {}, b:{}, c:{}, disc:{}",
-            delta, a, b, c, disc
+    let mid = if a > 0. {
+        (-b / (2. * a)) as f64
+    } else {
+        f64::INFINITY
+    };
+
+    return Some((root0, mid));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        collision::{
+            collidable::{Generation, EPSILON},
+            shapes::Collider,
+        },
+        rect::Rect,
+        wall::WallSide,
+    };
+    use legion::World;
+    use nalgebra::Vector3;
+
+    fn ball(x: f64, velocity: f64, radius: f64, growth_rate: f64) -> Ball {
+        Ball {
+            position: Vector2::new(x, 0.),
+            velocity: Vector2::new(velocity, 0.),
+            radius,
+            growth_rate,
+            initial_time: 0.,
+            color: Vector3::new(1., 1., 1.),
+            base_color: Vector3::new(1., 1., 1.),
+            heat: 0.,
+        }
+    }
+
+    fn ball_2d(position: (f64, f64), velocity: (f64, f64), radius: f64) -> Ball {
+        Ball {
+            position: Vector2::new(position.0, position.1),
+            velocity: Vector2::new(velocity.0, velocity.1),
+            ..ball(0., 0., radius, 0.)
+        }
+    }
+
+    fn square(size: f64) -> Polygon {
+        Polygon {
+            vertices: vec![
+                Vector2::new(0., 0.),
+                Vector2::new(size, 0.),
+                Vector2::new(size, size),
+                Vector2::new(0., size),
+            ],
+        }
+    }
+
+    #[test]
+    fn slow_approach_is_not_linearized() {
+        // A squared speed of 1e-6 is below the distance epsilon, but the equation is quadratic.
+        let (t0, _) =
+            solve_collision_ball_ball(&ball(0., 1e-3, 1., 0.), &ball(5., 0., 1., 0.), EPSILON)
+                .unwrap();
+        assert!((t0 - 3000.).abs() < 1e-6, "{}", t0);
+    }
+
+    #[test]
+    fn growth_matching_speed_is_linear() {
+        // The gap closes at 2, from 3.
+        let (t0, t1) =
+            solve_collision_ball_ball(&ball(0., 1., 1., 1.), &ball(5., 0., 1., 0.), EPSILON)
+                .unwrap();
+        assert!((t0 - 1.5).abs() < 1e-9, "{}", t0);
+        assert!(t0.is_finite() && t1.is_finite());
+    }
+
+    #[test]
+    fn marginal_overlap_collides_right_away() {
+        // Overlapping by 1e-3, closing at 1e-6, far slower than epsilon.
+        let (t0, t1) = solve_collision_ball_ball(
+            &ball(100., 5e-7, 10., 0.),
+            &ball(119.999, -5e-7, 10., 0.),
+            EPSILON,
+        )
+        .unwrap();
+        assert_eq!((t0, t1), (0., 0.));
+    }
+
+    #[test]
+    fn growing_ball_reaches_stationary_neighbor() {
+        // Touching when 2 + t = 5.
+        let (t0, _) =
+            solve_collision_ball_ball(&ball(0., 0., 1., 1.), &ball(5., 0., 1., 0.), EPSILON)
+                .unwrap();
+        assert!((t0 - 3.).abs() < 1e-9, "{}", t0);
+    }
+
+    #[test]
+    fn ball_hits_polygon_edge_head_on() {
+        // The left edge, when the center is one radius away.
+        let (t0, _) =
+            solve_collision_ball_polygon(&ball_2d((-10., 5.), (1., 0.), 1.), &square(10.), EPSILON)
+                .unwrap();
+        assert!((t0 - 9.).abs() < 1e-9, "{}", t0);
+    }
+
+    #[test]
+    fn ball_past_the_end_of_an_edge_hits_the_vertex() {
+        // Reaches the line of the left edge above its end, the corner is half a radius below the
+        // path of the center.
+        let (t0, _) = solve_collision_ball_polygon(
+            &ball_2d((-10., 10.5), (1., 0.), 1.),
+            &square(10.),
+            EPSILON,
+        )
+        .unwrap();
+        let expected = 10. - 0.75f64.sqrt();
+        assert!((t0 - expected).abs() < 1e-9, "{} != {}", t0, expected);
+
+        // Farther than a radius from the corner.
+        assert_eq!(
+            solve_collision_ball_polygon(
+                &ball_2d((-10., 11.5), (1., 0.), 1.),
+                &square(10.),
+                EPSILON,
+            ),
+            None
         );
-        println!(
-            "res: {}",
-            (a as f64) * root0 * root0 + (b as f64) * root0 + (c as f64)
+    }
+
+    #[test]
+    fn fast_ball_crossing_a_wall_overlaps_it_briefly() {
+        // Crosses the wall, 10 away, in a hundredth of a default step.
+        let wall = Wall {
+            p0: Vector2::new(10., -100.),
+            p1: Vector2::new(10., 100.),
+            side: WallSide::Both,
+            friction: 0.,
+            restitution: 1.,
+        };
+        let (t0, t1) = solve_collision_ball_wall(&ball(0., 1000., 1., 0.), &wall).unwrap();
+        assert!(t0.is_finite() && t1.is_finite());
+        assert!(t0 < t1);
+        // From the near side touching the line to the far side leaving it.
+        assert!((t0 - 0.009).abs() < 1e-12, "{}", t0);
+        assert!((t1 - 0.011).abs() < 1e-12, "{}", t1);
+    }
+
+    fn rect(min: (f64, f64), max: (f64, f64)) -> Rect {
+        Rect {
+            min: Vector2::new(min.0, min.1),
+            max: Vector2::new(max.0, max.1),
+        }
+    }
+
+    #[test]
+    fn ball_hits_rect_face() {
+        let rect = rect((0., 0.), (10., 20.));
+        let (t0, _) = rect
+            .solve_against(&ball_2d((-10., 5.), (1., 0.), 1.), EPSILON)
+            .unwrap();
+        assert!((t0 - 9.).abs() < 1e-9, "{}", t0);
+        // Moving away.
+        assert_eq!(
+            rect.solve_against(&ball_2d((-10., 5.), (-1., 0.), 1.), EPSILON),
+            None
         );
     }
 
-    return Some((root0, mid));
+    #[test]
+    fn ball_hits_rounded_rect_corner() {
+        // Along the diagonal, the center is one radius from the corner at 5 - 1/sqrt(2). A sharp
+        // box would be hit at 4, when the ball reaches the lines of the faces.
+        let (t0, _) = rect((0., 0.), (10., 10.))
+            .solve_against(&ball_2d((-5., -5.), (1., 1.), 1.), EPSILON)
+            .unwrap();
+        let expected = 5. - 0.5f64.sqrt();
+        assert!((t0 - expected).abs() < 1e-9, "{} != {}", t0, expected);
+    }
+
+    #[test]
+    fn wall_box_is_inflated_on_every_side() {
+        // p0 is the larger corner, the one the margin used to miss.
+        let wall = Wall {
+            p0: Vector2::new(10., 5.),
+            p1: Vector2::new(0., 0.),
+            side: WallSide::Both,
+            friction: 0.,
+            restitution: 1.,
+        };
+        let mut world = World::default();
+        let entity = world.push((wall, CollidableType::Wall, Generation { generation: 0 }));
+        let (world, _) = world.split::<(&Wall, &CollidableType)>();
+        let entry = world.entry_ref(entity).unwrap();
+        let (min, max) = get_movement_bounding_box(&world, &entry, 1., EPSILON);
+        assert_eq!(min, wall.p1.add_scalar(-EPSILON));
+        assert_eq!(max, wall.p0.add_scalar(EPSILON));
+        // Same margin around the center on both sides.
+        let center = (wall.p0 + wall.p1) / 2.;
+        assert!(((max - center) - (center - min)).norm() < 1e-12);
+    }
 }
@@ -0,0 +1,35 @@
+use std::{io, path::Path};
+
+use crate::{
+    collision::collidable::PhysicsConfig, render::DisplayConfig, simulation::SimulationConfig,
+    world_gen::GenerationConfig,
+};
+
+// The configurations main sets up, as read from a TOML file with a table for each. Omitted tables
+// and fields keep their defaults.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "config_file", derive(serde::Deserialize), serde(default))]
+pub struct AppConfig {
+    pub display: DisplayConfig,
+    pub simulation: SimulationConfig,
+    pub generation: GenerationConfig,
+    pub physics: PhysicsConfig,
+}
+
+// Needs the config_file feature.
+pub fn load_config(path: &Path) -> io::Result<AppConfig> {
+    parse_config(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(feature = "config_file")]
+fn parse_config(text: &str) -> io::Result<AppConfig> {
+    toml::from_str(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+#[cfg(not(feature = "config_file"))]
+fn parse_config(_text: &str) -> io::Result<AppConfig> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Build with the config_file feature to read configuration files.",
+    ))
+}
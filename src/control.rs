@@ -0,0 +1,170 @@
+use legion::{IntoQuery, World};
+use log::{error, info};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+use crate::ball::Ball;
+
+// Line commands accepted on the control socket.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlCommand {
+    Pause,
+    Resume,
+    // Back to the state at startup.
+    Reset,
+    // Multiplies the simulation speed, like the speed keys.
+    Speed(f64),
+    Count,
+}
+
+fn parse_command(line: &str) -> Result<ControlCommand, String> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some("pause") => ControlCommand::Pause,
+        Some("resume") => ControlCommand::Resume,
+        Some("reset") => ControlCommand::Reset,
+        Some("speed") => {
+            let factor = words
+                .next()
+                .ok_or("speed needs a factor")?
+                .parse::<f64>()
+                .map_err(|e| e.to_string())?;
+            ControlCommand::Speed(factor)
+        }
+        Some("count") => ControlCommand::Count,
+        Some(word) => return Err(format!("unknown command {}", word)),
+        None => return Err("empty command".to_string()),
+    };
+    if words.next().is_some() {
+        return Err("too many arguments".to_string());
+    }
+    Ok(command)
+}
+
+// A command waiting to be applied by the event loop, which answers with reply.
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Sender<String>,
+}
+
+impl ControlRequest {
+    pub fn reply(self, text: String) {
+        // The connection may be gone already.
+        self.reply.send(text).ok();
+    }
+}
+
+// Accepts connections in a background thread. Each line of a connection is answered with one line,
+// once the event loop took the command from pending.
+pub struct ControlServer {
+    requests: Receiver<ControlRequest>,
+    local_addr: SocketAddr,
+}
+
+impl ControlServer {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<ControlServer> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        info!("Control socket listening on {}", local_addr);
+        let (sender, requests) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = serve_connection(stream, sender) {
+                                error!("Control connection failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => error!("Failed to accept control connection: {}", e),
+                }
+            }
+        });
+        Ok(ControlServer {
+            requests,
+            local_addr,
+        })
+    }
+
+    // Address actually bound, with the port picked by the system for port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Commands received since the last call.
+    pub fn pending(&self) -> impl Iterator<Item = ControlRequest> + '_ {
+        self.requests.try_iter()
+    }
+}
+
+// Answer to ControlCommand::Count.
+pub fn count_reply(world: &World) -> String {
+    <&Ball>::query().iter(world).count().to_string()
+}
+
+fn serve_connection(stream: TcpStream, requests: Sender<ControlRequest>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let reply = match parse_command(&line?) {
+            Ok(command) => {
+                let (reply, answer) = channel();
+                if requests.send(ControlRequest { command, reply }).is_err() {
+                    // The event loop is gone.
+                    return Ok(());
+                }
+                answer
+                    .recv()
+                    .unwrap_or_else(|_| "error: no answer".to_string())
+            }
+            Err(e) => format!("error: {}", e),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ball::BallId, spawn::ball_components};
+    use nalgebra::{Vector2, Vector3};
+
+    #[test]
+    fn count_returns_the_ball_count() {
+        let mut world = World::default();
+        for i in 0..3 {
+            let ball = Ball {
+                position: Vector2::new(20. * i as f64, 0.),
+                velocity: Vector2::new(0., 0.),
+                radius: 5.,
+                growth_rate: 0.,
+                initial_time: 0.,
+                color: Vector3::new(1., 1., 1.),
+                base_color: Vector3::new(1., 1., 1.),
+                heat: 0.,
+            };
+            world.push(ball_components(ball, BallId(i)));
+        }
+        let server = ControlServer::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        writeln!(stream, "count").unwrap();
+        // Answered the way the event loop does, once the command arrives.
+        let request = loop {
+            if let Some(request) = server.pending().next() {
+                break request;
+            }
+            thread::yield_now();
+        };
+        assert_eq!(request.command, ControlCommand::Count);
+        request.reply(count_reply(&world));
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        assert_eq!(line, "3\n");
+    }
+}
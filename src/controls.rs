@@ -0,0 +1,104 @@
+use fnv::FnvHashMap;
+use winit::event::VirtualKeyCode;
+
+use crate::collision::PhysicsParameter;
+
+// Everything the keyboard can do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Exit,
+    SpeedUp,
+    SlowDown,
+    BlurOn,
+    BlurOff,
+    // No rendering and no frame time cap.
+    ToggleBatchMode,
+    ToggleDebugGrid,
+    // Spawn a ring of balls around the cursor.
+    Burst,
+    // Stop or resume stepping the simulation every frame.
+    TogglePause,
+    // Pause, and advance to the next collision.
+    StepToCollision,
+    // Run the motion backwards from now.
+    Reverse,
+    // Rebuild the ball shader from its file, with the hot_reload feature.
+    ReloadShaders,
+    // Step a physics parameter up or down, within its range.
+    IncreasePhysics(PhysicsParameter),
+    DecreasePhysics(PhysicsParameter),
+}
+
+pub struct KeyBindings {
+    bindings: FnvHashMap<VirtualKeyCode, Action>,
+}
+
+impl KeyBindings {
+    pub fn empty() -> KeyBindings {
+        KeyBindings {
+            bindings: Default::default(),
+        }
+    }
+
+    // Replaces any action bound to the key.
+    pub fn bind(&mut self, key: VirtualKeyCode, action: Action) {
+        self.bindings.insert(key, action);
+    }
+
+    pub fn unbind(&mut self, key: VirtualKeyCode) {
+        self.bindings.remove(&key);
+    }
+
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.bindings.get(&key).copied()
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        let mut key_bindings = KeyBindings::empty();
+        for (key, action) in [
+            (VirtualKeyCode::Escape, Action::Exit),
+            (VirtualKeyCode::NumpadAdd, Action::SpeedUp),
+            (VirtualKeyCode::NumpadSubtract, Action::SlowDown),
+            (VirtualKeyCode::B, Action::BlurOn),
+            (VirtualKeyCode::N, Action::BlurOff),
+            (VirtualKeyCode::V, Action::ToggleBatchMode),
+            (VirtualKeyCode::G, Action::ToggleDebugGrid),
+            (VirtualKeyCode::E, Action::Burst),
+            (VirtualKeyCode::P, Action::TogglePause),
+            (VirtualKeyCode::C, Action::StepToCollision),
+            (VirtualKeyCode::R, Action::Reverse),
+            (VirtualKeyCode::F5, Action::ReloadShaders),
+            (
+                VirtualKeyCode::U,
+                Action::IncreasePhysics(PhysicsParameter::Restitution),
+            ),
+            (
+                VirtualKeyCode::J,
+                Action::DecreasePhysics(PhysicsParameter::Restitution),
+            ),
+            (
+                VirtualKeyCode::I,
+                Action::IncreasePhysics(PhysicsParameter::GravityScale),
+            ),
+            (
+                VirtualKeyCode::K,
+                Action::DecreasePhysics(PhysicsParameter::GravityScale),
+            ),
+            (
+                VirtualKeyCode::O,
+                Action::IncreasePhysics(PhysicsParameter::Drag),
+            ),
+            (
+                VirtualKeyCode::L,
+                Action::DecreasePhysics(PhysicsParameter::Drag),
+            ),
+        ]
+        .iter()
+        {
+            key_bindings.bind(*key, *action);
+        }
+        key_bindings
+    }
+}
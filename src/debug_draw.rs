@@ -0,0 +1,50 @@
+use nalgebra::{Vector2, Vector3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugPrimitive {
+    Line {
+        a: Vector2<f64>,
+        b: Vector2<f64>,
+        color: Vector3<f32>,
+    },
+    // Outline only.
+    Circle {
+        center: Vector2<f64>,
+        radius: f64,
+        color: Vector3<f32>,
+    },
+    Point {
+        position: Vector2<f64>,
+        color: Vector3<f32>,
+    },
+}
+
+// Shapes drawn on top of the next frame, then dropped, for visualizing normals, boxes or forces
+// while developing a feature. A resource, cleared by the render systems after each frame.
+#[derive(Clone, Debug, Default)]
+pub struct DebugDraw {
+    pub primitives: Vec<DebugPrimitive>,
+}
+
+impl DebugDraw {
+    pub fn line(&mut self, a: Vector2<f64>, b: Vector2<f64>, color: Vector3<f32>) {
+        self.primitives.push(DebugPrimitive::Line { a, b, color });
+    }
+
+    pub fn circle(&mut self, center: Vector2<f64>, radius: f64, color: Vector3<f32>) {
+        self.primitives.push(DebugPrimitive::Circle {
+            center,
+            radius,
+            color,
+        });
+    }
+
+    pub fn point(&mut self, position: Vector2<f64>, color: Vector3<f32>) {
+        self.primitives
+            .push(DebugPrimitive::Point { position, color });
+    }
+
+    pub fn clear(&mut self) {
+        self.primitives.clear();
+    }
+}
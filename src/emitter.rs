@@ -0,0 +1,50 @@
+use legion::*;
+use nalgebra::{Vector2, Vector3};
+
+use crate::{ball::Ball, lifecycle::Lifecycle, simulation::SimulationData};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Emitter {
+    pub position: Vector2<f64>,
+    pub velocity: Vector2<f64>,
+    pub radius: f64,
+    pub color: Vector3<f32>,
+    pub interval: f64,
+    // Number of balls left to spawn.
+    pub remaining: u32,
+    pub next_spawn_time: f64,
+    // Lifetime of the spawned balls, forever if None.
+    pub ttl: Option<f64>,
+}
+
+#[system(for_each)]
+pub fn emit_balls(
+    emitter: &mut Emitter,
+    #[resource] lifecycle: &mut Lifecycle,
+    #[resource] simulation_data: &SimulationData,
+) {
+    if emitter.remaining == 0 || simulation_data.time < emitter.next_spawn_time {
+        return;
+    }
+    // At most one ball per frame, so consecutive spawns do not overlap.
+    let spawned = lifecycle.spawn(
+        Ball {
+            position: emitter.position,
+            velocity: emitter.velocity,
+            radius: emitter.radius,
+            growth_rate: 0.,
+            initial_time: simulation_data.time,
+            color: emitter.color,
+            base_color: emitter.color,
+            heat: 0.,
+        },
+        emitter.ttl,
+    );
+    if !spawned {
+        // Would fail the same way every interval.
+        emitter.remaining = 0;
+        return;
+    }
+    emitter.remaining -= 1;
+    emitter.next_spawn_time += emitter.interval;
+}
@@ -0,0 +1,87 @@
+use legion::{world::SubWorld, *};
+use log::error;
+use nalgebra::Vector2;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    ball::Ball,
+    shutdown::OnShutdown,
+    simulation::{PerfStats, SimulationData},
+};
+
+// Writes a JSON object per frame with the energy and momentum of the balls, one per line, to plot
+// how they evolve. Register its shutdown hook so the last frames are flushed.
+pub struct EnergyLog {
+    writer: BufWriter<File>,
+    frame: u64,
+}
+
+impl EnergyLog {
+    pub fn create(path: &Path) -> io::Result<EnergyLog> {
+        Ok(EnergyLog {
+            writer: BufWriter::new(File::create(path)?),
+            frame: 0,
+        })
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl OnShutdown for EnergyLog {
+    fn on_shutdown(&mut self) {
+        if let Err(e) = self.flush() {
+            error!("Failed to flush energy log: {}", e);
+        }
+    }
+}
+
+// JSON has no infinities or NaN.
+fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        x.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+// Runs after advance_time, when the balls are at the current time. Kinematic and pinned balls are
+// counted with their mass like the others.
+#[system]
+#[read_component(Ball)]
+pub fn log_energy(
+    world: &mut SubWorld,
+    #[resource] energy_log: &mut EnergyLog,
+    #[resource] simulation_data: &SimulationData,
+    #[resource] perf_stats: &PerfStats,
+) {
+    let mut ball_count = 0;
+    let mut kinetic_energy = 0.;
+    let mut momentum = Vector2::new(0., 0.);
+    for ball in <&Ball>::query().iter(world) {
+        ball_count += 1;
+        kinetic_energy += 0.5 * ball.mass() * ball.velocity.norm_squared();
+        momentum += ball.mass() * ball.velocity;
+    }
+    let result = writeln!(
+        energy_log.writer,
+        "{{\"frame\":{},\"time\":{},\"total_ke\":{},\"total_momentum\":[{},{}],\
+         \"ball_count\":{},\"collisions_this_frame\":{}}}",
+        energy_log.frame,
+        json_number(simulation_data.absolute_time()),
+        json_number(kinetic_energy),
+        json_number(momentum.x),
+        json_number(momentum.y),
+        ball_count,
+        perf_stats.collisions_last_frame,
+    );
+    if let Err(e) = result {
+        error!("Failed to write energy log: {}", e);
+    }
+    energy_log.frame += 1;
+}
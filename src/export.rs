@@ -0,0 +1,112 @@
+use image::{codecs::gif::GifEncoder, Delay, Frame, ImageResult, RgbaImage};
+use log::info;
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+use crate::{
+    render::{read_frame, Graphics},
+    simulation::Simulation,
+};
+
+// Frames per second of exported animations, one simulation step per frame.
+pub const EXPORT_FRAME_RATE: u32 = 60;
+
+// Encodes frames into a GIF file, or into any video format ffmpeg knows from the extension.
+pub enum FrameExporter {
+    Gif(GifEncoder<BufWriter<File>>),
+    // ffmpeg reading raw RGBA frames on its standard input.
+    Ffmpeg(Child),
+}
+
+impl FrameExporter {
+    pub fn create(path: &Path, width: u32, height: u32) -> io::Result<FrameExporter> {
+        let is_gif = path
+            .extension()
+            .map_or(false, |extension| extension.eq_ignore_ascii_case("gif"));
+        if is_gif {
+            return Ok(FrameExporter::Gif(GifEncoder::new(BufWriter::new(
+                File::create(path)?,
+            ))));
+        }
+        let child = Command::new("ffmpeg")
+            .args(&[
+                "-y",
+                "-loglevel",
+                "error",
+                "-f",
+                "rawvideo",
+                "-pix_fmt",
+                "rgba",
+            ])
+            .args(&["-s", &format!("{}x{}", width, height)])
+            .args(&["-r", &EXPORT_FRAME_RATE.to_string(), "-i", "-"])
+            // Most players only read this pixel format.
+            .args(&["-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        Ok(FrameExporter::Ffmpeg(child))
+    }
+
+    pub fn add_frame(&mut self, frame: RgbaImage) -> Result<(), Box<dyn Error>> {
+        match self {
+            FrameExporter::Gif(encoder) => add_gif_frame(encoder, frame)?,
+            FrameExporter::Ffmpeg(child) => child
+                .stdin
+                .as_mut()
+                .ok_or("ffmpeg input is closed")?
+                .write_all(frame.as_raw())?,
+        }
+        Ok(())
+    }
+
+    // Completes the file.
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            // The trailer is written when the encoder is dropped.
+            FrameExporter::Gif(encoder) => drop(encoder),
+            FrameExporter::Ffmpeg(mut child) => {
+                // Closing the input ends the video.
+                drop(child.stdin.take());
+                let status = child.wait()?;
+                if !status.success() {
+                    return Err(format!("ffmpeg failed: {}", status).into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn add_gif_frame(encoder: &mut GifEncoder<BufWriter<File>>, frame: RgbaImage) -> ImageResult<()> {
+    let delay = Delay::from_numer_denom_ms(1000, EXPORT_FRAME_RATE);
+    encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay))
+}
+
+// Steps the simulation for the given number of frames, exporting each rendered frame to the file.
+// The graphics must render offscreen, see init_headless_graphics.
+pub fn export_frames(
+    simulation: &mut Simulation,
+    path: &Path,
+    frames: usize,
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = {
+        let graphics = simulation.resources.get::<Graphics>().unwrap();
+        (graphics.config.width, graphics.config.height)
+    };
+    let mut exporter = FrameExporter::create(path, width, height)?;
+    for _ in 0..frames {
+        simulation.step();
+        let frame = read_frame(&mut simulation.resources.get_mut::<Graphics>().unwrap())
+            .ok_or("graphics do not render offscreen")?;
+        exporter.add_frame(frame)?;
+    }
+    exporter.finish()?;
+    info!("Exported {} frames to {:?}", frames, path);
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use fnv::FnvHashMap;
+use legion::{system, world::SubWorld, Entity, IntoQuery};
+use nalgebra::Vector2;
+use rayon::prelude::*;
+
+use crate::{ball::Ball, simulation::SimulationData};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InteractionMode {
+    Attract,
+    Repel,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InteractionField {
+    pub strength: f64,
+    pub softening: f64,
+    pub mode: InteractionMode,
+    // Pairs farther apart than this are ignored. Also used as the bucket size.
+    pub cutoff: f64,
+}
+
+fn get_cell(position: &Vector2<f64>, cell_size: f64) -> (i32, i32) {
+    (
+        (position.x / cell_size).floor() as i32,
+        (position.y / cell_size).floor() as i32,
+    )
+}
+
+#[system]
+#[read_component(Entity)]
+#[write_component(Ball)]
+pub fn interaction_forces(
+    world: &mut SubWorld,
+    #[resource] interaction_field: &InteractionField,
+    #[resource] simulation_data: &SimulationData,
+) {
+    let time_delta = simulation_data.next_time - simulation_data.time;
+    let sign = match interaction_field.mode {
+        InteractionMode::Attract => 1.,
+        InteractionMode::Repel => -1.,
+    };
+
+    // Two phases, so the result does not depend on iteration order or thread scheduling: read all
+    // balls, compute every velocity change from that copy in parallel, then write them back.
+    let (entities, bodies): (Vec<_>, Vec<_>) = <(Entity, &Ball)>::query()
+        .iter(world)
+        .map(|(entity, ball)| (*entity, (ball.position, ball.mass())))
+        .unzip();
+
+    // Bucket by cutoff sized cells, so only the 3x3 neighborhood needs to be scanned.
+    let mut buckets = FnvHashMap::<(i32, i32), Vec<usize>>::default();
+    for (index, (position, _)) in bodies.iter().enumerate() {
+        buckets
+            .entry(get_cell(position, interaction_field.cutoff))
+            .or_insert_with(Vec::new)
+            .push(index);
+    }
+
+    let softening2 = interaction_field.softening * interaction_field.softening;
+    let cutoff2 = interaction_field.cutoff * interaction_field.cutoff;
+    // Each delta only reads the copy, and sums its neighbors in bucket order.
+    let velocity_deltas = bodies
+        .par_iter()
+        .enumerate()
+        .map(|(index, (position, _))| {
+            let (ci, cj) = get_cell(position, interaction_field.cutoff);
+            let mut acceleration = Vector2::new(0., 0.);
+            for i in (ci - 1)..(ci + 2) {
+                for j in (cj - 1)..(cj + 2) {
+                    if let Some(bucket) = buckets.get(&(i, j)) {
+                        for &other_index in bucket {
+                            if other_index == index {
+                                continue;
+                            }
+                            let (other_position, other_mass) = bodies[other_index];
+                            let dx = other_position - position;
+                            let r2 = dx.dot(&dx);
+                            if r2 > cutoff2 {
+                                continue;
+                            }
+                            // Softened inverse square law.
+                            let softened = r2 + softening2;
+                            acceleration += interaction_field.strength * other_mass * dx
+                                / (softened * softened.sqrt());
+                        }
+                    }
+                }
+            }
+            sign * acceleration * time_delta
+        })
+        .collect::<Vec<_>>();
+
+    // Apply.
+    for (entity, velocity_delta) in entities.iter().zip(velocity_deltas.iter()) {
+        let mut entry = world.entry_mut(*entity).unwrap();
+        entry.get_component_mut::<Ball>().unwrap().velocity += velocity_delta;
+    }
+}
@@ -0,0 +1,55 @@
+use legion::{system, world::SubWorld, IntoQuery};
+use nalgebra::Vector2;
+
+use crate::{
+    ball::Ball,
+    collision::PhysicsConfig,
+    integrator::{Integrator, SemiImplicitEuler},
+    simulation::SimulationData,
+};
+
+// Point attractor pulling every ball, like a planet. Wells do not collide and do not move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gravitational {
+    pub position: Vector2<f64>,
+    pub mass: f64,
+    // Keeps the pull finite for balls passing through the well.
+    pub softening: f64,
+}
+
+// Acceleration towards every well, with a softened inverse square law.
+fn well_acceleration(wells: &[Gravitational], position: &Vector2<f64>) -> Vector2<f64> {
+    wells
+        .iter()
+        .map(|well| {
+            let dx = well.position - position;
+            let softened = dx.norm_squared() + well.softening * well.softening;
+            well.mass * dx / (softened * softened.sqrt())
+        })
+        .sum()
+}
+
+// Runs before collision detection, like integrate_accelerations. Paths around a well are not
+// straight, so velocities change with the semi implicit Euler step whatever the configured
+// integrator.
+#[system]
+#[read_component(Gravitational)]
+#[write_component(Ball)]
+pub fn gravity_wells(
+    world: &mut SubWorld,
+    #[resource] simulation_data: &SimulationData,
+    #[resource] physics_config: &PhysicsConfig,
+) {
+    let wells = <&Gravitational>::query()
+        .iter(world)
+        .copied()
+        .collect::<Vec<_>>();
+    if wells.is_empty() {
+        return;
+    }
+    let time_delta = simulation_data.next_time - simulation_data.time;
+    <&mut Ball>::query().par_for_each_mut(world, |ball| {
+        let acceleration = physics_config.gravity_scale * well_acceleration(&wells, &ball.position);
+        SemiImplicitEuler.integrate(ball, &acceleration, time_delta);
+    });
+}
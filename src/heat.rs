@@ -0,0 +1,25 @@
+use legion::*;
+use nalgebra::Vector3;
+
+use crate::{ball::Ball, simulation::SimulationData};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeatConfig {
+    // Fraction of heat lost per unit of simulation time.
+    pub decay_rate: f32,
+    // Heat at which the ball is fully colored by hot_color.
+    pub saturation: f32,
+    pub hot_color: Vector3<f32>,
+}
+
+#[system(par_for_each)]
+pub fn cool_balls(
+    ball: &mut Ball,
+    #[resource] heat_config: &HeatConfig,
+    #[resource] simulation_data: &SimulationData,
+) {
+    let time_delta = (simulation_data.next_time - simulation_data.time) as f32;
+    ball.heat *= (-heat_config.decay_rate * time_delta).exp();
+    let factor = (ball.heat / heat_config.saturation).min(1.);
+    ball.color = ball.base_color.lerp(&heat_config.hot_color, factor);
+}
@@ -0,0 +1,47 @@
+use legion::*;
+
+use crate::{collision::CollisionDetectionData, simulation::SimulationData};
+
+// Slows time down for a moment after a strong impact. Needs record_collisions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HitStopConfig {
+    // Impact speed that triggers the effect.
+    pub impact_speed: f64,
+    // Time scale right after the impact.
+    pub time_scale: f64,
+    // Frames spent at time_scale, then frames to ramp back to normal speed.
+    pub hold_frames: u32,
+    pub ramp_frames: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct HitStop {
+    // Frames until time runs at normal speed again, 0 when inactive.
+    frames_left: u32,
+}
+
+// Runs after collision_handle, and scales the next frame through SimulationData.time_scale.
+#[system]
+pub fn hit_stop(
+    #[resource] config: &HitStopConfig,
+    #[resource] hit_stop: &mut HitStop,
+    #[resource] collision_detection_data: &CollisionDetectionData,
+    #[resource] simulation_data: &mut SimulationData,
+) {
+    let triggered = collision_detection_data
+        .records()
+        .iter()
+        .any(|record| record.impact_speed > config.impact_speed);
+    if triggered {
+        hit_stop.frames_left = config.hold_frames + config.ramp_frames;
+    } else if hit_stop.frames_left > 0 {
+        hit_stop.frames_left -= 1;
+    }
+    simulation_data.time_scale = if hit_stop.frames_left > config.ramp_frames {
+        config.time_scale
+    } else {
+        // Linear ramp, reaching 1 when no frame is left.
+        let progress = 1. - hit_stop.frames_left as f64 / (config.ramp_frames + 1) as f64;
+        config.time_scale + (1. - config.time_scale) * progress
+    };
+}
@@ -0,0 +1,67 @@
+use legion::*;
+use nalgebra::Vector2;
+
+use crate::{
+    ball::Ball,
+    simulation::{SimulationConfig, SimulationData},
+};
+
+// Constant acceleration of a ball over a frame, for force based features.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Acceleration {
+    pub value: Vector2<f64>,
+}
+
+// Applies the acceleration of a ball for a frame. Runs before collision detection; within the
+// frame, balls then move in straight lines, which is what the collision solvers predict.
+pub trait Integrator {
+    fn integrate(&self, ball: &mut Ball, acceleration: &Vector2<f64>, time_delta: f64);
+}
+
+// Exact constant velocity motion. Accelerations are ignored, forces must change velocities
+// directly. Collision predictions are exact.
+pub struct AnalyticLinear;
+
+impl Integrator for AnalyticLinear {
+    fn integrate(&self, _ball: &mut Ball, _acceleration: &Vector2<f64>, _time_delta: f64) {}
+}
+
+// v += a * dt, then the position moves with the new velocity. Collisions stay exact for the
+// straight path of the frame, but the path itself is a first order approximation.
+pub struct SemiImplicitEuler;
+
+impl Integrator for SemiImplicitEuler {
+    fn integrate(&self, ball: &mut Ball, acceleration: &Vector2<f64>, time_delta: f64) {
+        ball.velocity += acceleration * time_delta;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "config_file", derive(serde::Deserialize))]
+pub enum IntegratorKind {
+    AnalyticLinear,
+    SemiImplicitEuler,
+}
+
+impl IntegratorKind {
+    pub fn integrator(&self) -> &'static dyn Integrator {
+        match self {
+            IntegratorKind::AnalyticLinear => &AnalyticLinear,
+            IntegratorKind::SemiImplicitEuler => &SemiImplicitEuler,
+        }
+    }
+}
+
+#[system(par_for_each)]
+pub fn integrate_accelerations(
+    ball: &mut Ball,
+    acceleration: &Acceleration,
+    #[resource] simulation_data: &SimulationData,
+    #[resource] simulation_config: &SimulationConfig,
+) {
+    simulation_config.integrator.integrator().integrate(
+        ball,
+        &acceleration.value,
+        simulation_data.next_time - simulation_data.time,
+    );
+}
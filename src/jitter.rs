@@ -0,0 +1,49 @@
+use fnv::FnvHasher;
+use legion::{query::component, *};
+use nalgebra::Vector2;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use std::hash::{Hash, Hasher};
+
+use crate::{
+    ball::{Ball, BallId, Kinematic, Pinned},
+    simulation::SimulationData,
+};
+
+// Random velocity kicks given to every ball each frame, to fuzz the solver and check that energy
+// stays bounded. The same seed gives the same kicks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Jitter {
+    // Largest change of speed per frame.
+    pub magnitude: f64,
+    pub seed: u64,
+}
+
+// Each ball draws from its own generator, seeded from the seed, the time and the ball id, so the
+// kicks do not depend on the order balls are visited in, nor on the entities legion hands out.
+fn kick(jitter: &Jitter, time: f64, id: &BallId) -> Vector2<f64> {
+    let mut hasher = FnvHasher::default();
+    jitter.seed.hash(&mut hasher);
+    time.to_bits().hash(&mut hasher);
+    id.hash(&mut hasher);
+    let mut rng = Pcg64::seed_from_u64(hasher.finish());
+    let angle = rng.gen_range(0.0..std::f64::consts::TAU);
+    let speed = rng.gen_range(0.0..=jitter.magnitude);
+    Vector2::new(speed * angle.cos(), speed * angle.sin())
+}
+
+// Runs with the other systems changing velocities, before collision detection. Kinematic and
+// pinned balls keep their velocity.
+#[system(par_for_each)]
+#[filter(!component::<Kinematic>() & !component::<Pinned>())]
+pub fn jitter_balls(
+    id: &BallId,
+    ball: &mut Ball,
+    #[resource] jitter: &Jitter,
+    #[resource] simulation_data: &SimulationData,
+) {
+    if jitter.magnitude == 0. {
+        return;
+    }
+    ball.velocity += kick(jitter, simulation_data.absolute_time(), id);
+}
@@ -0,0 +1,32 @@
+pub mod advance;
+pub mod ball;
+pub mod bounds;
+pub mod collision;
+pub mod config;
+pub mod control;
+pub mod controls;
+pub mod debug_draw;
+pub mod emitter;
+pub mod energy_log;
+pub mod export;
+pub mod forces;
+pub mod gravity;
+pub mod heat;
+pub mod hit_stop;
+pub mod integrator;
+pub mod jitter;
+pub mod lifecycle;
+pub mod lifetime;
+pub mod polygon;
+pub mod profile;
+pub mod rect;
+pub mod render;
+pub mod replay;
+pub mod sanitize;
+pub mod shutdown;
+pub mod simulation;
+pub mod spawn;
+pub mod stats;
+pub mod trace;
+pub mod wall;
+pub mod world_gen;
@@ -0,0 +1,99 @@
+use fnv::FnvHashMap;
+use legion::{systems::CommandBuffer, world::SubWorld, *};
+use log::error;
+
+use crate::{
+    ball::{Ball, BallId},
+    lifetime::Lifetime,
+    simulation::SimulationData,
+    spawn::ball_components,
+};
+
+// Balls spawned and despawned while running, applied together by apply_lifecycle at fixed points
+// of the schedule. Despawns name balls by id, and spawned balls get ids in the order they were
+// queued, so a replay creates and removes the same balls as the recorded run, whatever entities
+// legion hands out. A resource.
+#[derive(Clone, Debug, Default)]
+pub struct Lifecycle {
+    // With the lifetime of the ball, if any.
+    spawns: Vec<(Ball, Option<f64>)>,
+    despawns: Vec<BallId>,
+    // Id of the next spawned ball. Starts after the largest id in the world.
+    next_id: Option<u64>,
+}
+
+impl Lifecycle {
+    // Spawned at the simulation time the queue is applied, whatever its initial_time, despawned
+    // after ttl if given. Balls without a positive radius are not spawned, whoever asks for them.
+    // Returns whether the ball was queued.
+    pub fn spawn(&mut self, ball: Ball, ttl: Option<f64>) -> bool {
+        if !(ball.radius > 0.) {
+            error!("Not spawning a ball of radius {}", ball.radius);
+            return false;
+        }
+        self.spawns.push((ball, ttl));
+        true
+    }
+
+    pub fn despawn(&mut self, id: BallId) {
+        self.despawns.push(id);
+    }
+
+    // Drops the queued spawns and despawns, which may not apply to a restored world, and numbers
+    // the next spawned balls after those of the world again.
+    pub fn clear(&mut self) {
+        self.spawns.clear();
+        self.despawns.clear();
+        self.next_id = None;
+    }
+}
+
+// Despawns in order of id, then spawns in the order they were queued. The commands are applied at
+// the next flush of the schedule.
+#[system]
+#[read_component(BallId)]
+pub fn apply_lifecycle(
+    world: &mut SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] lifecycle: &mut Lifecycle,
+    #[resource] simulation_data: &SimulationData,
+) {
+    if lifecycle.spawns.is_empty() && lifecycle.despawns.is_empty() {
+        return;
+    }
+    let entities = <(Entity, &BallId)>::query()
+        .iter(world)
+        .map(|(entity, id)| (*id, *entity))
+        .collect::<FnvHashMap<_, _>>();
+
+    lifecycle.despawns.sort();
+    lifecycle.despawns.dedup();
+    for id in lifecycle.despawns.drain(..) {
+        if let Some(entity) = entities.get(&id) {
+            commands.remove(*entity);
+        }
+    }
+
+    let next_id = lifecycle
+        .next_id
+        .get_or_insert_with(|| entities.keys().map(|id| id.0 + 1).max().unwrap_or(0));
+    for (ball, ttl) in lifecycle.spawns.drain(..) {
+        let entity = commands.push(ball_components(
+            Ball {
+                initial_time: simulation_data.time,
+                ..ball
+            },
+            BallId(*next_id),
+        ));
+        *next_id += 1;
+        if let Some(ttl) = ttl {
+            commands.add_component(
+                entity,
+                Lifetime {
+                    spawn_time: simulation_data.time,
+                    ttl,
+                },
+            );
+        }
+    }
+}
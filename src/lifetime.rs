@@ -0,0 +1,24 @@
+use legion::*;
+
+use crate::{ball::BallId, lifecycle::Lifecycle, simulation::SimulationData};
+
+// Balls with a lifetime are despawned once it runs out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lifetime {
+    pub spawn_time: f64,
+    pub ttl: f64,
+}
+
+// Runs at the start of a frame, before apply_lifecycle and the rebuild of the collision data, so no
+// queued collision refers to a despawned ball.
+#[system(for_each)]
+pub fn despawn_expired(
+    id: &BallId,
+    lifetime: &Lifetime,
+    #[resource] lifecycle: &mut Lifecycle,
+    #[resource] simulation_data: &SimulationData,
+) {
+    if simulation_data.time - lifetime.spawn_time > lifetime.ttl {
+        lifecycle.despawn(*id);
+    }
+}
@@ -1,76 +1,350 @@
-use winit::event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, Event, KeyboardInput, MouseButton, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Window, WindowBuilder};
-pub mod advance;
-pub mod ball;
-pub mod collision;
-pub mod render;
-pub mod simulation;
-pub mod wall;
-pub mod world_gen;
 
-use collision::CollisionDetectionData;
+use balls::ball::{Ball, Pinned};
+use balls::bounds::KeepInBounds;
+use balls::collision::{adaptive_cell_size, CollisionDetectionData};
+use balls::config::{load_config, AppConfig};
+use balls::control::{count_reply, ControlCommand, ControlServer};
+use balls::controls::{Action, KeyBindings};
+use balls::energy_log::EnergyLog;
+use balls::export::export_frames;
+use balls::forces::InteractionField;
+use balls::gravity::Gravitational;
+use balls::heat::HeatConfig;
+use balls::hit_stop::{HitStop, HitStopConfig};
+use balls::jitter::Jitter;
+use balls::render::{
+    init_graphics, init_headless_graphics, reload_shaders, DisplayConfig, Graphics,
+};
+use balls::replay::{dispatch_input, InputEvent, Recorder, Replayer};
+use balls::sanitize::SanitizeAction;
+use balls::shutdown::{register_shutdown, run_shutdown_hooks};
+use balls::simulation::{init_simulation, init_thread_pool, Simulation, SimulationConfig};
+use balls::spawn::{
+    ball_components, burst, next_ball_id, overlaps_existing, separate_overlaps, BurstConfig,
+};
+use balls::stats::scene_summary;
+use balls::trace::{pick_ball, select_ball};
+use balls::world_gen::{init_world, GenerationConfig};
 use legion::*;
-use render::{init_graphics, DisplayConfig, Graphics};
-use simulation::{adjust_simulation_speed, init_simulation, SimulationConfig};
-use world_gen::{init_world, GenerationConfig};
+use log::{error, info, warn};
+use nalgebra::Vector3;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 const WIDTH: u32 = 1600;
 const HEIGHT: u32 = 800;
+const WORLD_WIDTH: u32 = 1600;
+const WORLD_HEIGHT: u32 = 800;
+// Frames between two rebases of the simulation time, see rebase_time.
+const REBASE_INTERVAL: usize = 10000;
+// Frames exported with --export when --frames is not given.
+const DEFAULT_EXPORT_FRAMES: usize = 300;
+
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1).cloned())
+}
+
+// Performs the action of a key, other than those handled by the event loop.
+fn apply_action(
+    simulation: &mut Simulation,
+    action: Action,
+    cursor_position: PhysicalPosition<f64>,
+    burst_config: &BurstConfig,
+) {
+    match action {
+        Action::Exit | Action::TogglePause | Action::StepToCollision => (),
+        Action::SpeedUp => dispatch_input(simulation, InputEvent::AdjustSpeed { factor: 1.1 }),
+        Action::SlowDown => {
+            dispatch_input(simulation, InputEvent::AdjustSpeed { factor: 1. / 1.1 })
+        }
+        Action::IncreasePhysics(parameter) => dispatch_input(
+            simulation,
+            InputEvent::AdjustPhysics {
+                parameter,
+                delta: parameter.step(),
+            },
+        ),
+        Action::DecreasePhysics(parameter) => dispatch_input(
+            simulation,
+            InputEvent::AdjustPhysics {
+                parameter,
+                delta: -parameter.step(),
+            },
+        ),
+        Action::BlurOn => {
+            let mut graphics = simulation.resources.get_mut::<Graphics>().unwrap();
+            graphics.config.blur = true;
+        }
+        Action::BlurOff => {
+            let mut graphics = simulation.resources.get_mut::<Graphics>().unwrap();
+            graphics.config.blur = false;
+        }
+        Action::ToggleBatchMode => {
+            let render_enabled = {
+                let mut graphics = simulation.resources.get_mut::<Graphics>().unwrap();
+                graphics.config.render_enabled = !graphics.config.render_enabled;
+                graphics.config.render_enabled
+            };
+            let mut simulation_config = simulation.resources.get_mut::<SimulationConfig>().unwrap();
+            simulation_config.cap_frame_time = render_enabled;
+        }
+        Action::Reverse => dispatch_input(simulation, InputEvent::Reverse),
+        Action::ReloadShaders => {
+            reload_shaders(&mut simulation.resources.get_mut::<Graphics>().unwrap());
+        }
+        Action::ToggleDebugGrid => {
+            let mut graphics = simulation.resources.get_mut::<Graphics>().unwrap();
+            graphics.config.debug_grid = !graphics.config.debug_grid;
+        }
+        Action::Burst => {
+            let center = simulation
+                .resources
+                .get::<Graphics>()
+                .unwrap()
+                .window_to_world(cursor_position);
+            for event in burst(center, burst_config) {
+                if let InputEvent::SpawnBall {
+                    position, radius, ..
+                } = event
+                {
+                    // Skip balls that would start inside existing ones.
+                    if overlaps_existing(&simulation.world, &position, radius) {
+                        continue;
+                    }
+                }
+                dispatch_input(simulation, event);
+            }
+        }
+    }
+}
 
 pub fn main() {
     // Logging.
     log4rs::init_file("config/log4rs.yaml", Default::default())
         .expect("Logging configuration file 'log4rs.yaml' not found.");
 
-    // Setup.
-    let (graphics, event_loop) = init_graphics(DisplayConfig {
-        width: WIDTH,
-        height: HEIGHT,
-        max_vertices: 60000,
-        blur: true,
+    // Arguments.
+    let args = std::env::args().collect::<Vec<_>>();
+    let recorder = arg_value(&args, "--record")
+        .map(|path| Recorder::create(Path::new(&path)).expect("Failed to create recording file."));
+    let mut replayer = arg_value(&args, "--replay")
+        .map(|path| Replayer::load(Path::new(&path)).expect("Failed to load recording file."));
+    // Energy and momentum of every frame, as JSON lines.
+    let energy_log = arg_value(&args, "--energy-log")
+        .map(|path| EnergyLog::create(Path::new(&path)).expect("Failed to create energy log."));
+    let control_server = arg_value(&args, "--control-addr")
+        .map(|addr| ControlServer::bind(addr).expect("Failed to open control socket."));
+    // Renders without a window to a GIF, or a video through ffmpeg, then exits.
+    let export = arg_value(&args, "--export").map(PathBuf::from);
+    let export_frame_count = arg_value(&args, "--frames").map_or(DEFAULT_EXPORT_FRAMES, |frames| {
+        frames.parse().expect("--frames needs a number of frames.")
     });
+    // Worker threads, one per core by default. 1 makes runs deterministic, for debugging.
+    let threads = arg_value(&args, "--threads")
+        .or_else(|| std::env::var("BALLS_THREADS").ok())
+        .map(|threads| {
+            threads
+                .parse()
+                .expect("--threads needs a number of threads.")
+        });
+    if let Some(threads) = threads {
+        init_thread_pool(threads);
+    }
+
+    // Setup.
+    // Configuration file, in place of the settings below. Needs the config_file feature.
+    let app_config = match arg_value(&args, "--config") {
+        Some(path) => load_config(Path::new(&path)).expect("Failed to load configuration file."),
+        None => AppConfig {
+            display: DisplayConfig::builder()
+                .width(WIDTH)
+                .height(HEIGHT)
+                .world_width(WORLD_WIDTH as f64)
+                .world_height(WORLD_HEIGHT as f64)
+                .max_trail_history(0)
+                .build(),
+            generation: GenerationConfig::builder()
+                .width(WORLD_WIDTH)
+                .height(WORLD_HEIGHT)
+                .build(),
+            ..AppConfig::default()
+        },
+    };
+    let display_config = app_config.display.clone();
+    let max_trail_history = display_config.max_trail_history;
+    let (world_width, world_height) = (display_config.world_width, display_config.world_height);
+    let (graphics, event_loop) = if export.is_some() {
+        (init_headless_graphics(display_config), None)
+    } else {
+        let (graphics, event_loop) = init_graphics(display_config);
+        (graphics, Some(event_loop))
+    };
     let mut world = World::default();
 
     // Initialize world.
-    init_world(
-        &mut world,
-        GenerationConfig {
-            width: WIDTH,
-            height: HEIGHT,
-        },
-    );
+    let generation_config = app_config.generation;
+    init_world(&mut world, generation_config);
+    let overlapping = separate_overlaps(&mut world);
+    if overlapping > 0 {
+        warn!("{} pairs of balls still overlap at startup", overlapping);
+    }
     let mut resources = Resources::default();
     resources.insert(graphics);
-    init_simulation(&mut resources, SimulationConfig { time_delta: 0.1 });
-    resources.insert(CollisionDetectionData::default());
+    if let Some(recorder) = recorder {
+        resources.insert(recorder);
+        register_shutdown::<Recorder>(&mut resources);
+    }
+    let simulation_config = app_config.simulation;
+    init_simulation(&mut resources, simulation_config);
+    resources.insert(app_config.physics);
+    info!(
+        "Scene: {}",
+        scene_summary(
+            &world,
+            &generation_config,
+            &simulation_config,
+            resources.get::<Graphics>().unwrap().config.blur,
+        )
+    );
+    resources.insert(CollisionDetectionData::with_cell_size(
+        world_width,
+        world_height,
+        adaptive_cell_size(&world),
+    ));
+    let interaction_field: Option<InteractionField> = None;
+    let gravity_wells: Vec<Gravitational> = vec![];
+    // Balls that never move, spawned at rest.
+    let pinned_balls: Vec<Ball> = vec![];
+    let heat_config: Option<HeatConfig> = None;
+    let keep_in_bounds: Option<KeepInBounds> = None;
+    let jitter: Option<Jitter> = None;
+    let sanitize: Option<SanitizeAction> = None;
+    let hit_stop_config: Option<HitStopConfig> = None;
+    // Real time between two steps. Frames drawn in between show the balls moved ahead along their
+    // velocities. None steps every frame.
+    let step_interval: Option<Duration> = None;
+    let burst_config = BurstConfig {
+        count: 12,
+        speed: 40.,
+        ring_radius: 20.,
+        ball_radius: 5.,
+        color: Vector3::new(1., 0.8, 0.2),
+    };
+    let mut cursor_position = PhysicalPosition::new(0., 0.);
+    let key_bindings = KeyBindings::default();
+    let mut paused = false;
 
     // Initialize scheduler.
-    let mut schedule = Schedule::builder()
-        .add_system(crate::advance::clear_trails_system())
-        .add_system(crate::collision::collision_system())
-        .add_system(crate::collision::collision_handle_system())
-        .add_system(crate::advance::advance_balls_system())
-        .add_thread_local(crate::render::render_balls_system())
-        .add_system(crate::simulation::advance_time_system())
+    let mut schedule_builder = Schedule::builder();
+    schedule_builder
+        .add_system(balls::advance::clear_trails_system(max_trail_history))
+        .add_system(balls::lifetime::despawn_expired_system())
+        .add_system(balls::emitter::emit_balls_system())
+        .add_system(balls::lifecycle::apply_lifecycle_system())
+        // Spawned and despawned balls must be applied before collision detection.
+        .flush();
+    schedule_builder.add_system(balls::integrator::integrate_accelerations_system());
+    if !gravity_wells.is_empty() {
+        world.extend(gravity_wells.into_iter().map(|well| (well,)));
+        schedule_builder.add_system(balls::gravity::gravity_wells_system());
+    }
+    if let Some(interaction_field) = interaction_field {
+        resources.insert(interaction_field);
+        schedule_builder.add_system(balls::forces::interaction_forces_system());
+    }
+    schedule_builder.add_system(balls::advance::apply_drag_system());
+    if let Some(jitter) = jitter {
+        resources.insert(jitter);
+        schedule_builder.add_system(balls::jitter::jitter_balls_system());
+    }
+    if !pinned_balls.is_empty() {
+        for ball in pinned_balls {
+            let id = next_ball_id(&world);
+            let entity = world.push(ball_components(ball, id));
+            world.entry(entity).unwrap().add_component(Pinned);
+        }
+        schedule_builder.add_system(balls::advance::hold_pinned_balls_system());
+    }
+    schedule_builder
+        .add_system(balls::collision::collision_system())
+        .add_system(balls::collision::collision_handle_system());
+    if let Some(hit_stop_config) = hit_stop_config {
+        resources
+            .get_mut::<CollisionDetectionData>()
+            .unwrap()
+            .record_collisions = true;
+        resources.insert(hit_stop_config);
+        resources.insert(HitStop::default());
+        schedule_builder.add_system(balls::hit_stop::hit_stop_system());
+    }
+    if let Some(heat_config) = heat_config {
+        resources.insert(heat_config);
+        schedule_builder.add_system(balls::heat::cool_balls_system());
+    }
+    schedule_builder
+        .add_system(balls::advance::advance_balls_system())
+        .add_system(balls::trace::record_trace_system());
+    if let Some(keep_in_bounds) = keep_in_bounds {
+        resources.insert(keep_in_bounds);
+        schedule_builder.add_system(balls::bounds::keep_in_bounds_system());
+    }
+    if let Some(sanitize) = sanitize {
+        resources.insert(sanitize);
+        schedule_builder.add_system(balls::sanitize::sanitize_balls_system());
+    }
+    // Merged and broken balls are gone before rendering.
+    schedule_builder
+        .add_system(balls::lifecycle::apply_lifecycle_system())
+        .flush();
+    schedule_builder.add_thread_local(balls::render::render_balls_system());
+    // Renders without stepping, between steps.
+    let mut render_schedule = Schedule::builder()
+        .add_thread_local(balls::render::render_balls_system())
+        .build();
+    schedule_builder.add_system(balls::simulation::advance_time_system());
+    if let Some(energy_log) = energy_log {
+        resources.insert(energy_log);
+        register_shutdown::<EnergyLog>(&mut resources);
+        schedule_builder.add_system(balls::energy_log::log_energy_system());
+    }
+    let schedule = schedule_builder
+        .add_system(balls::simulation::rebase_time_periodically_system(
+            REBASE_INTERVAL,
+            0,
+        ))
         .build();
+    let mut simulation = Simulation::new(world, resources, schedule);
 
+    if let Some(path) = export {
+        // As fast as possible, frames are timed by the export frame rate.
+        simulation
+            .resources
+            .get_mut::<SimulationConfig>()
+            .unwrap()
+            .cap_frame_time = false;
+        if let Err(e) = export_frames(&mut simulation, &path, export_frame_count) {
+            error!("Failed to export {:?}: {}", path, e);
+        }
+        run_shutdown_hooks(&mut simulation.resources);
+        return;
+    }
+    // Created whenever not exporting.
+    let event_loop = event_loop.unwrap();
+    let mut last_step = Instant::now();
+    // Start of the last frame, stepped, interpolated or paused.
+    let mut last_frame = Instant::now();
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             event: WindowEvent::CloseRequested,
             ..
-        }
-        | Event::WindowEvent {
-            event:
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        },
-                    ..
-                },
-            ..
         } => {
             *control_flow = ControlFlow::Exit;
         }
@@ -79,61 +353,116 @@ pub fn main() {
                 WindowEvent::KeyboardInput {
                     input:
                         KeyboardInput {
-                            virtual_keycode: Some(VirtualKeyCode::NumpadAdd),
-                            ..
-                        },
-                    ..
-                },
-            ..
-        } => {
-            adjust_simulation_speed(&mut resources, 1.1);
-        }
-        Event::WindowEvent {
-            event:
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            virtual_keycode: Some(VirtualKeyCode::NumpadSubtract),
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key),
                             ..
                         },
                     ..
                 },
             ..
-        } => {
-            adjust_simulation_speed(&mut resources, 1. / 1.1);
-        }
+        } => match key_bindings.action(key) {
+            Some(Action::Exit) => *control_flow = ControlFlow::Exit,
+            Some(Action::TogglePause) => paused = !paused,
+            Some(Action::StepToCollision) => {
+                paused = true;
+                dispatch_input(&mut simulation, InputEvent::StepToCollision);
+            }
+            Some(action) => apply_action(&mut simulation, action, cursor_position, &burst_config),
+            None => (),
+        },
         Event::WindowEvent {
-            event:
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            virtual_keycode: Some(VirtualKeyCode::B),
-                            ..
-                        },
-                    ..
-                },
+            event: WindowEvent::CursorMoved { position, .. },
             ..
         } => {
-            let mut graphics = resources.get_mut::<Graphics>().unwrap();
-            graphics.config.blur = true;
+            cursor_position = position;
         }
+        // Clicking a ball traces its path, clicking elsewhere stops tracing.
         Event::WindowEvent {
             event:
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            virtual_keycode: Some(VirtualKeyCode::N),
-                            ..
-                        },
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Left,
                     ..
                 },
             ..
         } => {
-            let mut graphics = resources.get_mut::<Graphics>().unwrap();
-            graphics.config.blur = false;
+            let point = simulation
+                .resources
+                .get::<Graphics>()
+                .unwrap()
+                .window_to_world(cursor_position);
+            let entity = pick_ball(&simulation.world, point);
+            select_ball(&mut simulation.world, entity);
         }
         Event::RedrawEventsCleared => {
-            schedule.execute(&mut world, &mut resources);
+            // Too early for the next frame. Input is handled while waiting.
+            if let Some(deadline) = simulation.frame_deadline(last_frame) {
+                if Instant::now() < deadline {
+                    if *control_flow != ControlFlow::Exit {
+                        *control_flow = ControlFlow::WaitUntil(deadline);
+                    }
+                    return;
+                }
+            }
+            last_frame = Instant::now();
+            if let Some(control_server) = control_server.as_ref() {
+                for request in control_server.pending() {
+                    let reply = match request.command {
+                        ControlCommand::Pause => {
+                            paused = true;
+                            "ok".to_string()
+                        }
+                        ControlCommand::Resume => {
+                            paused = false;
+                            "ok".to_string()
+                        }
+                        ControlCommand::Reset => {
+                            dispatch_input(&mut simulation, InputEvent::Reset);
+                            "ok".to_string()
+                        }
+                        ControlCommand::Speed(factor) => {
+                            dispatch_input(&mut simulation, InputEvent::AdjustSpeed { factor });
+                            "ok".to_string()
+                        }
+                        ControlCommand::Count => count_reply(&simulation.world),
+                    };
+                    request.reply(reply);
+                }
+            }
+            if let Some(replayer) = replayer.as_mut() {
+                replayer.apply_due(&mut simulation);
+            }
+            // While paused, the balls stay drawn where they were.
+            if !paused {
+                let since_step = last_step.elapsed();
+                let alpha = match step_interval {
+                    Some(step_interval) if since_step < step_interval => {
+                        Some(since_step.as_secs_f64() / step_interval.as_secs_f64())
+                    }
+                    _ => None,
+                };
+                simulation
+                    .resources
+                    .get_mut::<Graphics>()
+                    .unwrap()
+                    .config
+                    .interpolation_alpha = alpha.unwrap_or(0.);
+                if alpha.is_some() {
+                    render_schedule.execute(&mut simulation.world, &mut simulation.resources);
+                } else {
+                    simulation.step();
+                    last_step = Instant::now();
+                }
+            }
+            if *control_flow != ControlFlow::Exit {
+                *control_flow = match simulation.frame_deadline(last_frame) {
+                    Some(deadline) => ControlFlow::WaitUntil(deadline),
+                    None => ControlFlow::Poll,
+                };
+            }
+        }
+        Event::LoopDestroyed => {
+            run_shutdown_hooks(&mut simulation.resources);
         }
         _ => (),
     });
@@ -0,0 +1,57 @@
+use nalgebra::Vector2;
+
+use crate::wall::{Wall, WallSide};
+
+// Filled convex obstacle. Spawned with CollidableType::Polygon and a Generation like walls.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polygon {
+    // In order around the polygon, either direction.
+    pub vertices: Vec<Vector2<f64>>,
+}
+
+impl Polygon {
+    // Twice the signed area, positive when the vertices go counter clockwise.
+    fn signed_area(&self) -> f64 {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(p0, p1)| p0.x * p1.y - p1.x * p0.y)
+            .sum()
+    }
+
+    // Edges as walls that only block from outside the polygon.
+    pub fn edges(&self) -> Vec<Wall> {
+        // Wall normals point to the left of p0 -> p1, which is inside for counter clockwise order.
+        let side = if self.signed_area() > 0. {
+            WallSide::Back
+        } else {
+            WallSide::Front
+        };
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(p0, p1)| Wall {
+                p0: *p0,
+                p1: *p1,
+                side,
+                friction: 0.,
+                restitution: 1.,
+            })
+            .collect()
+    }
+
+    // Closest point of the boundary, on an edge or a vertex.
+    pub fn closest_point(&self, point: &Vector2<f64>) -> Vector2<f64> {
+        self.edges()
+            .iter()
+            .map(|edge| {
+                let diff = edge.p1 - edge.p0;
+                let s = ((point - edge.p0).dot(&diff) / diff.norm_squared())
+                    .max(0.)
+                    .min(1.);
+                edge.p0 + diff * s
+            })
+            .min_by(|a, b| (a - point).norm().partial_cmp(&(b - point).norm()).unwrap())
+            .unwrap()
+    }
+}
@@ -0,0 +1,73 @@
+use log::info;
+use std::time::{Duration, Instant};
+
+// Seconds between two logs of the profile.
+const PROFILE_LOG_PERIOD: f64 = 1.;
+
+// Parts of a frame that are timed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    BroadPhase,
+    NarrowPhase,
+    Advance,
+    Render,
+}
+
+const STAGES: [Stage; 4] = [
+    Stage::BroadPhase,
+    Stage::NarrowPhase,
+    Stage::Advance,
+    Stage::Render,
+];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StageTiming {
+    // Duration in the last frame that ran the stage.
+    pub last: Duration,
+    // Totals since the last log.
+    total: Duration,
+    count: u32,
+}
+
+// Time spent in each stage, filled by the systems of the stages.
+#[derive(Clone, Debug, Default)]
+pub struct ProfileData {
+    timings: [StageTiming; 4],
+    last_log: Option<Instant>,
+}
+
+impl ProfileData {
+    pub fn record(&mut self, stage: Stage, duration: Duration) {
+        let timing = &mut self.timings[stage as usize];
+        timing.last = duration;
+        timing.total += duration;
+        timing.count += 1;
+    }
+
+    pub fn timing(&self, stage: Stage) -> &StageTiming {
+        &self.timings[stage as usize]
+    }
+
+    // Logs the average duration of each stage, at most once per PROFILE_LOG_PERIOD.
+    pub fn log_periodically(&mut self) {
+        let now = Instant::now();
+        let last_log = *self.last_log.get_or_insert(now);
+        if now.duration_since(last_log).as_secs_f64() < PROFILE_LOG_PERIOD {
+            return;
+        }
+        for stage in STAGES.iter() {
+            let timing = &mut self.timings[*stage as usize];
+            if timing.count > 0 {
+                info!(
+                    "{:?}: {:.3} ms over {} frames",
+                    stage,
+                    timing.total.as_secs_f64() * 1000. / timing.count as f64,
+                    timing.count
+                );
+            }
+            timing.total = Duration::default();
+            timing.count = 0;
+        }
+        self.last_log = Some(now);
+    }
+}
@@ -0,0 +1,48 @@
+use nalgebra::Vector2;
+
+use crate::polygon::Polygon;
+
+// Axis aligned box obstacle. Spawned with CollidableType::Rect and a Generation like walls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub min: Vector2<f64>,
+    pub max: Vector2<f64>,
+}
+
+impl Rect {
+    // Counter clockwise, so its edges block from outside.
+    pub fn polygon(&self) -> Polygon {
+        Polygon {
+            vertices: vec![
+                self.min,
+                Vector2::new(self.max.x, self.min.y),
+                self.max,
+                Vector2::new(self.min.x, self.max.y),
+            ],
+        }
+    }
+
+    // Closest point of the boundary, on a face or a corner.
+    pub fn closest_point(&self, point: &Vector2<f64>) -> Vector2<f64> {
+        let clamped = point.sup(&self.min).inf(&self.max);
+        if clamped != *point {
+            return clamped;
+        }
+        // Inside, move to the nearest face.
+        let distances = [
+            point.x - self.min.x,
+            self.max.x - point.x,
+            point.y - self.min.y,
+            self.max.y - point.y,
+        ];
+        let nearest = (0..4)
+            .min_by(|a, b| distances[*a].partial_cmp(&distances[*b]).unwrap())
+            .unwrap();
+        match nearest {
+            0 => Vector2::new(self.min.x, point.y),
+            1 => Vector2::new(self.max.x, point.y),
+            2 => Vector2::new(point.x, self.min.y),
+            _ => Vector2::new(point.x, self.max.y),
+        }
+    }
+}
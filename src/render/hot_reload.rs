@@ -0,0 +1,51 @@
+use std::{error::Error, ffi::CStr, sync::Arc};
+use vulkano::{
+    descriptor::descriptor::ShaderStages,
+    pipeline::{
+        shader::{GraphicsShaderType, ShaderModule},
+        GraphicsPipelineAbstract,
+    },
+};
+
+use super::{build_blur_pipeline, create_shaders, fs, Graphics};
+
+// Read at runtime, relative to the working directory. Same file as the embedded shader.
+pub const BLUR_FRAGMENT_SHADER: &str = "src/render/shaders/ball_blur.frag";
+
+// Compiles the fragment shader file and builds the ball pipeline with it. The shader must keep
+// the inputs, outputs and push constants of the embedded one, which describe its interface.
+pub fn reload_blur_pipeline(
+    graphics: &Graphics,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, Box<dyn Error>> {
+    let source = std::fs::read_to_string(BLUR_FRAGMENT_SHADER)?;
+    let mut compiler = shaderc::Compiler::new().ok_or("failed to create the shader compiler")?;
+    let artifact = compiler.compile_into_spirv(
+        &source,
+        shaderc::ShaderKind::Fragment,
+        BLUR_FRAGMENT_SHADER,
+        "main",
+        None,
+    )?;
+    let module =
+        unsafe { ShaderModule::from_words(graphics.device.clone(), artifact.as_binary())? };
+    // Safe as long as the file keeps the interface of the embedded shader, see above.
+    let entry_point = unsafe {
+        module.graphics_entry_point(
+            CStr::from_bytes_with_nul(b"main\0").unwrap(),
+            fs::MainInput,
+            fs::MainOutput,
+            fs::Layout(ShaderStages {
+                fragment: true,
+                ..ShaderStages::none()
+            }),
+            GraphicsShaderType::Fragment,
+        )
+    };
+    let (vs, _) = create_shaders(&graphics.device);
+    Ok(build_blur_pipeline(
+        &graphics.device,
+        &graphics.render_pass,
+        &vs,
+        entry_point,
+    )?)
+}
@@ -1,29 +1,53 @@
-use crate::{ball::Ball, ball::Trail, ball::Trails, simulation::SimulationData};
-use legion::IntoQuery;
-use legion::{system, world::SubWorld};
-use nalgebra::Vector2;
-use std::{any::Any, ffi::CStr, sync::Arc};
-use vulkano::{
-    buffer::BufferUsage,
-    pipeline::{
-        blend::{AttachmentBlend, BlendFactor, BlendOp},
-        shader::ShaderModule,
-    },
+#[cfg(feature = "hot_reload")]
+mod hot_reload;
+
+use crate::{
+    ball::Ball, ball::Trail, ball::Trails, collision::CollisionDetectionData,
+    debug_draw::DebugDraw, debug_draw::DebugPrimitive, gravity::Gravitational,
+    profile::ProfileData, profile::Stage, simulation::PerfStats, simulation::Simulation,
+    simulation::SimulationData, trace::Selected, trace::TraceHistory,
+};
+use image::RgbaImage;
+use legion::{query::component, IntoQuery};
+use legion::{system, world::SubWorld, EntityStore};
+use log::{error, info, warn};
+use nalgebra::{Vector2, Vector3};
+use std::{
+    any::Any,
+    error::Error,
+    ffi::CStr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
 };
 use vulkano::{
     buffer::CpuAccessibleBuffer,
-    command_buffer::{AutoCommandBufferBuilder, DynamicState, SubpassContents},
-    descriptor::PipelineLayoutAbstract,
+    command_buffer::{
+        AutoCommandBufferBuilder, DynamicState, PrimaryAutoCommandBuffer, SubpassContents,
+    },
+    descriptor::{descriptor_set::PersistentDescriptorSet, DescriptorSet, PipelineLayoutAbstract},
     format::Format,
     framebuffer::{RenderPass, RenderPassAbstract, Subpass},
-    image::{view::ImageView, ImageUsage},
+    image::{
+        view::ImageView, AttachmentImage, ImageAccess, ImageDimensions, ImageUsage, ImmutableImage,
+        MipmapsCount,
+    },
     instance::InstanceExtensions,
     pipeline::{vertex::SingleBufferDefinition, viewport::Viewport, GraphicsPipelineAbstract},
+    sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode},
     swapchain::{
         self, AcquireError, ColorSpace, FullscreenExclusive, PresentMode, SurfaceTransform,
     },
     sync::{self, FlushError, GpuFuture, NowFuture},
 };
+use vulkano::{
+    buffer::{BufferAccess, BufferSlice, BufferUsage, DeviceLocalBuffer, TypedBufferAccess},
+    pipeline::{
+        blend::{AttachmentBlend, BlendFactor, BlendOp},
+        shader::{GraphicsEntryPoint, ShaderModule},
+        GraphicsPipelineCreationError,
+    },
+};
 use vulkano::{device::DeviceExtensions, framebuffer::Framebuffer};
 use vulkano::{device::Features, pipeline::GraphicsPipeline};
 use vulkano::{
@@ -34,15 +58,205 @@ use vulkano::{framebuffer::FramebufferAbstract, instance::PhysicalDevice};
 use vulkano::{image::SwapchainImage, instance::Instance};
 use vulkano_win::VkSurfaceBuild;
 use winit::{
-    dpi::LogicalSize,
+    dpi::{LogicalSize, PhysicalPosition},
     event_loop::EventLoop,
-    window::{Window, WindowBuilder},
+    window::{Icon, Window, WindowBuilder},
 };
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "config_file", derive(serde::Deserialize), serde(default))]
 pub struct DisplayConfig {
+    // Window size in pixels.
     pub width: u32,
     pub height: u32,
+    // Extent of the world mapped onto the window.
+    pub world_width: f64,
+    pub world_height: f64,
     pub max_vertices: i32,
+    // Reallocate larger buffers when a frame needs more than max_vertices, and draw it again.
+    // Off by default, quads that do not fit are dropped.
+    pub grow_buffers: bool,
     pub blur: bool,
+    pub render_enabled: bool,
+    // Overlay the broad phase grid and its occupancy.
+    pub debug_grid: bool,
+    // Spacing in world units of a faint grid drawn behind everything, for measuring. None for no
+    // grid, the default.
+    pub reference_grid: Option<f64>,
+    // Flash a corner of the view in frames where collisions were lost, see SkippedCollisions.
+    pub show_skipped_collisions: bool,
+    // Trails kept from previous frames and drawn with blur on. Must match clear_trails.
+    pub max_trail_history: usize,
+    // Exponent applied to trail opacity in the blur shader. 1 leaves it unchanged, lower values
+    // keep fast balls visible.
+    pub trail_opacity_curve: f32,
+    // Opacity lost at the old end of a trail, from 0 for none to 1 for fully transparent.
+    pub trail_fade: f32,
+    // Frames recorded before waiting for the oldest one to finish, each with its own buffers.
+    // Independent from the number of swapchain images.
+    pub frames_in_flight: usize,
+    pub title: String,
+    // Image file for the window icon. The window keeps the default icon if it cannot be loaded.
+    pub icon: Option<PathBuf>,
+    // Image file drawn across each ball, instead of its color. Balls keep their color if it
+    // cannot be loaded.
+    pub ball_texture: Option<PathBuf>,
+    // Fraction of the coming step the balls are drawn ahead by, from 0 to 1. Set by the loop for
+    // frames drawn between steps, see interpolated_position.
+    pub interpolation_alpha: f64,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> DisplayConfig {
+        DisplayConfig {
+            width: 1600,
+            height: 800,
+            world_width: 1600.,
+            world_height: 800.,
+            max_vertices: 60000,
+            grow_buffers: false,
+            blur: true,
+            render_enabled: true,
+            debug_grid: false,
+            reference_grid: None,
+            show_skipped_collisions: false,
+            max_trail_history: 0,
+            trail_opacity_curve: 1.,
+            trail_fade: 0.,
+            frames_in_flight: 2,
+            title: "Balls".to_string(),
+            icon: None,
+            ball_texture: None,
+            interpolation_alpha: 0.,
+        }
+    }
+}
+
+impl DisplayConfig {
+    /// Starts from the defaults, fields that are not set keep them.
+    ///
+    /// ```
+    /// use balls::render::DisplayConfig;
+    ///
+    /// let config = DisplayConfig::builder()
+    ///     .width(800)
+    ///     .height(400)
+    ///     .blur(false)
+    ///     .build();
+    /// assert_eq!((config.width, config.height, config.blur), (800, 400, false));
+    /// assert_eq!(config.world_width, 1600.);
+    /// assert_eq!(config.frames_in_flight, 2);
+    /// ```
+    pub fn builder() -> DisplayConfigBuilder {
+        DisplayConfigBuilder {
+            config: DisplayConfig::default(),
+        }
+    }
+}
+
+// Sets some fields of a DisplayConfig, the others keep their defaults.
+pub struct DisplayConfigBuilder {
+    config: DisplayConfig,
+}
+
+impl DisplayConfigBuilder {
+    pub fn width(mut self, width: u32) -> Self {
+        self.config.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: u32) -> Self {
+        self.config.height = height;
+        self
+    }
+
+    pub fn world_width(mut self, world_width: f64) -> Self {
+        self.config.world_width = world_width;
+        self
+    }
+
+    pub fn world_height(mut self, world_height: f64) -> Self {
+        self.config.world_height = world_height;
+        self
+    }
+
+    pub fn max_vertices(mut self, max_vertices: i32) -> Self {
+        self.config.max_vertices = max_vertices;
+        self
+    }
+
+    pub fn grow_buffers(mut self, grow_buffers: bool) -> Self {
+        self.config.grow_buffers = grow_buffers;
+        self
+    }
+
+    pub fn blur(mut self, blur: bool) -> Self {
+        self.config.blur = blur;
+        self
+    }
+
+    pub fn render_enabled(mut self, render_enabled: bool) -> Self {
+        self.config.render_enabled = render_enabled;
+        self
+    }
+
+    pub fn debug_grid(mut self, debug_grid: bool) -> Self {
+        self.config.debug_grid = debug_grid;
+        self
+    }
+
+    pub fn reference_grid(mut self, spacing: f64) -> Self {
+        self.config.reference_grid = Some(spacing);
+        self
+    }
+
+    pub fn show_skipped_collisions(mut self, show_skipped_collisions: bool) -> Self {
+        self.config.show_skipped_collisions = show_skipped_collisions;
+        self
+    }
+
+    pub fn max_trail_history(mut self, max_trail_history: usize) -> Self {
+        self.config.max_trail_history = max_trail_history;
+        self
+    }
+
+    pub fn trail_opacity_curve(mut self, trail_opacity_curve: f32) -> Self {
+        self.config.trail_opacity_curve = trail_opacity_curve;
+        self
+    }
+
+    pub fn trail_fade(mut self, trail_fade: f32) -> Self {
+        self.config.trail_fade = trail_fade;
+        self
+    }
+
+    pub fn frames_in_flight(mut self, frames_in_flight: usize) -> Self {
+        self.config.frames_in_flight = frames_in_flight;
+        self
+    }
+
+    pub fn title(mut self, title: String) -> Self {
+        self.config.title = title;
+        self
+    }
+
+    pub fn icon(mut self, icon: Option<PathBuf>) -> Self {
+        self.config.icon = icon;
+        self
+    }
+
+    pub fn ball_texture(mut self, ball_texture: Option<PathBuf>) -> Self {
+        self.config.ball_texture = ball_texture;
+        self
+    }
+
+    pub fn interpolation_alpha(mut self, interpolation_alpha: f64) -> Self {
+        self.config.interpolation_alpha = interpolation_alpha;
+        self
+    }
+
+    pub fn build(self) -> DisplayConfig {
+        self.config
+    }
 }
 
 #[derive(Default, Copy, Clone)]
@@ -52,8 +266,18 @@ pub struct Vertex {
     color: [f32; 3],
     trail_length: f32,
     total_portion: f32,
+    // 1 where the ball texture is drawn, see DisplayConfig::ball_texture.
+    textured: f32,
 }
-vulkano::impl_vertex!(Vertex, position, coords, color, trail_length, total_portion);
+vulkano::impl_vertex!(
+    Vertex,
+    position,
+    coords,
+    color,
+    trail_length,
+    total_portion,
+    textured
+);
 
 #[derive(Default, Copy, Clone)]
 pub struct BasicVertex {
@@ -66,27 +290,91 @@ pub struct Graphics {
     instance: Arc<Instance>,
     device: Arc<Device>,
     queue: Arc<Queue>,
-    swapchain: Arc<Swapchain<Window>>,
+    // None when rendering offscreen.
+    swapchain: Option<Arc<Swapchain<Window>>>,
+    offscreen: Option<OffscreenTarget>,
     dynamic_state: DynamicState,
     framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
     pipeline0: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
     pipeline1: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    // Binds the ball texture when pipeline0 draws it.
+    texture_set: Option<Arc<dyn DescriptorSet + Send + Sync>>,
+    // Per frame in flight. The future of a slot guards its buffers until the GPU is done with them.
     previous_frame_ends: Vec<Option<Box<dyn GpuFuture>>>,
+    frame_slot: usize,
     vertex_buffers: Vec<Arc<CpuAccessibleBuffer<[Vertex]>>>,
     index_buffers: Vec<Arc<CpuAccessibleBuffer<[u16]>>>,
+    // Device local copies of the buffers above, which then only serve for staging. Only for large
+    // buffers, see STAGED_UPLOAD_MIN_VERTICES.
+    staged_buffers: Option<StagedBuffers>,
+    // Frame data is built here, then copied to the buffers in one go.
+    vertex_data: Vec<Vertex>,
+    index_data: Vec<u16>,
     basic_vertex_buffer: Arc<CpuAccessibleBuffer<[BasicVertex]>>,
 }
 
-fn window_size_dependent_setup(
-    images: &[Arc<SwapchainImage<Window>>],
+// Image rendered into without a window, and the buffer it is copied to after each frame.
+struct OffscreenTarget {
+    image: Arc<AttachmentImage>,
+    readback: Arc<CpuAccessibleBuffer<[u8]>>,
+}
+
+enum RenderTarget {
+    Swapchain(Arc<Swapchain<Window>>, Vec<Arc<SwapchainImage<Window>>>),
+    Offscreen(OffscreenTarget),
+}
+
+impl Graphics {
+    // Maps a cursor position, in pixels from the top left corner of the window, to the world.
+    pub fn window_to_world(&self, position: PhysicalPosition<f64>) -> Vector2<f64> {
+        let (width, height) = match &self.swapchain {
+            Some(swapchain) => {
+                let size = swapchain.surface().window().inner_size();
+                (size.width, size.height)
+            }
+            None => (self.config.width, self.config.height),
+        };
+        Vector2::new(
+            position.x / width as f64 * self.config.world_width,
+            position.y / height as f64 * self.config.world_height,
+        )
+    }
+}
+
+// Rebuilds the ball pipeline from the fragment shader file, to iterate on it without rebuilding
+// the crate. Keeps the current pipeline on errors. Needs the hot_reload feature.
+pub fn reload_shaders(graphics: &mut Graphics) {
+    if graphics.texture_set.is_some() {
+        warn!("Textured balls are drawn with another shader, which does not reload.");
+        return;
+    }
+    #[cfg(feature = "hot_reload")]
+    match hot_reload::reload_blur_pipeline(graphics) {
+        Ok(pipeline) => {
+            graphics.pipeline0 = pipeline;
+            info!("Reloaded {}", hot_reload::BLUR_FRAGMENT_SHADER);
+        }
+        Err(e) => error!("Failed to reload shaders, keeping the previous ones: {}", e),
+    }
+    #[cfg(not(feature = "hot_reload"))]
+    warn!("Shaders are embedded, build with the hot_reload feature to reload them.");
+}
+
+// Swapchain images, or the offscreen image.
+fn window_size_dependent_setup<I>(
+    images: &[Arc<I>],
     render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
     dynamic_state: &mut DynamicState,
-) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>> {
+) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>>
+where
+    I: ImageAccess + Send + Sync + 'static,
+{
     let dimensions = images[0].dimensions();
 
     let viewport = Viewport {
         origin: [0.0, 0.0],
-        dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+        dimensions: [dimensions.width() as f32, dimensions.height() as f32],
         depth_range: 0.0..1.0,
     };
     dynamic_state.viewports = Some(vec![viewport]);
@@ -106,6 +394,19 @@ fn window_size_dependent_setup(
         .collect::<Vec<_>>()
 }
 
+// Vertex count from which buffers are uploaded to device local memory through staging, instead
+// of being read by the GPU from host visible memory. Reading host visible memory from discrete
+// GPUs is slow, but staging costs a copy per frame, which only pays off for large buffers.
+const STAGED_UPLOAD_MIN_VERTICES: i32 = 20000;
+// Indices are 16 bits, so buffers never grow past the vertices they can address.
+const MAX_GROWN_VERTICES: usize = 1 << 16;
+
+fn load_icon(path: &Path) -> Result<Icon, Box<dyn Error>> {
+    let image = image::open(path)?.into_rgba8();
+    let (width, height) = image.dimensions();
+    Ok(Icon::from_rgba(image.into_raw(), width, height)?)
+}
+
 pub fn init_graphics(display_config: DisplayConfig) -> (Graphics, EventLoop<()>) {
     let instance = {
         let extensions = vulkano_win::required_extensions();
@@ -117,6 +418,12 @@ pub fn init_graphics(display_config: DisplayConfig) -> (Graphics, EventLoop<()>)
             display_config.width,
             display_config.height,
         ))
+        .with_title(display_config.title.clone())
+        .with_window_icon(display_config.icon.as_ref().and_then(|path| {
+            load_icon(path)
+                .map_err(|e| error!("Failed to load window icon {:?}: {}", path, e))
+                .ok()
+        }))
         .build_vk_surface(&event_loop, instance.clone())
         .unwrap();
 
@@ -149,7 +456,15 @@ pub fn init_graphics(display_config: DisplayConfig) -> (Graphics, EventLoop<()>)
         .current_extent
         .unwrap_or([display_config.width, display_config.height]);
     let alpha = caps.supported_composite_alpha.iter().next().unwrap();
-    let format = caps.supported_formats[0].0;
+    // Colors are linear. With an sRGB format the hardware gamma encodes them on write and blends
+    // in linear space, so a 0.5 gray ball shows as mid bright instead of too dark. Without one,
+    // colors are written as is.
+    let format = caps
+        .supported_formats
+        .iter()
+        .map(|(format, _)| *format)
+        .find(|format| matches!(format, Format::B8G8R8A8Srgb | Format::R8G8B8A8Srgb))
+        .unwrap_or(caps.supported_formats[0].0);
     let (swapchain, images) = Swapchain::new(
         device.clone(),
         surface.clone(),
@@ -167,6 +482,203 @@ pub fn init_graphics(display_config: DisplayConfig) -> (Graphics, EventLoop<()>)
         ColorSpace::SrgbNonLinear,
     )
     .expect("failed to create swapchain");
+
+    (
+        build_graphics(
+            display_config,
+            instance,
+            device,
+            queue,
+            format,
+            RenderTarget::Swapchain(swapchain, images),
+        ),
+        event_loop,
+    )
+}
+
+// Renders into an image instead of a window, for exports. Frames are read with read_frame.
+pub fn init_headless_graphics(display_config: DisplayConfig) -> Graphics {
+    let instance = Instance::new(None, &InstanceExtensions::none(), None)
+        .expect("failed to create Vulkan instance");
+    let physical = PhysicalDevice::enumerate(&instance)
+        .next()
+        .expect("no device available");
+    let queue_family = physical
+        .queue_families()
+        .find(|&q| q.supports_graphics())
+        .expect("couldn't find a graphical queue family");
+    let (device, mut queues) = {
+        Device::new(
+            physical,
+            physical.supported_features(),
+            &DeviceExtensions::none(),
+            [(queue_family, 0.5)].iter().cloned(),
+        )
+        .expect("failed to create device")
+    };
+    let queue = queues.next().unwrap();
+    offscreen_graphics(display_config, instance, device, queue)
+}
+
+// Offscreen graphics on an existing device.
+fn offscreen_graphics(
+    display_config: DisplayConfig,
+    instance: Arc<Instance>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+) -> Graphics {
+    // sRGB like the window, so the bytes read back are the colors it would show.
+    let format = Format::R8G8B8A8Srgb;
+    let image = AttachmentImage::with_usage(
+        device.clone(),
+        [display_config.width, display_config.height],
+        format,
+        ImageUsage {
+            color_attachment: true,
+            transfer_source: true,
+            ..ImageUsage::none()
+        },
+    )
+    .expect("failed to create offscreen image");
+    let readback = CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage {
+            transfer_destination: true,
+            ..BufferUsage::none()
+        },
+        false,
+        (0..display_config.width * display_config.height * 4).map(|_| 0u8),
+    )
+    .expect("failed to create buffer");
+
+    build_graphics(
+        display_config,
+        instance,
+        device,
+        queue,
+        format,
+        RenderTarget::Offscreen(OffscreenTarget { image, readback }),
+    )
+}
+
+type StagedBuffers = Vec<(
+    Arc<DeviceLocalBuffer<[Vertex]>>,
+    Arc<DeviceLocalBuffer<[u16]>>,
+)>;
+
+// Vertex and index buffers of each frame slot, with their device local copies if staged.
+fn create_quad_buffers(
+    device: &Arc<Device>,
+    queue: &Arc<Queue>,
+    frames_in_flight: usize,
+    max_vertices: i32,
+) -> (
+    Vec<Arc<CpuAccessibleBuffer<[Vertex]>>>,
+    Vec<Arc<CpuAccessibleBuffer<[u16]>>>,
+    Option<StagedBuffers>,
+) {
+    let vertex_buffers = (0..frames_in_flight)
+        .map(|slot| {
+            CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage::all(),
+                false,
+                (0..max_vertices).map(|i| Vertex::default()),
+            )
+            .expect("failed to create buffer")
+        })
+        .collect::<Vec<_>>();
+    let index_buffers = (0..frames_in_flight)
+        .map(|slot| {
+            CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage::all(),
+                false,
+                (0..max_vertices).map(|i| 0u16),
+            )
+            .expect("failed to create buffer")
+        })
+        .collect::<Vec<_>>();
+
+    let staged_buffers = if max_vertices >= STAGED_UPLOAD_MIN_VERTICES {
+        Some(
+            (0..frames_in_flight)
+                .map(|slot| {
+                    let usage = BufferUsage {
+                        transfer_destination: true,
+                        vertex_buffer: true,
+                        index_buffer: true,
+                        ..BufferUsage::none()
+                    };
+                    (
+                        DeviceLocalBuffer::array(
+                            device.clone(),
+                            max_vertices as usize,
+                            usage,
+                            std::iter::once(queue.family()),
+                        )
+                        .expect("failed to create buffer"),
+                        DeviceLocalBuffer::array(
+                            device.clone(),
+                            max_vertices as usize,
+                            usage,
+                            std::iter::once(queue.family()),
+                        )
+                        .expect("failed to create buffer"),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        None
+    };
+    (vertex_buffers, index_buffers, staged_buffers)
+}
+
+// Capacity to grow the buffers to when a frame needs more than they hold. None if the frame fits,
+// or if the buffers cannot grow any further.
+fn grown_capacity(capacity: usize, needed: usize) -> Option<usize> {
+    if needed <= capacity || capacity >= MAX_GROWN_VERTICES {
+        return None;
+    }
+    Some(needed.next_power_of_two().min(MAX_GROWN_VERTICES))
+}
+
+// Replaces the buffers of all frame slots with larger ones. Frames in flight still read the old
+// buffers, so they are finished first.
+fn grow_quad_buffers(graphics: &mut Graphics, max_vertices: usize) {
+    for previous_frame_end in graphics.previous_frame_ends.iter_mut() {
+        if let Some(future) = previous_frame_end.take() {
+            future.then_signal_fence().wait(None).unwrap();
+        }
+    }
+    info!(
+        "Growing vertex buffers from {} to {} vertices",
+        graphics.config.max_vertices, max_vertices
+    );
+    graphics.config.max_vertices = max_vertices as i32;
+    let (vertex_buffers, index_buffers, staged_buffers) = create_quad_buffers(
+        &graphics.device,
+        &graphics.queue,
+        graphics.previous_frame_ends.len(),
+        graphics.config.max_vertices,
+    );
+    graphics.vertex_buffers = vertex_buffers;
+    graphics.index_buffers = index_buffers;
+    graphics.staged_buffers = staged_buffers;
+    graphics.vertex_data = vec![Vertex::default(); max_vertices];
+    graphics.index_data = vec![0u16; max_vertices];
+}
+
+// Pipelines and buffers, shared by window and offscreen rendering.
+fn build_graphics(
+    display_config: DisplayConfig,
+    instance: Arc<Instance>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    format: Format,
+    target: RenderTarget,
+) -> Graphics {
     let mut dynamic_state = DynamicState {
         line_width: None,
         viewports: None,
@@ -183,7 +695,7 @@ pub fn init_graphics(display_config: DisplayConfig) -> (Graphics, EventLoop<()>)
                 color: {
                     load: Clear,
                     store: Store,
-                    format: Format::B8G8R8A8Unorm,
+                    format: format,
                     samples: 1,
                 }
             },
@@ -203,32 +715,20 @@ pub fn init_graphics(display_config: DisplayConfig) -> (Graphics, EventLoop<()>)
         .unwrap(),
     );
 
+    let render_pass = render_pass as Arc<dyn RenderPassAbstract + Send + Sync>;
     let (vs, fs) = create_shaders(&device);
-    let pipeline0 = Arc::new(
-        GraphicsPipeline::start()
-            .vertex_input_single_buffer::<Vertex>()
-            .vertex_shader(vs.main_entry_point(), ())
-            .triangle_list()
-            .viewports_dynamic_scissors_irrelevant(1)
-            .fragment_shader(fs.main_entry_point(), ())
-            .depth_stencil_disabled()
-            .blend_collective(AttachmentBlend {
-                enabled: true,
-                color_op: BlendOp::Add,
-                color_source: BlendFactor::SrcAlpha,
-                color_destination: BlendFactor::One,
-                alpha_op: BlendOp::Add,
-                alpha_source: BlendFactor::One,
-                alpha_destination: BlendFactor::One,
-                mask_red: true,
-                mask_green: true,
-                mask_blue: true,
-                mask_alpha: true,
-            })
-            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-            .build(device.clone())
-            .unwrap(),
-    );
+    let textured = display_config.ball_texture.as_ref().and_then(|path| {
+        load_ball_texture(&device, &queue, &render_pass, &vs, path)
+            .map_err(|e| error!("Failed to load ball texture {:?}: {}", path, e))
+            .ok()
+    });
+    let (pipeline0, texture_set) = match textured {
+        Some((pipeline, texture_set)) => (pipeline, Some(texture_set)),
+        None => (
+            build_blur_pipeline(&device, &render_pass, &vs, fs.main_entry_point()).unwrap(),
+            None,
+        ),
+    };
 
     let (vs1, fs1) = create_shaders1(&device);
     let pipeline1 = Arc::new(
@@ -257,24 +757,25 @@ pub fn init_graphics(display_config: DisplayConfig) -> (Graphics, EventLoop<()>)
             .unwrap(),
     );
 
-    let mut framebuffers =
-        window_size_dependent_setup(&images, render_pass.clone(), &mut dynamic_state);
+    let (swapchain, offscreen, framebuffers) = match target {
+        RenderTarget::Swapchain(swapchain, images) => {
+            let framebuffers =
+                window_size_dependent_setup(&images, render_pass.clone(), &mut dynamic_state);
+            (Some(swapchain), None, framebuffers)
+        }
+        RenderTarget::Offscreen(offscreen) => {
+            let framebuffers = window_size_dependent_setup(
+                &[offscreen.image.clone()],
+                render_pass.clone(),
+                &mut dynamic_state,
+            );
+            (None, Some(offscreen), framebuffers)
+        }
+    };
 
-    let mut previous_frame_ends = images.iter().map(|image| None).collect::<Vec<_>>();
+    let frame_slots = 0..display_config.frames_in_flight.max(1);
+    let mut previous_frame_ends = frame_slots.clone().map(|slot| None).collect::<Vec<_>>();
 
-    // We now create a buffer that will store the shape of our triangle.
-    let vertex_buffers = images
-        .iter()
-        .map(|image| {
-            CpuAccessibleBuffer::from_iter(
-                device.clone(),
-                BufferUsage::all(),
-                false,
-                (0..display_config.max_vertices).map(|i| Vertex::default()),
-            )
-            .expect("failed to create buffer")
-        })
-        .collect::<Vec<_>>();
     let basic_vertex_buffer = CpuAccessibleBuffer::from_iter(
         device.clone(),
         BufferUsage::all(),
@@ -303,38 +804,37 @@ pub fn init_graphics(display_config: DisplayConfig) -> (Graphics, EventLoop<()>)
         .cloned(),
     )
     .expect("failed to create buffer");
+    let (vertex_buffers, index_buffers, staged_buffers) = create_quad_buffers(
+        &device,
+        &queue,
+        frame_slots.len(),
+        display_config.max_vertices,
+    );
+    let vertex_data = vec![Vertex::default(); display_config.max_vertices as usize];
+    let index_data = vec![0u16; display_config.max_vertices as usize];
 
-    let index_buffers = images
-        .iter()
-        .map(|image| {
-            CpuAccessibleBuffer::from_iter(
-                device.clone(),
-                BufferUsage::all(),
-                false,
-                (0..display_config.max_vertices).map(|i| 0u16),
-            )
-            .expect("failed to create buffer")
-        })
-        .collect::<Vec<_>>();
-
-    (
-        Graphics {
-            config: display_config,
-            instance: instance,
-            device: device,
-            queue: queue,
-            swapchain: swapchain,
-            dynamic_state: dynamic_state,
-            framebuffers: framebuffers,
-            pipeline0: pipeline0,
-            pipeline1: pipeline1,
-            previous_frame_ends: previous_frame_ends,
-            vertex_buffers: vertex_buffers,
-            index_buffers: index_buffers,
-            basic_vertex_buffer: basic_vertex_buffer,
-        },
-        event_loop,
-    )
+    Graphics {
+        config: display_config,
+        instance: instance,
+        device: device,
+        queue: queue,
+        swapchain: swapchain,
+        offscreen: offscreen,
+        dynamic_state: dynamic_state,
+        framebuffers: framebuffers,
+        render_pass: render_pass,
+        pipeline0: pipeline0,
+        pipeline1: pipeline1,
+        texture_set: texture_set,
+        previous_frame_ends: previous_frame_ends,
+        frame_slot: 0,
+        vertex_buffers: vertex_buffers,
+        index_buffers: index_buffers,
+        staged_buffers: staged_buffers,
+        vertex_data: vertex_data,
+        index_data: index_data,
+        basic_vertex_buffer: basic_vertex_buffer,
+    }
 }
 
 mod vs {
@@ -351,6 +851,13 @@ mod fs {
     }
 }
 
+mod fs_texture {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/render/shaders/ball_texture.frag",
+    }
+}
+
 mod vs1 {
     vulkano_shaders::shader! {
         ty: "vertex",
@@ -365,6 +872,110 @@ mod fs1 {
     }
 }
 
+// Draws the balls. Built again when the fragment shader is reloaded.
+fn build_blur_pipeline(
+    device: &Arc<Device>,
+    render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    vs: &vs::Shader,
+    fs: GraphicsEntryPoint<(), fs::MainInput, fs::MainOutput, fs::Layout>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, GraphicsPipelineCreationError> {
+    Ok(Arc::new(
+        GraphicsPipeline::start()
+            .vertex_input_single_buffer::<Vertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs, ())
+            .depth_stencil_disabled()
+            .blend_collective(ball_blend())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(device.clone())?,
+    ))
+}
+
+// Balls add up, so overlapping trails get brighter.
+fn ball_blend() -> AttachmentBlend {
+    AttachmentBlend {
+        enabled: true,
+        color_op: BlendOp::Add,
+        color_source: BlendFactor::SrcAlpha,
+        color_destination: BlendFactor::One,
+        alpha_op: BlendOp::Add,
+        alpha_source: BlendFactor::One,
+        alpha_destination: BlendFactor::One,
+        mask_red: true,
+        mask_green: true,
+        mask_blue: true,
+        mask_alpha: true,
+    }
+}
+
+// Uploads the ball texture, and builds the ball pipeline that samples it with the set binding it.
+fn load_ball_texture(
+    device: &Arc<Device>,
+    queue: &Arc<Queue>,
+    render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    vs: &vs::Shader,
+    path: &Path,
+) -> Result<
+    (
+        Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        Arc<dyn DescriptorSet + Send + Sync>,
+    ),
+    Box<dyn Error>,
+> {
+    let image = image::open(path)?.into_rgba8();
+    let (width, height) = image.dimensions();
+    let (texture, upload) = ImmutableImage::from_iter(
+        image.into_raw().into_iter(),
+        ImageDimensions::Dim2d {
+            width,
+            height,
+            array_layers: 1,
+        },
+        MipmapsCount::One,
+        Format::R8G8B8A8Srgb,
+        queue.clone(),
+    )?;
+    upload.then_signal_fence_and_flush()?.wait(None)?;
+    let sampler = Sampler::new(
+        device.clone(),
+        Filter::Linear,
+        Filter::Linear,
+        MipmapMode::Nearest,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    )?;
+
+    let fs = fs_texture::Shader::load(device.clone())?;
+    let pipeline = Arc::new(
+        GraphicsPipeline::start()
+            .vertex_input_single_buffer::<Vertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .depth_stencil_disabled()
+            .blend_collective(ball_blend())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(device.clone())?,
+    );
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .ok_or("the texture shader has no descriptor set")?;
+    let texture_set = Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(ImageView::new(texture)?, sampler)?
+            .build()?,
+    );
+    Ok((pipeline, texture_set))
+}
+
 fn create_shaders(device: &Arc<Device>) -> (vs::Shader, fs::Shader) {
     let vs = vs::Shader::load(device.clone()).unwrap();
     let fs = fs::Shader::load(device.clone()).unwrap();
@@ -376,120 +987,478 @@ fn create_shaders1(device: &Arc<Device>) -> (vs1::Shader, fs1::Shader) {
     (vs, fs)
 }
 
-#[system]
-#[read_component(Ball)]
-#[read_component(Trails)]
-pub fn render_balls(
-    world: &mut SubWorld,
-    #[resource] graphics: &mut Graphics,
-    #[resource] simulation_data: &mut SimulationData,
+// Part of the window a world is drawn into, as fractions of the window size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Tile {
+    pub const FULL: Tile = Tile {
+        x: 0.,
+        y: 0.,
+        width: 1.,
+        height: 1.,
+    };
+}
+
+fn to_screen(config: &DisplayConfig, tile: &Tile, position: &Vector2<f64>) -> [f32; 2] {
+    [
+        -1.0 + 2.0 * (tile.x + tile.width * (position[0] / config.world_width) as f32),
+        -1.0 + 2.0 * (tile.y + tile.height * (position[1] / config.world_height) as f32),
+    ]
+}
+
+// Appends quads to the vertex and index buffers.
+pub struct QuadWriter<'a> {
+    vertices: &'a mut [Vertex],
+    indices: &'a mut [u16],
+    vertex_index: usize,
+    index_index: usize,
+    // Quads that did not fit.
+    dropped: usize,
+}
+
+impl<'a> QuadWriter<'a> {
+    fn new(vertices: &'a mut [Vertex], indices: &'a mut [u16]) -> QuadWriter<'a> {
+        QuadWriter {
+            vertices,
+            indices,
+            vertex_index: 0,
+            index_index: 0,
+            dropped: 0,
+        }
+    }
+
+    // Corners are ordered row by row. Quads that do not fit are dropped.
+    fn push_quad(&mut self, vertices: [Vertex; 4]) -> bool {
+        if self.vertex_index + 4 > self.vertices.len() || self.index_index + 6 > self.indices.len()
+        {
+            self.dropped += 1;
+            return false;
+        }
+        for (offset, corner) in [0usize, 1, 2, 2, 1, 3].iter().enumerate() {
+            self.indices[self.index_index + offset] = (self.vertex_index + *corner) as u16;
+        }
+        self.index_index += 6;
+        self.vertices[self.vertex_index..self.vertex_index + 4].copy_from_slice(&vertices);
+        self.vertex_index += 4;
+        true
+    }
+
+    // Entries the buffers would need for every quad pushed, the dropped ones included. Both
+    // buffers hold max_vertices entries, and indices run out first.
+    fn needed(&self) -> usize {
+        self.index_index + 6 * self.dropped
+    }
+
+    // Uniformly colored axis aligned rectangle. With constant coords and no trail, the ball shader
+    // outputs the color with an alpha of total_portion.
+    fn push_rect(
+        &mut self,
+        config: &DisplayConfig,
+        tile: &Tile,
+        min: Vector2<f64>,
+        max: Vector2<f64>,
+        color: [f32; 3],
+        alpha: f32,
+    ) -> bool {
+        let mut vertices = [Vertex::default(); 4];
+        for (corner, position) in [
+            Vector2::new(min.x, min.y),
+            Vector2::new(max.x, min.y),
+            Vector2::new(min.x, max.y),
+            Vector2::new(max.x, max.y),
+        ]
+        .iter()
+        .enumerate()
+        {
+            vertices[corner] = Vertex {
+                position: to_screen(config, tile, position),
+                coords: [0., 0.],
+                color: color,
+                trail_length: 0.,
+                total_portion: alpha,
+                textured: 0.,
+            };
+        }
+        self.push_quad(vertices)
+    }
+}
+
+// Side of the corner marker of show_skipped_collisions, as a fraction of the world width.
+const SKIPPED_COLLISIONS_MARKER_SIZE: f64 = 0.02;
+
+// Offsets of the reference grid lines across extent world units, from 0 to the last one that fits.
+// Empty for a spacing that is not positive.
+fn reference_grid_lines(extent: f64, spacing: f64) -> Vec<f64> {
+    if spacing.is_nan() || spacing <= 0. {
+        return Vec::new();
+    }
+    (0..=(extent / spacing).floor() as usize)
+        .map(|i| i as f64 * spacing)
+        .collect()
+}
+
+// Faint lines every spacing world units across the world, with brighter axes through the origin.
+fn push_reference_grid(quads: &mut QuadWriter, config: &DisplayConfig, tile: &Tile, spacing: f64) {
+    // One pixel wide lines.
+    let line_width = config.world_width / (config.width as f64 * tile.width as f64);
+    let alpha = |i: usize| if i == 0 { 0.6 } else { 0.2 };
+    for (i, x) in reference_grid_lines(config.world_width, spacing)
+        .into_iter()
+        .enumerate()
+    {
+        quads.push_rect(
+            config,
+            tile,
+            Vector2::new(x, 0.),
+            Vector2::new(x + line_width, config.world_height),
+            [0.5, 0.6, 0.8],
+            alpha(i),
+        );
+    }
+    for (j, y) in reference_grid_lines(config.world_height, spacing)
+        .into_iter()
+        .enumerate()
+    {
+        quads.push_rect(
+            config,
+            tile,
+            Vector2::new(0., y),
+            Vector2::new(config.world_width, y + line_width),
+            [0.5, 0.6, 0.8],
+            alpha(j),
+        );
+    }
+}
+
+// Draws the broad phase grid, shading cells by the number of collidables they hold.
+fn push_debug_grid(
+    quads: &mut QuadWriter,
+    config: &DisplayConfig,
+    tile: &Tile,
+    collision_detection_data: &CollisionDetectionData,
 ) {
-    let (image_num, suboptimal, acquire_future) =
-        match swapchain::acquire_next_image(graphics.swapchain.clone(), None) {
-            Ok(r) => r,
-            Err(AcquireError::OutOfDate) => {
-                // recreate_swapchain = true;
-                return;
-            }
-            Err(e) => panic!("Failed to acquire next image: {:?}", e),
+    let cell_size = collision_detection_data.cell_size();
+    for ((i, j), count) in collision_detection_data.occupied_cells() {
+        let min = Vector2::new(i as f64 * cell_size.x, j as f64 * cell_size.y);
+        quads.push_rect(
+            config,
+            tile,
+            min,
+            min + cell_size,
+            [1.0, 0.3, 0.1],
+            (count as f32 * 0.1).min(0.8),
+        );
+    }
+
+    // One pixel wide lines.
+    let (grid_width, grid_height) = collision_detection_data.grid_size();
+    let (x0, y0) = collision_detection_data.grid_origin();
+    let origin = Vector2::new(x0 as f64 * cell_size.x, y0 as f64 * cell_size.y);
+    let extent = Vector2::new(
+        grid_width as f64 * cell_size.x,
+        grid_height as f64 * cell_size.y,
+    );
+    let line_width = config.world_width / (config.width as f64 * tile.width as f64);
+    for i in 0..(grid_width + 1) {
+        let x = (x0 + i) as f64 * cell_size.x;
+        quads.push_rect(
+            config,
+            tile,
+            Vector2::new(x, origin.y),
+            Vector2::new(x + line_width, origin.y + extent.y),
+            [0.3, 0.3, 0.3],
+            0.5,
+        );
+    }
+    for j in 0..(grid_height + 1) {
+        let y = (y0 + j) as f64 * cell_size.y;
+        quads.push_rect(
+            config,
+            tile,
+            Vector2::new(origin.x, y),
+            Vector2::new(origin.x + extent.x, y + line_width),
+            [0.3, 0.3, 0.3],
+            0.5,
+        );
+    }
+}
+
+// Where a ball is after alpha of a step of time_delta, moving along its velocity. Smooths motion
+// drawn more often than the simulation steps.
+pub fn interpolated_position(ball: &Ball, time_delta: f64, alpha: f64) -> Vector2<f64> {
+    ball.position + ball.velocity * (alpha * time_delta)
+}
+
+// Appends the balls of a world, and the debug overlays, drawn into the given tile.
+pub fn push_scene<W: EntityStore>(
+    quads: &mut QuadWriter,
+    config: &DisplayConfig,
+    tile: &Tile,
+    world: &W,
+    simulation_data: &SimulationData,
+    collision_detection_data: &CollisionDetectionData,
+) {
+    // First, so everything else is drawn over it.
+    if let Some(spacing) = config.reference_grid {
+        push_reference_grid(quads, config, tile, spacing);
+    }
+    if config.debug_grid {
+        push_debug_grid(quads, config, tile, collision_detection_data);
+    }
+    push_wells(quads, config, tile, world);
+    let time_delta = simulation_data.next_time - simulation_data.time;
+    for (ball, trails) in <(&Ball, &Trails)>::query().iter(world) {
+        // Trails fade over the whole retained window, not only the current frame.
+        let window_start = trails
+            .trails
+            .iter()
+            .map(|trail| trail.initial_time)
+            .fold(simulation_data.time, f64::min);
+        let local_trails: Vec<Trail>;
+        // Blurred trails already show the motion over the whole step, and are drawn as they are.
+        let all_trails = if !config.blur {
+            let position = interpolated_position(ball, time_delta, config.interpolation_alpha);
+            local_trails = vec![Trail {
+                position0: position,
+                position1: position,
+                initial_time: simulation_data.time,
+                final_time: simulation_data.next_time,
+            }];
+            &local_trails
+        } else {
+            &trails.trails
         };
-    let clear_values = vec![[0.0, 0.0, 0.0, 0.0].into()];
-    let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
-        graphics.device.clone(),
-        graphics.queue.family(),
-    )
-    .unwrap();
+        for trail in all_trails {
+            push_capsule(
+                quads,
+                config,
+                tile,
+                trail.position0,
+                trail.position1,
+                ball.radius,
+                [ball.color[0], ball.color[1], ball.color[2]],
+                ((trail.final_time - trail.initial_time)
+                    / (simulation_data.next_time - window_start)) as f32,
+                true,
+            );
+        }
+    }
+    push_traces(quads, config, tile, world, time_delta);
+    if config.show_skipped_collisions && collision_detection_data.skipped().lost() > 0 {
+        // Over everything, in the far corner.
+        let size = SKIPPED_COLLISIONS_MARKER_SIZE * config.world_width;
+        quads.push_rect(
+            config,
+            tile,
+            Vector2::new(config.world_width - size, config.world_height - size),
+            Vector2::new(config.world_width, config.world_height),
+            [1.0, 0.1, 0.1],
+            0.8,
+        );
+    }
+}
 
-    // Wait for last render of that image to end.
-    graphics.previous_frame_ends[image_num].take().map(|res| {
-        res.then_signal_fence().wait(None).unwrap();
-    });
-    let vertex_buffer = &mut graphics.vertex_buffers[image_num];
-    let index_buffer = &mut graphics.index_buffers[image_num];
+// Disc of the given radius swept from position0 to position1, as drawn for trails.
+fn push_capsule(
+    quads: &mut QuadWriter,
+    config: &DisplayConfig,
+    tile: &Tile,
+    position0: Vector2<f64>,
+    position1: Vector2<f64>,
+    radius: f64,
+    color: [f32; 3],
+    total_portion: f32,
+    textured: bool,
+) -> bool {
+    let mut u_vec = position1 - position0;
+    let mut trail_length = u_vec.norm() / radius;
+    if u_vec.norm() < 0.001 {
+        // Too short to have a direction. Draw a plain disc, which the shader handles
+        // exactly when the length is zero.
+        u_vec = Vector2::new(1.0, 0.0);
+        trail_length = 0.;
+    } else {
+        u_vec /= u_vec.norm();
+    }
+    let v_vec = Vector2::new(-u_vec[1], u_vec[0]);
 
-    // Fill buffers.
-    {
-        let mut vertex_buffer_data = vertex_buffer.write().unwrap();
-        let mut index_buffer_data = index_buffer.write().unwrap();
-        let mut vertex_index = 0;
-        let mut index_index = 0;
-        for (ball, trails) in <(&Ball, &Trails)>::query().iter(world) {
-            let local_trails: Vec<Trail>;
-            let all_trails = if !graphics.config.blur {
-                local_trails = vec![Trail {
-                    position0: ball.position,
-                    position1: ball.position,
-                    initial_time: simulation_data.time,
-                    final_time: simulation_data.next_time,
-                }];
-                &local_trails
-            } else {
-                &trails.trails
+    let mut vertices = [Vertex::default(); 4];
+    let mut corner = 0;
+    for vo in [-1.1f64, 1.1].iter() {
+        for ho in [-1.1f64, trail_length + 1.1].iter() {
+            let position = position0 + (*vo * v_vec + *ho * u_vec) * radius;
+            vertices[corner] = Vertex {
+                position: to_screen(config, tile, &position),
+                coords: [*ho as f32, *vo as f32],
+                color: color,
+                trail_length: trail_length as f32,
+                total_portion: total_portion,
+                textured: if textured { 1. } else { 0. },
             };
-            for trail in all_trails {
-                let mut u_vec = trail.position1 - trail.position0;
-                let trail_length = u_vec.norm() / ball.radius;
-                if u_vec.norm() < 0.001 {
-                    u_vec = Vector2::new(1.0, 0.0);
-                } else {
-                    u_vec /= u_vec.norm();
-                }
-                let v_vec = Vector2::new(-u_vec[1], u_vec[0]);
-
-                index_buffer_data[index_index + 0] = (vertex_index) as u16;
-                index_buffer_data[index_index + 1] = (vertex_index + 1) as u16;
-                index_buffer_data[index_index + 2] = (vertex_index + 2) as u16;
-                index_buffer_data[index_index + 3] = (vertex_index + 2) as u16;
-                index_buffer_data[index_index + 4] = (vertex_index + 1) as u16;
-                index_buffer_data[index_index + 5] = (vertex_index + 3) as u16;
-                index_index += 6;
-
-                for vo in [-1.1f64, 1.1].iter() {
-                    for ho in [-1.1f64, trail_length + 1.1].iter() {
-                        let position = trail.position0 + (*vo * v_vec + *ho * u_vec) * ball.radius;
-                        vertex_buffer_data[vertex_index] = Vertex {
-                            position: [
-                                -1.0 + 2.0 * position[0] as f32 / graphics.config.width as f32,
-                                -1.0 + 2.0 * position[1] as f32 / graphics.config.height as f32,
-                            ],
-                            coords: [*ho as f32, *vo as f32],
-                            color: [ball.color[0], ball.color[1], ball.color[2]],
-                            trail_length: trail_length as f32,
-                            total_portion: ((trail.final_time - trail.initial_time)
-                                / (simulation_data.next_time - simulation_data.time))
-                                as f32,
-                        };
-                        vertex_index += 1;
-                    }
-                }
-            }
+            corner += 1;
         }
+    }
+    quads.push_quad(vertices)
+}
+
+// Radius of the marker of a gravity well, in world units.
+const WELL_MARKER_RADIUS: f64 = 6.;
+
+// Gravity wells as violet dots, under the balls.
+fn push_wells<W: EntityStore>(
+    quads: &mut QuadWriter,
+    config: &DisplayConfig,
+    tile: &Tile,
+    world: &W,
+) {
+    for well in <&Gravitational>::query().iter(world) {
+        push_capsule(
+            quads,
+            config,
+            tile,
+            well.position,
+            well.position,
+            WELL_MARKER_RADIUS,
+            [0.6, 0.3, 1.0],
+            1.,
+            false,
+        );
+    }
+}
+
+// Half width of debug lines, and radius of debug points, in world units.
+const DEBUG_LINE_RADIUS: f64 = 0.5;
+const DEBUG_POINT_RADIUS: f64 = 2.;
+// Debug circles are drawn as polygons with this many sides.
+const DEBUG_CIRCLE_SEGMENTS: usize = 24;
 
-        // Clear the rest of the index buffer;
-        while index_index < index_buffer_data.len() {
-            index_buffer_data[index_index] = 0;
-            index_index += 1;
+// Capsules, as (start, end, radius), that draw a debug primitive. Circles become polygons of
+// DEBUG_CIRCLE_SEGMENTS sides and points zero length capsules.
+fn debug_capsules(primitive: &DebugPrimitive) -> Vec<(Vector2<f64>, Vector2<f64>, f64)> {
+    match primitive {
+        DebugPrimitive::Line { a, b, .. } => vec![(*a, *b, DEBUG_LINE_RADIUS)],
+        DebugPrimitive::Circle { center, radius, .. } => {
+            let corner = |i: usize| {
+                let angle = std::f64::consts::TAU * i as f64 / DEBUG_CIRCLE_SEGMENTS as f64;
+                center + Vector2::new(angle.cos(), angle.sin()) * *radius
+            };
+            (0..DEBUG_CIRCLE_SEGMENTS)
+                .map(|i| (corner(i), corner(i + 1), DEBUG_LINE_RADIUS))
+                .collect()
         }
+        DebugPrimitive::Point { position, .. } => vec![(*position, *position, DEBUG_POINT_RADIUS)],
     }
+}
 
-    // Start rendering.
+// Shapes queued in DebugDraw, drawn like traces.
+pub fn push_debug_draw(
+    quads: &mut QuadWriter,
+    config: &DisplayConfig,
+    tile: &Tile,
+    debug_draw: &DebugDraw,
+) {
+    for primitive in debug_draw.primitives.iter() {
+        let color = match primitive {
+            DebugPrimitive::Line { color, .. }
+            | DebugPrimitive::Circle { color, .. }
+            | DebugPrimitive::Point { color, .. } => [color.x, color.y, color.z],
+        };
+        for (a, b, radius) in debug_capsules(primitive) {
+            push_capsule(quads, config, tile, a, b, radius, color, 1., false);
+        }
+    }
+}
+
+// Half width of the drawn path of the selected ball, in world units.
+const TRACE_RADIUS: f64 = 1.;
+
+// Draws the path of the selected ball, and the ball itself brightened on top.
+fn push_traces<W: EntityStore>(
+    quads: &mut QuadWriter,
+    config: &DisplayConfig,
+    tile: &Tile,
+    world: &W,
+    time_delta: f64,
+) {
+    for (ball, trace) in <(&Ball, &TraceHistory)>::query()
+        .filter(component::<Selected>())
+        .iter(world)
+    {
+        for segment in trace.points.windows(2) {
+            push_capsule(
+                quads,
+                config,
+                tile,
+                segment[0],
+                segment[1],
+                TRACE_RADIUS,
+                [1.0, 1.0, 1.0],
+                1.,
+                false,
+            );
+        }
+        let highlight = ball.color.map(|channel| 0.5 + 0.5 * channel);
+        let position = interpolated_position(ball, time_delta, config.interpolation_alpha);
+        push_capsule(
+            quads,
+            config,
+            tile,
+            position,
+            position,
+            ball.radius,
+            [highlight[0], highlight[1], highlight[2]],
+            1.,
+            false,
+        );
+    }
+}
+
+// Draws the balls, then blends the result.
+fn record_passes<I>(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    graphics: &Graphics,
+    image_num: usize,
+    vertex_buffer: Arc<dyn BufferAccess + Send + Sync>,
+    index_buffer: Arc<I>,
+    index_count: usize,
+) where
+    I: BufferAccess + TypedBufferAccess<Content = [u16]> + Send + Sync + 'static,
+{
+    let clear_values = vec![[0.0, 0.0, 0.0, 0.0].into()];
     builder
         .begin_render_pass(
             graphics.framebuffers[image_num].clone(),
             SubpassContents::Inline,
             clear_values,
         )
-        .unwrap()
-        .draw_indexed(
-            graphics.pipeline0.clone(),
-            &graphics.dynamic_state,
-            vec![vertex_buffer.clone()],
-            index_buffer.clone(),
-            (),
-            (),
-            vec![],
-        )
-        .unwrap()
+        .unwrap();
+    // Only the indices written this frame, the rest of the buffer holds stale data.
+    if index_count > 0 {
+        builder
+            .draw_indexed(
+                graphics.pipeline0.clone(),
+                &graphics.dynamic_state,
+                vec![vertex_buffer],
+                BufferSlice::from_typed_buffer_access(index_buffer)
+                    .slice(0..index_count)
+                    .unwrap(),
+                graphics.texture_set.iter().cloned().collect::<Vec<_>>(),
+                // Same layout in both ball shaders.
+                fs::ty::PushConstants {
+                    trail_opacity_curve: graphics.config.trail_opacity_curve,
+                    trail_fade: graphics.config.trail_fade,
+                },
+                vec![],
+            )
+            .unwrap();
+    }
+    builder
         .next_subpass(SubpassContents::Inline)
         .unwrap()
         .draw(
@@ -503,32 +1472,387 @@ pub fn render_balls(
         .unwrap()
         .end_render_pass()
         .unwrap();
+}
+
+// Renders and presents one frame, with quads added by fill. Several scenes can share a frame.
+// With grow_buffers, fill is called again after growing the buffers if its quads did not fit.
+pub fn render_frame(
+    graphics: &mut Graphics,
+    mut fill: impl FnMut(&mut QuadWriter, &DisplayConfig),
+) {
+    if !graphics.config.render_enabled {
+        // Nothing will be presented for a while, so finish the pending frames now. This way no
+        // fence is left behind to be waited on when rendering resumes.
+        for previous_frame_end in graphics.previous_frame_ends.iter_mut() {
+            if let Some(future) = previous_frame_end.take() {
+                future.then_signal_fence().wait(None).unwrap();
+            }
+        }
+        return;
+    }
+
+    // Offscreen, there is a single image.
+    let (image_num, acquire_future) = match &graphics.swapchain {
+        Some(swapchain) => match swapchain::acquire_next_image(swapchain.clone(), None) {
+            Ok((image_num, _, acquire_future)) => (image_num, Some(acquire_future)),
+            Err(AcquireError::OutOfDate) => {
+                // recreate_swapchain = true;
+                return;
+            }
+            Err(e) => panic!("Failed to acquire next image: {:?}", e),
+        },
+        None => (0, None),
+    };
+    let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+        graphics.device.clone(),
+        graphics.queue.family(),
+    )
+    .unwrap();
+
+    // Wait for the last frame that used the buffers of this slot to end.
+    let slot = graphics.frame_slot;
+    graphics.previous_frame_ends[slot].take().map(|res| {
+        res.then_signal_fence().wait(None).unwrap();
+    });
+
+    // Fill buffers. Only the used part is uploaded, and drawn.
+    let (vertex_count, index_count) = loop {
+        let (vertex_count, index_count, needed) = {
+            let mut quads = QuadWriter::new(&mut graphics.vertex_data, &mut graphics.index_data);
+            fill(&mut quads, &graphics.config);
+            (quads.vertex_index, quads.index_index, quads.needed())
+        };
+        match grown_capacity(graphics.vertex_data.len(), needed) {
+            Some(capacity) if graphics.config.grow_buffers => grow_quad_buffers(graphics, capacity),
+            _ => break (vertex_count, index_count),
+        }
+    };
+    let vertex_buffer = graphics.vertex_buffers[slot].clone();
+    let index_buffer = graphics.index_buffers[slot].clone();
+    vertex_buffer.write().unwrap()[..vertex_count]
+        .copy_from_slice(&graphics.vertex_data[..vertex_count]);
+    index_buffer.write().unwrap()[..index_count]
+        .copy_from_slice(&graphics.index_data[..index_count]);
+
+    match &graphics.staged_buffers {
+        Some(staged_buffers) => {
+            let (device_vertex_buffer, device_index_buffer) = staged_buffers[slot].clone();
+            builder
+                .copy_buffer(vertex_buffer, device_vertex_buffer.clone())
+                .unwrap()
+                .copy_buffer(index_buffer, device_index_buffer.clone())
+                .unwrap();
+            record_passes(
+                &mut builder,
+                graphics,
+                image_num,
+                device_vertex_buffer,
+                device_index_buffer,
+                index_count,
+            );
+        }
+        None => record_passes(
+            &mut builder,
+            graphics,
+            image_num,
+            vertex_buffer,
+            index_buffer,
+            index_count,
+        ),
+    }
+
+    if let Some(offscreen) = &graphics.offscreen {
+        builder
+            .copy_image_to_buffer(offscreen.image.clone(), offscreen.readback.clone())
+            .unwrap();
+    }
 
     // Finish building the command buffer by calling `build`.
     let command_buffer = builder.build().unwrap();
 
-    let future = sync::now(graphics.device.clone())
-        .join(acquire_future)
-        .then_execute(graphics.queue.clone(), command_buffer)
-        .unwrap()
-        .then_swapchain_present(
-            graphics.queue.clone(),
-            graphics.swapchain.clone(),
-            image_num,
-        )
-        .then_signal_fence_and_flush();
+    let future = match (&graphics.swapchain, acquire_future) {
+        (Some(swapchain), Some(acquire_future)) => sync::now(graphics.device.clone())
+            .join(acquire_future)
+            .then_execute(graphics.queue.clone(), command_buffer)
+            .unwrap()
+            .then_swapchain_present(graphics.queue.clone(), swapchain.clone(), image_num)
+            .then_signal_fence_and_flush()
+            .map(|future| future.boxed()),
+        _ => sync::now(graphics.device.clone())
+            .then_execute(graphics.queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .map(|future| future.boxed()),
+    };
 
     match future {
         Ok(future) => {
-            graphics.previous_frame_ends[image_num] = Some(future.boxed());
+            graphics.previous_frame_ends[slot] = Some(future);
         }
         Err(FlushError::OutOfDate) => {
             // recreate_swapchain = true;
-            graphics.previous_frame_ends[image_num] = None;
+            graphics.previous_frame_ends[slot] = None;
         }
         Err(e) => {
-            println!("Failed to flush future: {:?}", e);
-            graphics.previous_frame_ends[image_num] = None;
+            error!("Failed to flush future: {:?}", e);
+            graphics.previous_frame_ends[slot] = None;
+        }
+    }
+    graphics.frame_slot = next_frame_slot(slot, graphics.previous_frame_ends.len());
+}
+
+// Pixels of the last frame rendered offscreen, made opaque. None when rendering to a window.
+pub fn read_frame(graphics: &mut Graphics) -> Option<RgbaImage> {
+    let offscreen = graphics.offscreen.as_ref()?;
+    // All frames copy to the same buffer, so wait for every one of them.
+    for previous_frame_end in graphics.previous_frame_ends.iter_mut() {
+        if let Some(future) = previous_frame_end.take() {
+            future.then_signal_fence().wait(None).unwrap();
+        }
+    }
+    let pixels = offscreen.readback.read().unwrap().to_vec();
+    let mut image = RgbaImage::from_raw(graphics.config.width, graphics.config.height, pixels)?;
+    // Balls are blended over a black background, but the blend pass leaves partial alpha.
+    for pixel in image.pixels_mut() {
+        pixel[3] = 255;
+    }
+    Some(image)
+}
+
+// Draws the current state of a simulation into a new image of the given size, as render_balls
+// would, without a window. Thumbnails use the device of the graphics of the simulation, if it has
+// some, and its display settings.
+pub fn render_to_image(simulation: &Simulation, width: u32, height: u32) -> RgbaImage {
+    let graphics = simulation.resources.get::<Graphics>();
+    let display_config = thumbnail_config(
+        graphics.as_ref().map(|graphics| &graphics.config),
+        width,
+        height,
+    );
+    let mut thumbnail = match graphics {
+        Some(graphics) => offscreen_graphics(
+            display_config,
+            graphics.instance.clone(),
+            graphics.device.clone(),
+            graphics.queue.clone(),
+        ),
+        None => init_headless_graphics(display_config),
+    };
+    let simulation_data = simulation.resources.get::<SimulationData>().unwrap();
+    let collision_detection_data = simulation
+        .resources
+        .get::<CollisionDetectionData>()
+        .unwrap();
+    render_frame(&mut thumbnail, |quads, config| {
+        push_scene(
+            quads,
+            config,
+            &Tile::FULL,
+            &simulation.world,
+            &simulation_data,
+            &collision_detection_data,
+        )
+    });
+    read_frame(&mut thumbnail).unwrap()
+}
+
+// Settings of a thumbnail of the given size, the others from the display settings of the
+// simulation if it has some.
+fn thumbnail_config(config: Option<&DisplayConfig>, width: u32, height: u32) -> DisplayConfig {
+    DisplayConfig {
+        width,
+        height,
+        render_enabled: true,
+        // Single frame, waited for right away.
+        frames_in_flight: 1,
+        ..config.cloned().unwrap_or_default()
+    }
+}
+
+// Slots are used round robin, so a slot is reused only after all the others.
+fn next_frame_slot(slot: usize, frames_in_flight: usize) -> usize {
+    (slot + 1) % frames_in_flight
+}
+
+#[system]
+#[read_component(Ball)]
+#[read_component(Gravitational)]
+#[read_component(Selected)]
+#[read_component(TraceHistory)]
+#[read_component(Trails)]
+pub fn render_balls(
+    world: &mut SubWorld,
+    #[resource] graphics: &mut Graphics,
+    #[resource] simulation_data: &SimulationData,
+    #[resource] collision_detection_data: &CollisionDetectionData,
+    #[resource] debug_draw: &mut DebugDraw,
+    #[resource] perf_stats: &mut PerfStats,
+    #[resource] profile_data: &mut ProfileData,
+) {
+    let start = Instant::now();
+    perf_stats.record_frame();
+    render_frame(graphics, |quads, config| {
+        push_scene(
+            quads,
+            config,
+            &Tile::FULL,
+            world,
+            simulation_data,
+            collision_detection_data,
+        );
+        push_debug_draw(quads, config, &Tile::FULL, debug_draw);
+    });
+    debug_draw.clear();
+    profile_data.record(Stage::Render, start.elapsed());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_slots_are_used_round_robin() {
+        let mut slot = 0;
+        let slots = (0..7)
+            .map(|_| {
+                slot = next_frame_slot(slot, 3);
+                slot
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(slots, vec![1, 2, 0, 1, 2, 0, 1]);
+        // A single slot is always reused.
+        assert_eq!(next_frame_slot(0, 1), 0);
+    }
+
+    #[test]
+    fn six_indices_are_drawn_per_quad() {
+        let mut vertices = vec![Vertex::default(); 12];
+        let mut indices = vec![0u16; 16];
+        let mut quads = QuadWriter::new(&mut vertices, &mut indices);
+        assert!(quads.push_quad([Vertex::default(); 4]));
+        assert!(quads.push_quad([Vertex::default(); 4]));
+        assert_eq!((quads.vertex_index, quads.index_index), (8, 12));
+        // Room for the vertices of a third quad, but not its indices.
+        assert!(!quads.push_quad([Vertex::default(); 4]));
+        assert_eq!((quads.vertex_index, quads.index_index), (8, 12));
+        assert_eq!(quads.dropped, 1);
+        assert_eq!(indices[..12], [0, 1, 2, 2, 1, 3, 4, 5, 6, 6, 5, 7]);
+    }
+
+    #[test]
+    fn buffers_grow_to_fit_the_dropped_quads() {
+        let mut vertices = vec![Vertex::default(); 8];
+        let mut indices = vec![0u16; 8];
+        let mut quads = QuadWriter::new(&mut vertices, &mut indices);
+        for _ in 0..3 {
+            quads.push_quad([Vertex::default(); 4]);
         }
+        assert_eq!(quads.needed(), 18);
+        assert_eq!(grown_capacity(8, quads.needed()), Some(32));
+        // Enough room, no retry.
+        assert_eq!(grown_capacity(32, 18), None);
+        // Capped by the 16 bit indices, then never grown again.
+        assert_eq!(grown_capacity(60000, 70000), Some(MAX_GROWN_VERTICES));
+        assert_eq!(grown_capacity(MAX_GROWN_VERTICES, 70000), None);
+    }
+
+    #[test]
+    fn thumbnails_have_their_own_size() {
+        let config = thumbnail_config(None, 160, 80);
+        assert_eq!((config.width, config.height), (160, 80));
+        assert_eq!(config.frames_in_flight, 1);
+
+        // The world drawn and the look come from the window.
+        let window = DisplayConfig::builder()
+            .world_width(400.)
+            .world_height(200.)
+            .blur(false)
+            .render_enabled(false)
+            .build();
+        let config = thumbnail_config(Some(&window), 64, 32);
+        assert_eq!((config.width, config.height), (64, 32));
+        assert_eq!((config.world_width, config.world_height), (400., 200.));
+        assert!(!config.blur);
+        assert!(config.render_enabled);
+        assert_eq!(config.frames_in_flight, 1);
+    }
+
+    #[test]
+    fn halfway_between_steps_is_the_midpoint() {
+        let ball = Ball {
+            position: Vector2::new(10., 20.),
+            velocity: Vector2::new(30., -40.),
+            radius: 5.,
+            growth_rate: 0.,
+            initial_time: 0.,
+            color: Vector3::new(1., 1., 1.),
+            base_color: Vector3::new(1., 1., 1.),
+            heat: 0.,
+        };
+        let position = interpolated_position(&ball, 0.1, 0.5);
+        assert!(
+            (position - Vector2::new(11.5, 18.)).norm() < 1e-12,
+            "{}",
+            position
+        );
+        assert_eq!(interpolated_position(&ball, 0.1, 0.), ball.position);
+    }
+
+    #[test]
+    fn debug_primitives_are_drawn_as_capsules() {
+        let color = Vector3::new(1., 0., 0.);
+        let line = DebugPrimitive::Line {
+            a: Vector2::new(0., 0.),
+            b: Vector2::new(10., 0.),
+            color,
+        };
+        assert_eq!(
+            debug_capsules(&line),
+            vec![(
+                Vector2::new(0., 0.),
+                Vector2::new(10., 0.),
+                DEBUG_LINE_RADIUS
+            )]
+        );
+        let point = DebugPrimitive::Point {
+            position: Vector2::new(3., 4.),
+            color,
+        };
+        assert_eq!(
+            debug_capsules(&point),
+            vec![(
+                Vector2::new(3., 4.),
+                Vector2::new(3., 4.),
+                DEBUG_POINT_RADIUS
+            )]
+        );
+        let center = Vector2::new(50., 50.);
+        let circle = DebugPrimitive::Circle {
+            center,
+            radius: 10.,
+            color,
+        };
+        let capsules = debug_capsules(&circle);
+        assert_eq!(capsules.len(), DEBUG_CIRCLE_SEGMENTS);
+        // A closed polygon with its corners on the circle.
+        for (i, (a, b, radius)) in capsules.iter().enumerate() {
+            assert!(((a - center).norm() - 10.).abs() < 1e-9);
+            assert!((b - capsules[(i + 1) % capsules.len()].0).norm() < 1e-9);
+            assert_eq!(*radius, DEBUG_LINE_RADIUS);
+        }
+    }
+
+    #[test]
+    fn reference_grid_lines_include_both_ends_that_fit() {
+        assert_eq!(
+            reference_grid_lines(100., 25.),
+            vec![0., 25., 50., 75., 100.]
+        );
+        assert_eq!(reference_grid_lines(90., 25.), vec![0., 25., 50., 75.]);
+        // Only the axis when the spacing is larger than the world.
+        assert_eq!(reference_grid_lines(90., 200.), vec![0.]);
+        assert!(reference_grid_lines(100., 0.).is_empty());
+        assert!(reference_grid_lines(100., -5.).is_empty());
+        assert!(reference_grid_lines(100., f64::NAN).is_empty());
     }
 }
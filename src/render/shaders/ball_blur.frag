@@ -6,9 +6,18 @@ layout(location = 0) in vec2 coords;
 layout(location = 1) in vec3 color;
 layout(location = 2) in float trail_length;
 layout(location = 3) in float total_portion;
+// Only used by ball_texture.frag, which shares the vertex shader.
+layout(location = 4) in float textured;
 
 layout(location = 0) out vec4 f_color;
 
+layout(push_constant) uniform PushConstants {
+    // Exponent applied to the trail opacity. Below 1, faint trails of fast balls get brighter.
+    float trail_opacity_curve;
+    // Opacity lost at the start of the trail, fading linearly to none at its end.
+    float trail_fade;
+} push_constants;
+
 float correct_value(float val, float d){
     if (val - d < 0){
         return (val+d)/2;
@@ -37,6 +46,15 @@ void main() {
     seg = min(correct_value(seg, xwidth*0.5*aa_pixels), trail_length);
     float normalized_length = (seg+EPSILON)/(trail_length+EPSILON)*total_portion;
     float alpha = clamp(normalized_length, 0, 1);
+    if (trail_length < EPSILON) {
+        // Stationary: the whole disc is lit for the full duration, edges come from 'factor' only.
+        alpha = clamp(total_portion, 0, 1);
+    }
+    alpha = pow(alpha, push_constants.trail_opacity_curve);
+    if (trail_length >= EPSILON) {
+        float along = clamp(coords.x / trail_length, 0, 1);
+        alpha *= 1 - push_constants.trail_fade * (1 - along);
+    }
     // alpha=seg;
 
     float ex = coords.x-clamp(coords.x, 0, trail_length);
@@ -0,0 +1,70 @@
+#version 450
+const float EPSILON = 0.0001;
+const float aa_pixels = 2.;
+
+layout(location = 0) in vec2 coords;
+layout(location = 1) in vec3 color;
+layout(location = 2) in float trail_length;
+layout(location = 3) in float total_portion;
+// 1 for balls, which show the texture, 0 for overlays, which keep their color.
+layout(location = 4) in float textured;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D ball_texture;
+
+layout(push_constant) uniform PushConstants {
+    // Exponent applied to the trail opacity. Below 1, faint trails of fast balls get brighter.
+    float trail_opacity_curve;
+    // Opacity lost at the start of the trail, fading linearly to none at its end.
+    float trail_fade;
+} push_constants;
+
+float correct_value(float val, float d){
+    if (val - d < 0){
+        return (val+d)/2;
+    }
+    return val;
+}
+
+void main() {
+    // The goal of anti aliasing is estimating the lit area, and sampling the color at the middle of
+    // that area (i.e. average color).
+    // For the second bit, we make sure to correct the values sampled.
+    // For the first, we compute the area with the 'factor' logic.
+    
+    // In our case, the color is seg.
+    // At the top/bottom, 
+    float d2 = 1-coords.y*coords.y;
+    float dwidth = length(vec2(dFdx(d2), dFdy(d2)));
+    d2 = correct_value(d2, dwidth*0.5*aa_pixels);
+
+    float d = sqrt(max(0,d2));
+    float t0 = max(0, coords.x-d);
+    float t1 = min(trail_length, coords.x+d);
+    // Note that seg reaches negative value at the sides.
+    float seg = t1 - t0;
+    float xwidth = length(vec2(dFdx(coords.x), dFdy(coords.x)));
+    seg = min(correct_value(seg, xwidth*0.5*aa_pixels), trail_length);
+    float normalized_length = (seg+EPSILON)/(trail_length+EPSILON)*total_portion;
+    float alpha = clamp(normalized_length, 0, 1);
+    if (trail_length < EPSILON) {
+        // Stationary: the whole disc is lit for the full duration, edges come from 'factor' only.
+        alpha = clamp(total_portion, 0, 1);
+    }
+    alpha = pow(alpha, push_constants.trail_opacity_curve);
+    if (trail_length >= EPSILON) {
+        float along = clamp(coords.x / trail_length, 0, 1);
+        alpha *= 1 - push_constants.trail_fade * (1 - along);
+    }
+    // alpha=seg;
+
+    float ex = coords.x-clamp(coords.x, 0, trail_length);
+    float dist = sqrt(ex*ex + coords.y*coords.y);
+    float pwidth = length(vec2(dFdx(dist), dFdy(dist)));
+    float factor = smoothstep(-0.5*aa_pixels, 0.5*aa_pixels, (1-dist)/pwidth);
+    alpha *= factor;
+    // The texture covers the disc at the closest end of the trail, and stretches in between.
+    vec4 texel = texture(ball_texture, (vec2(ex, coords.y) + 1) / 2);
+    f_color = vec4(mix(color, texel.rgb, textured), alpha * mix(1, texel.a, textured));
+}
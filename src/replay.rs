@@ -0,0 +1,243 @@
+use log::{error, info};
+use nalgebra::{Vector2, Vector3};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    ball::Ball,
+    collision::PhysicsParameter,
+    lifecycle::Lifecycle,
+    shutdown::OnShutdown,
+    simulation::{adjust_physics_parameter, adjust_simulation_speed, Simulation, SimulationData},
+    spawn::spawn_ball,
+};
+
+// Runtime inputs that change the simulation. Anything that affects physics must go through here,
+// otherwise replays diverge.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    AdjustSpeed {
+        factor: f64,
+    },
+    AdjustPhysics {
+        parameter: PhysicsParameter,
+        delta: f64,
+    },
+    SpawnBall {
+        position: Vector2<f64>,
+        velocity: Vector2<f64>,
+        radius: f64,
+        color: Vector3<f32>,
+    },
+    // See Simulation::reverse.
+    Reverse,
+    // Steps to the next collision, see Simulation::step_to_next_collision.
+    StepToCollision,
+    // Back to the state the simulation started in.
+    Reset,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordedEvent {
+    pub time: f64,
+    pub event: InputEvent,
+}
+
+// Applies an input event, recording it if a Recorder resource is present.
+pub fn dispatch_input(simulation: &mut Simulation, event: InputEvent) {
+    let time = simulation
+        .resources
+        .get::<SimulationData>()
+        .unwrap()
+        .absolute_time();
+    if let Some(mut recorder) = simulation.resources.get_mut::<Recorder>() {
+        if let Err(e) = recorder.record(time, &event) {
+            error!("Failed to record input event: {}", e);
+        }
+    }
+    apply_input_event(simulation, &event);
+}
+
+// Call between steps. StepToCollision steps the simulation itself.
+pub fn apply_input_event(simulation: &mut Simulation, event: &InputEvent) {
+    let resources = &mut simulation.resources;
+    match *event {
+        InputEvent::AdjustSpeed { factor } => adjust_simulation_speed(resources, factor),
+        InputEvent::AdjustPhysics { parameter, delta } => {
+            adjust_physics_parameter(resources, parameter, delta)
+        }
+        InputEvent::SpawnBall {
+            position,
+            velocity,
+            radius,
+            color,
+        } => {
+            spawn_ball(
+                &mut resources.get_mut::<Lifecycle>().unwrap(),
+                Ball {
+                    position: position,
+                    velocity: velocity,
+                    radius: radius,
+                    growth_rate: 0.,
+                    initial_time: 0.,
+                    color: color,
+                    base_color: color,
+                    heat: 0.,
+                },
+            );
+        }
+        InputEvent::Reverse => simulation.reverse(),
+        InputEvent::StepToCollision => match simulation.step_to_next_collision() {
+            Some(time) => info!("Stepped to collision at {}", time),
+            None => info!("No collision in this frame"),
+        },
+        InputEvent::Reset => simulation.reset(),
+    }
+}
+
+// One event per line: the simulation time, the event kind and its arguments.
+// f64 display is round trip exact, so replayed times compare equal to recorded ones.
+fn format_event(recorded: &RecordedEvent) -> String {
+    match recorded.event {
+        InputEvent::AdjustSpeed { factor } => format!("{} speed {}", recorded.time, factor),
+        InputEvent::AdjustPhysics { parameter, delta } => {
+            format!("{} physics {} {}", recorded.time, parameter.name(), delta)
+        }
+        InputEvent::SpawnBall {
+            position,
+            velocity,
+            radius,
+            color,
+        } => format!(
+            "{} spawn {} {} {} {} {} {} {} {}",
+            recorded.time,
+            position.x,
+            position.y,
+            velocity.x,
+            velocity.y,
+            radius,
+            color.x,
+            color.y,
+            color.z
+        ),
+        InputEvent::Reverse => format!("{} reverse", recorded.time),
+        InputEvent::StepToCollision => format!("{} step_to_collision", recorded.time),
+        InputEvent::Reset => format!("{} reset", recorded.time),
+    }
+}
+
+fn parse_event(line: &str) -> Option<RecordedEvent> {
+    let mut tokens = line.split_whitespace();
+    let time = tokens.next()?.parse::<f64>().ok()?;
+    let kind = tokens.next()?;
+    if kind == "physics" {
+        let parameter = PhysicsParameter::from_name(tokens.next()?)?;
+        let delta = tokens.next()?.parse::<f64>().ok()?;
+        if tokens.next().is_some() {
+            return None;
+        }
+        let event = InputEvent::AdjustPhysics { parameter, delta };
+        return Some(RecordedEvent { time, event });
+    }
+    let args = tokens
+        .map(|token| token.parse::<f64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let event = match (kind, args.as_slice()) {
+        ("speed", [factor]) => InputEvent::AdjustSpeed { factor: *factor },
+        ("spawn", [x, y, vx, vy, radius, r, g, b]) => InputEvent::SpawnBall {
+            position: Vector2::new(*x, *y),
+            velocity: Vector2::new(*vx, *vy),
+            radius: *radius,
+            color: Vector3::new(*r as f32, *g as f32, *b as f32),
+        },
+        ("reverse", []) => InputEvent::Reverse,
+        ("step_to_collision", []) => InputEvent::StepToCollision,
+        ("reset", []) => InputEvent::Reset,
+        _ => return None,
+    };
+    Some(RecordedEvent { time, event })
+}
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder {
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn record(&mut self, time: f64, event: &InputEvent) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{}",
+            format_event(&RecordedEvent {
+                time,
+                event: *event,
+            })
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl OnShutdown for Recorder {
+    fn on_shutdown(&mut self) {
+        if let Err(e) = self.flush() {
+            error!("Failed to flush recording: {}", e);
+        }
+    }
+}
+
+pub struct Replayer {
+    events: VecDeque<RecordedEvent>,
+}
+
+impl Replayer {
+    pub fn load(path: &Path) -> io::Result<Replayer> {
+        let mut events = VecDeque::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            events.push_back(parse_event(&line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid recorded event '{}'", line),
+                )
+            })?);
+        }
+        Ok(Replayer { events })
+    }
+
+    // Applies every event recorded at or before the current simulation time.
+    // Call between schedule executions, where the events were recorded. The time is checked again
+    // after each event, since stepping to a collision or resetting changes it.
+    pub fn apply_due(&mut self, simulation: &mut Simulation) {
+        while let Some(recorded) = self.events.front() {
+            let time = simulation
+                .resources
+                .get::<SimulationData>()
+                .unwrap()
+                .absolute_time();
+            if recorded.time > time {
+                break;
+            }
+            let recorded = self.events.pop_front().unwrap();
+            apply_input_event(simulation, &recorded.event);
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
@@ -0,0 +1,46 @@
+use legion::*;
+use log::error;
+
+use crate::{
+    ball::{Ball, BallId},
+    lifecycle::Lifecycle,
+};
+
+// What to do with a ball found with non finite state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SanitizeAction {
+    Despawn,
+    // Stop the ball where it is. Balls without a valid position or radius are despawned.
+    Stop,
+}
+
+fn is_finite(ball: &Ball) -> bool {
+    ball.position.iter().all(|x| x.is_finite())
+        && ball.velocity.iter().all(|x| x.is_finite())
+        && ball.radius.is_finite()
+        && ball.growth_rate.is_finite()
+        && ball.initial_time.is_finite()
+}
+
+// Debug check that keeps NaNs from spreading to every ball touching a broken one.
+#[system(for_each)]
+pub fn sanitize_balls(
+    id: &BallId,
+    ball: &mut Ball,
+    #[resource] lifecycle: &mut Lifecycle,
+    #[resource] action: &SanitizeAction,
+) {
+    if is_finite(ball) {
+        return;
+    }
+    error!("Non finite ball {:?}: {:?}", id, ball);
+    let salvageable = ball.position.iter().all(|x| x.is_finite())
+        && ball.radius.is_finite()
+        && ball.initial_time.is_finite();
+    if *action == SanitizeAction::Stop && salvageable {
+        ball.velocity.fill(0.);
+        ball.growth_rate = 0.;
+    } else {
+        lifecycle.despawn(*id);
+    }
+}
@@ -0,0 +1,37 @@
+use legion::{systems::Resource, Resources};
+
+// Resources holding buffered output implement this to get it out before the process exits.
+pub trait OnShutdown {
+    fn on_shutdown(&mut self);
+}
+
+#[derive(Default)]
+pub struct ShutdownHooks {
+    hooks: Vec<fn(&mut Resources)>,
+}
+
+fn call_on_shutdown<T: Resource + OnShutdown>(resources: &mut Resources) {
+    if let Some(mut resource) = resources.get_mut::<T>() {
+        resource.on_shutdown();
+    }
+}
+
+// Makes run_shutdown_hooks call on_shutdown of the resource of type T, if present at that point.
+pub fn register_shutdown<T: Resource + OnShutdown>(resources: &mut Resources) {
+    if !resources.contains::<ShutdownHooks>() {
+        resources.insert(ShutdownHooks::default());
+    }
+    resources
+        .get_mut::<ShutdownHooks>()
+        .unwrap()
+        .hooks
+        .push(call_on_shutdown::<T>);
+}
+
+pub fn run_shutdown_hooks(resources: &mut Resources) {
+    if let Some(shutdown_hooks) = resources.remove::<ShutdownHooks>() {
+        for hook in shutdown_hooks.hooks {
+            hook(resources);
+        }
+    }
+}
@@ -1,18 +1,188 @@
-use legion::*;
-use log::info;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use fnv::{FnvHashMap, FnvHashSet};
+use legion::{
+    query::component,
+    storage::Component,
+    world::{Entry, SubWorld},
+    *,
+};
+use log::{info, warn};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-const FRAME_TIME_CAP: i64 = 16;
+use crate::{
+    ball::{
+        Ball, BallId, CollisionCount, Kinematic, LastImpulse, Pinned, Spin, Trails, POINT_RADIUS,
+    },
+    collision::{
+        collidable::{CollisionFilter, Generation, PhysicsConfig, PhysicsParameter},
+        collision_system, CollisionDetectionData, SkippedCollisions,
+    },
+    debug_draw::DebugDraw,
+    emitter::Emitter,
+    integrator::IntegratorKind,
+    lifecycle::Lifecycle,
+    lifetime::Lifetime,
+    profile::ProfileData,
+    spawn::ball_components,
+};
+
+// Shortest duration of a frame with cap_frame_time.
+pub const FRAME_TIME_CAP: Duration = Duration::from_millis(16);
+// Tolerance of comparisons between times, which are sums of time deltas. Unrelated to the
+// distance epsilon of PhysicsConfig.
+const TIME_EPSILON: f64 = 1e-9;
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct SimulationData {
     pub time: f64,
     pub next_time: f64,
     pub last_simulated: i64,
+    // Multiplies time_delta for the next frames. For effects, separate from the speed setting.
+    pub time_scale: f64,
+    // Time removed from all times by rebase_time. time + time_offset is the time since the start.
+    pub time_offset: f64,
+}
+
+impl SimulationData {
+    // Current time, unaffected by rebasing. For anything kept outside the world, like recordings.
+    pub fn absolute_time(&self) -> f64 {
+        self.time + self.time_offset
+    }
 }
 
+// How the duration of each step is chosen.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "config_file", derive(serde::Deserialize))]
+pub enum DtPolicy {
+    // Always time_delta.
+    Fixed,
+    // Shorter than time_delta when needed, so that the fastest ball moves at most this fraction of
+    // the smallest radius in a step. Keeps fast balls from skipping over thin objects.
+    Adaptive { max_displacement_fraction: f64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "config_file", derive(serde::Deserialize), serde(default))]
 pub struct SimulationConfig {
+    // Duration of a step. The longest one with an adaptive dt_policy.
     pub time_delta: f64,
+    pub dt_policy: DtPolicy,
+    // Frames take at least FRAME_TIME_CAP. The event loop waits for it, see
+    // Simulation::frame_deadline.
+    pub cap_frame_time: bool,
+    // How accelerations move balls.
+    pub integrator: IntegratorKind,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> SimulationConfig {
+        SimulationConfig {
+            time_delta: 0.1,
+            dt_policy: DtPolicy::Fixed,
+            cap_frame_time: true,
+            integrator: IntegratorKind::AnalyticLinear,
+        }
+    }
+}
+
+impl SimulationConfig {
+    /// Starts from the defaults, fields that are not set keep them.
+    ///
+    /// ```
+    /// use balls::simulation::{DtPolicy, SimulationConfig};
+    ///
+    /// let config = SimulationConfig::builder().time_delta(0.05).build();
+    /// assert_eq!(config.time_delta, 0.05);
+    /// assert_eq!(config.dt_policy, DtPolicy::Fixed);
+    /// assert!(config.cap_frame_time);
+    /// ```
+    pub fn builder() -> SimulationConfigBuilder {
+        SimulationConfigBuilder {
+            config: SimulationConfig::default(),
+        }
+    }
+}
+
+// Sets some fields of a SimulationConfig, the others keep their defaults.
+pub struct SimulationConfigBuilder {
+    config: SimulationConfig,
+}
+
+impl SimulationConfigBuilder {
+    pub fn time_delta(mut self, time_delta: f64) -> Self {
+        self.config.time_delta = time_delta;
+        self
+    }
+
+    pub fn dt_policy(mut self, dt_policy: DtPolicy) -> Self {
+        self.config.dt_policy = dt_policy;
+        self
+    }
+
+    pub fn cap_frame_time(mut self, cap_frame_time: bool) -> Self {
+        self.config.cap_frame_time = cap_frame_time;
+        self
+    }
+
+    pub fn integrator(mut self, integrator: IntegratorKind) -> Self {
+        self.config.integrator = integrator;
+        self
+    }
+
+    pub fn build(self) -> SimulationConfig {
+        self.config
+    }
+}
+
+// Frames averaged in PerfStats.
+const PERF_WINDOW: usize = 60;
+// Real time over which steps are counted in PerfStats.
+const STEP_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// Measured performance. Frames are counted when rendered, steps when the schedule advances time,
+// which differ when frames are interpolated between steps or nothing is rendered.
+#[derive(Clone, Debug, Default)]
+pub struct PerfStats {
+    // Average real time between frames, in seconds, over the last PERF_WINDOW frames.
+    pub frame_time: f64,
+    // Steps executed over the last STEP_RATE_WINDOW, per second.
+    pub steps_per_second: f64,
+    pub collisions_last_frame: usize,
+    pub skipped_collisions: SkippedCollisions,
+    frame_times: VecDeque<f64>,
+    last_frame: Option<Instant>,
+    step_times: VecDeque<Instant>,
+}
+
+impl PerfStats {
+    pub fn record_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame {
+            self.frame_times
+                .push_back(now.duration_since(last_frame).as_secs_f64());
+            if self.frame_times.len() > PERF_WINDOW {
+                self.frame_times.pop_front();
+            }
+            self.frame_time = self.frame_times.iter().sum::<f64>() / self.frame_times.len() as f64;
+        }
+        self.last_frame = Some(now);
+    }
+
+    fn record_step(&mut self) {
+        let now = Instant::now();
+        self.step_times.push_back(now);
+        while now.duration_since(self.step_times[0]) > STEP_RATE_WINDOW {
+            self.step_times.pop_front();
+        }
+        // Steps after the first one, over the time since it.
+        let span = now.duration_since(self.step_times[0]).as_secs_f64();
+        self.steps_per_second = if span > 0. {
+            (self.step_times.len() - 1) as f64 / span
+        } else {
+            0.
+        };
+    }
 }
 
 pub fn init_simulation(resources: &mut Resources, simulation_config: SimulationConfig) {
@@ -23,17 +193,57 @@ pub fn init_simulation(resources: &mut Resources, simulation_config: SimulationC
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_millis() as i64,
+        time_scale: 1.,
+        time_offset: 0.,
     });
     resources.insert(simulation_config);
+    // Replaced by inserting another PhysicsConfig.
+    resources.insert(PhysicsConfig::default());
+    resources.insert(PerfStats::default());
+    resources.insert(ProfileData::default());
+    resources.insert(DebugDraw::default());
+    resources.insert(Lifecycle::default());
+}
+
+// Longest step in which no ball moves more than max_displacement_fraction of the smallest radius,
+// capped at time_delta. Points count with POINT_RADIUS, so they do not stall the simulation.
+pub fn adaptive_time_delta<W: EntityStore>(
+    world: &W,
+    time_delta: f64,
+    max_displacement_fraction: f64,
+) -> f64 {
+    let (max_speed, min_radius) = <&Ball>::query().iter(world).fold(
+        (0., f64::INFINITY),
+        |(max_speed, min_radius): (f64, f64), ball| {
+            (
+                max_speed.max(ball.velocity.norm()),
+                min_radius.min(ball.radius.max(POINT_RADIUS)),
+            )
+        },
+    );
+    if max_speed == 0. {
+        return time_delta;
+    }
+    time_delta.min(max_displacement_fraction * min_radius / max_speed)
 }
 
 #[system]
+#[read_component(Ball)]
 pub fn advance_time(
+    world: &mut SubWorld,
     #[resource] simulation_data: &mut SimulationData,
     #[resource] simulation_config: &SimulationConfig,
+    #[resource] perf_stats: &mut PerfStats,
+    #[resource] profile_data: &mut ProfileData,
 ) {
+    let time_delta = simulation_config.time_delta * simulation_data.time_scale;
     simulation_data.time = simulation_data.next_time;
-    simulation_data.next_time += simulation_config.time_delta;
+    simulation_data.next_time += match simulation_config.dt_policy {
+        DtPolicy::Fixed => time_delta,
+        DtPolicy::Adaptive {
+            max_displacement_fraction,
+        } => adaptive_time_delta(world, time_delta, max_displacement_fraction),
+    };
     let current_time = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
@@ -42,15 +252,351 @@ pub fn advance_time(
         "Frame time: {}",
         current_time - simulation_data.last_simulated
     );
-    let ms_to_sleep = std::cmp::max(
-        0,
-        FRAME_TIME_CAP - (current_time - simulation_data.last_simulated),
-    ) as u64;
-    std::thread::sleep(Duration::from_millis(ms_to_sleep));
-    simulation_data.last_simulated = current_time + (ms_to_sleep as i64);
+    simulation_data.last_simulated = current_time;
+    perf_stats.record_step();
+    profile_data.log_periodically();
+}
+
+// Shifts every time in the world so the current time becomes zero. Balls move as
+// position + velocity * (t - initial_time), and only such differences matter, so nothing else
+// changes. Keeps times small, where subtractions of large times in the solvers lose precision.
+// Must run between frames, when the only queued collisions are the deferred ones.
+pub fn rebase_time<W: EntityStore>(
+    world: &mut W,
+    simulation_data: &mut SimulationData,
+    collision_detection_data: &mut CollisionDetectionData,
+) {
+    let shift = simulation_data.time;
+    for ball in <&mut Ball>::query().iter_mut(world) {
+        ball.initial_time -= shift;
+    }
+    for last_impulse in <&mut LastImpulse>::query().iter_mut(world) {
+        last_impulse.time -= shift;
+    }
+    for trails in <&mut Trails>::query().iter_mut(world) {
+        for trail in trails.trails.iter_mut() {
+            trail.initial_time -= shift;
+            trail.final_time -= shift;
+        }
+    }
+    for lifetime in <&mut Lifetime>::query().iter_mut(world) {
+        lifetime.spawn_time -= shift;
+    }
+    for emitter in <&mut Emitter>::query().iter_mut(world) {
+        emitter.next_spawn_time -= shift;
+    }
+    collision_detection_data.shift_times(shift);
+    simulation_data.time -= shift;
+    simulation_data.next_time -= shift;
+    simulation_data.time_offset += shift;
+}
+
+// Rebases time every interval frames. Runs after advance_time.
+#[system]
+#[write_component(Ball)]
+#[write_component(Emitter)]
+#[write_component(LastImpulse)]
+#[write_component(Lifetime)]
+#[write_component(Trails)]
+pub fn rebase_time_periodically(
+    world: &mut SubWorld,
+    #[state] interval: &usize,
+    #[state] frames: &mut usize,
+    #[resource] simulation_data: &mut SimulationData,
+    #[resource] collision_detection_data: &mut CollisionDetectionData,
+) {
+    *frames += 1;
+    if *frames >= *interval {
+        *frames = 0;
+        rebase_time(world, simulation_data, collision_detection_data);
+    }
+}
+
+// Sets the number of threads of the global rayon pool, which runs the systems of schedules and the
+// par_for_each queries. 0 uses one per core, the default. With one thread, systems and balls are
+// processed in a fixed order, for deterministic debugging. Call before executing any schedule.
+pub fn init_thread_pool(threads: usize) {
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        warn!("Failed to set up {} worker threads: {}", threads, e);
+    }
 }
 
 pub fn adjust_simulation_speed(resources: &mut Resources, factor: f64) {
     let mut simulation_config = resources.get_mut::<SimulationConfig>().unwrap();
     simulation_config.time_delta *= factor;
 }
+
+pub fn adjust_physics_parameter(
+    resources: &mut Resources,
+    parameter: PhysicsParameter,
+    delta: f64,
+) {
+    let value = resources
+        .get_mut::<PhysicsConfig>()
+        .unwrap()
+        .adjust(parameter, delta);
+    info!("{} set to {}", parameter.name(), value);
+}
+
+// A world together with its resources and the schedule that advances it.
+pub struct Simulation {
+    pub world: World,
+    pub resources: Resources,
+    pub schedule: Schedule,
+    // State when created, restored by reset.
+    initial_snapshot: WorldSnapshot,
+}
+
+#[derive(Clone, Debug)]
+struct BallSnapshot {
+    entity: Entity,
+    id: BallId,
+    ball: Ball,
+    trails: Trails,
+    generation: Generation,
+    collision_count: CollisionCount,
+    last_impulse: LastImpulse,
+    spin: Spin,
+    lifetime: Option<Lifetime>,
+    collision_filter: Option<CollisionFilter>,
+    kinematic: bool,
+    pinned: bool,
+}
+
+// In memory copy of the dynamic state of a simulation.
+#[derive(Clone)]
+pub struct WorldSnapshot {
+    balls: Vec<BallSnapshot>,
+    simulation_data: SimulationData,
+    simulation_config: SimulationConfig,
+    // None without collision detection.
+    collision_detection_data: Option<CollisionDetectionData>,
+}
+
+impl Simulation {
+    // The resources must include those of init_simulation.
+    pub fn new(world: World, resources: Resources, schedule: Schedule) -> Simulation {
+        let initial_snapshot = take_snapshot(&world, &resources);
+        Simulation {
+            world,
+            resources,
+            schedule,
+            initial_snapshot,
+        }
+    }
+
+    pub fn step(&mut self) {
+        self.schedule.execute(&mut self.world, &mut self.resources);
+    }
+
+    // When the frame after one started at last_frame may start, if frames are capped. Waiting
+    // for it in the event loop, rather than sleeping in the schedule, keeps handling input.
+    pub fn frame_deadline(&self, last_frame: Instant) -> Option<Instant> {
+        if self
+            .resources
+            .get::<SimulationConfig>()
+            .unwrap()
+            .cap_frame_time
+        {
+            Some(last_frame + FRAME_TIME_CAP)
+        } else {
+            None
+        }
+    }
+
+    fn time(&self) -> f64 {
+        self.resources
+            .get::<SimulationData>()
+            .unwrap()
+            .absolute_time()
+    }
+
+    // Steps until the simulation time advanced by the given duration, and returns the number of
+    // steps. Checks the time itself, so changes of time_delta while running are accounted for.
+    pub fn run_for(&mut self, seconds: f64) -> usize {
+        let target = self.time() + seconds;
+        let mut steps = 0;
+        // Times are sums of time deltas, allow for their rounding errors.
+        while self.time() < target - TIME_EPSILON {
+            let time = self.time();
+            self.step();
+            steps += 1;
+            // A zero time_delta or time_scale would keep the loop spinning.
+            if self.time() <= time {
+                warn!(
+                    "Simulation time stopped at {}, stopping run_for early",
+                    time
+                );
+                break;
+            }
+        }
+        steps
+    }
+
+    // Steps until every ball moves slower than speed_threshold, or for at most max_steps, and
+    // returns whether it settled. Balls only come to rest if energy is lost, through drag, a
+    // restitution below 1 or wall friction. Kinematic balls never slow down and are not counted.
+    pub fn run_until_settled(&mut self, max_steps: usize, speed_threshold: f64) -> bool {
+        for _ in 0..max_steps {
+            if self.max_speed() < speed_threshold {
+                return true;
+            }
+            self.step();
+        }
+        self.max_speed() < speed_threshold
+    }
+
+    fn max_speed(&self) -> f64 {
+        <&Ball>::query()
+            .filter(!component::<Kinematic>())
+            .iter(&self.world)
+            .map(|ball| ball.velocity.norm())
+            .fold(0., f64::max)
+    }
+
+    // Time of the first collision of the coming frame, from a broad phase run without resolving
+    // anything.
+    pub fn next_collision_time(&mut self) -> Option<f64> {
+        let mut broad_phase = Schedule::builder().add_system(collision_system()).build();
+        broad_phase.execute(&mut self.world, &mut self.resources);
+        self.resources
+            .get::<CollisionDetectionData>()
+            .unwrap()
+            .peek_next_collision_time()
+    }
+
+    // Steps a frame cut short to end at its first collision, and returns the time of that
+    // collision. Steps a full frame and returns None if there is none in it.
+    pub fn step_to_next_collision(&mut self) -> Option<f64> {
+        let collision_time = self.next_collision_time();
+        if let Some(collision_time) = collision_time {
+            let mut simulation_data = self.resources.get_mut::<SimulationData>().unwrap();
+            simulation_data.next_time = collision_time.max(simulation_data.time);
+        }
+        self.step();
+        collision_time
+    }
+
+    // Negates the velocity, growth and spin of every ball, so running on retraces their paths.
+    // Elastic collisions are time symmetric, and time itself keeps moving forward, so the solvers
+    // need no change. Must be called between steps, when all balls are advanced to the current
+    // time.
+    pub fn reverse(&mut self) {
+        for ball in <&mut Ball>::query().iter_mut(&mut self.world) {
+            ball.velocity = -ball.velocity;
+            ball.growth_rate = -ball.growth_rate;
+        }
+        for spin in <&mut Spin>::query().iter_mut(&mut self.world) {
+            spin.angular_velocity = -spin.angular_velocity;
+        }
+    }
+
+    pub fn snapshot(&self) -> WorldSnapshot {
+        take_snapshot(&self.world, &self.resources)
+    }
+
+    // Restores the state the simulation was created with.
+    pub fn reset(&mut self) {
+        let initial_snapshot = self.initial_snapshot.clone();
+        self.restore(&initial_snapshot);
+    }
+
+    // Balls are matched by id. Those despawned since the snapshot come back as new entities, and
+    // the collisions deferred to the next frame follow them.
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        let current_balls = <(Entity, &BallId)>::query()
+            .iter(&self.world)
+            .map(|(entity, id)| (*id, *entity))
+            .collect::<FnvHashMap<_, _>>();
+        let snapshot_ids = snapshot
+            .balls
+            .iter()
+            .map(|ball| ball.id)
+            .collect::<FnvHashSet<_>>();
+        // Balls spawned after the snapshot was taken.
+        for (id, entity) in current_balls.iter() {
+            if !snapshot_ids.contains(id) {
+                self.world.remove(*entity);
+            }
+        }
+
+        let mut replaced_entities = FnvHashMap::default();
+        for ball in snapshot.balls.iter() {
+            let entity = match current_balls.get(&ball.id) {
+                Some(entity) => *entity,
+                None => self.world.push(ball_components(ball.ball, ball.id)),
+            };
+            if entity != ball.entity {
+                replaced_entities.insert(ball.entity, entity);
+            }
+            let mut entry = self.world.entry(entity).unwrap();
+            *entry.get_component_mut::<Ball>().unwrap() = ball.ball;
+            *entry.get_component_mut::<Trails>().unwrap() = ball.trails.clone();
+            *entry.get_component_mut::<Generation>().unwrap() = ball.generation;
+            *entry.get_component_mut::<CollisionCount>().unwrap() = ball.collision_count;
+            *entry.get_component_mut::<LastImpulse>().unwrap() = ball.last_impulse;
+            *entry.get_component_mut::<Spin>().unwrap() = ball.spin;
+            set_component(&mut entry, ball.lifetime);
+            set_component(&mut entry, ball.collision_filter);
+            set_component(
+                &mut entry,
+                if ball.kinematic {
+                    Some(Kinematic)
+                } else {
+                    None
+                },
+            );
+            set_component(&mut entry, if ball.pinned { Some(Pinned) } else { None });
+        }
+
+        *self.resources.get_mut::<SimulationData>().unwrap() = snapshot.simulation_data;
+        *self.resources.get_mut::<SimulationConfig>().unwrap() = snapshot.simulation_config;
+        self.resources.get_mut::<Lifecycle>().unwrap().clear();
+        if let Some(collision_detection_data) = snapshot.collision_detection_data.as_ref() {
+            let mut collision_detection_data = collision_detection_data.clone();
+            collision_detection_data.replace_entities(&replaced_entities);
+            *self.resources.get_mut::<CollisionDetectionData>().unwrap() = collision_detection_data;
+        }
+    }
+}
+
+fn take_snapshot(world: &World, resources: &Resources) -> WorldSnapshot {
+    WorldSnapshot {
+        balls: <(Entity, &BallId)>::query()
+            .iter(world)
+            .map(|(entity, id)| {
+                let entry = world.entry_ref(*entity).unwrap();
+                BallSnapshot {
+                    entity: *entity,
+                    id: *id,
+                    ball: *entry.get_component::<Ball>().unwrap(),
+                    trails: entry.get_component::<Trails>().unwrap().clone(),
+                    generation: *entry.get_component::<Generation>().unwrap(),
+                    collision_count: *entry.get_component::<CollisionCount>().unwrap(),
+                    last_impulse: *entry.get_component::<LastImpulse>().unwrap(),
+                    spin: *entry.get_component::<Spin>().unwrap(),
+                    lifetime: entry.get_component::<Lifetime>().ok().copied(),
+                    collision_filter: entry.get_component::<CollisionFilter>().ok().copied(),
+                    kinematic: entry.get_component::<Kinematic>().is_ok(),
+                    pinned: entry.get_component::<Pinned>().is_ok(),
+                }
+            })
+            .collect(),
+        simulation_data: *resources.get::<SimulationData>().unwrap(),
+        simulation_config: *resources.get::<SimulationConfig>().unwrap(),
+        collision_detection_data: resources
+            .get::<CollisionDetectionData>()
+            .map(|collision_detection_data| collision_detection_data.clone()),
+    }
+}
+
+// Adds the component, or removes it if None.
+fn set_component<T: Component>(entry: &mut Entry, component: Option<T>) {
+    match component {
+        Some(component) => entry.add_component(component),
+        None => entry.remove_component::<T>(),
+    }
+}
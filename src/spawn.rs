@@ -0,0 +1,184 @@
+use fnv::FnvHashMap;
+use legion::{Entity, EntityStore, IntoQuery, World};
+use nalgebra::{Vector2, Vector3};
+
+use crate::{
+    ball::{Ball, BallId, CollisionCount, Kinematic, LastImpulse, Pinned, Spin, Trails},
+    collision::collidable::{CollidableType, Generation},
+    lifecycle::Lifecycle,
+    replay::InputEvent,
+};
+
+// Everything a ball entity is made of.
+pub type BallComponents = (
+    Ball,
+    Trails,
+    CollidableType,
+    Generation,
+    CollisionCount,
+    LastImpulse,
+    Spin,
+    BallId,
+);
+
+pub fn ball_components(ball: Ball, id: BallId) -> BallComponents {
+    (
+        ball,
+        Trails::default(),
+        CollidableType::Ball,
+        Generation { generation: 0 },
+        CollisionCount::default(),
+        LastImpulse::default(),
+        Spin::default(),
+        id,
+    )
+}
+
+// One past the largest id in the world, for balls added to it directly.
+pub fn next_ball_id<W: EntityStore>(world: &W) -> BallId {
+    BallId(
+        <&BallId>::query()
+            .iter(world)
+            .map(|id| id.0 + 1)
+            .max()
+            .unwrap_or(0),
+    )
+}
+
+// Queues a ball to spawn at the start of the next frame, at the simulation time then, whatever its
+// initial_time. Balls move as position + velocity * (t - initial_time), so the solvers would place
+// a ball stamped with another time somewhere else, and predict its collisions in the past or the
+// future. Returns whether the ball was queued, see Lifecycle::spawn.
+pub fn spawn_ball(lifecycle: &mut Lifecycle, ball: Ball) -> bool {
+    lifecycle.spawn(ball, None)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BurstConfig {
+    pub count: usize,
+    pub speed: f64,
+    // Distance of the spawned balls from the burst center. Grown if needed to fit all balls.
+    pub ring_radius: f64,
+    pub ball_radius: f64,
+    pub color: Vector3<f32>,
+}
+
+// Balls evenly spread on a ring around the center, moving outwards.
+pub fn burst(center: Vector2<f64>, config: &BurstConfig) -> Vec<InputEvent> {
+    // Neighbors on the ring are 2*ring_radius*sin(pi/count) apart. Keep a small gap.
+    let min_ring_radius = if config.count > 1 {
+        1.05 * config.ball_radius / (std::f64::consts::PI / config.count as f64).sin()
+    } else {
+        0.
+    };
+    let ring_radius = config.ring_radius.max(min_ring_radius);
+    (0..config.count)
+        .map(|i| {
+            let angle = std::f64::consts::TAU * i as f64 / config.count as f64;
+            let direction = Vector2::new(angle.cos(), angle.sin());
+            InputEvent::SpawnBall {
+                position: center + direction * ring_radius,
+                velocity: direction * config.speed,
+                radius: config.ball_radius,
+                color: config.color,
+            }
+        })
+        .collect()
+}
+
+pub fn overlaps_existing(world: &World, position: &Vector2<f64>, radius: f64) -> bool {
+    <&Ball>::query()
+        .iter(world)
+        .any(|ball| (ball.position - position).norm() <= ball.radius + radius)
+}
+
+// Relaxation passes of separate_overlaps.
+const SEPARATION_ITERATIONS: usize = 8;
+
+// Pushes overlapping balls apart along their line of centers, until they just touch. The solvers
+// only resolve balls moving towards each other, so balls spawned overlapping can stay stuck inside
+// each other. Call after generating or importing a scene. Each pass moves every ball by the sum of
+// its corrections, shared by inverse mass like an impulse. Kinematic and pinned balls do not move,
+// and velocities are kept. Returns the number of pairs still overlapping.
+pub fn separate_overlaps(world: &mut World) -> usize {
+    let (entities, mut balls): (Vec<Entity>, Vec<_>) =
+        <(Entity, &Ball, Option<&Kinematic>, Option<&Pinned>)>::query()
+            .iter(world)
+            .map(|(entity, ball, kinematic, pinned)| {
+                let inverse_mass = if kinematic.is_some() || pinned.is_some() {
+                    0.
+                } else {
+                    1. / ball.mass()
+                };
+                (*entity, (ball.position, ball.radius, inverse_mass))
+            })
+            .unzip();
+    let cell_size = 2.
+        * balls
+            .iter()
+            .map(|(_, radius, _)| *radius)
+            .fold(0., f64::max);
+    if cell_size <= 0. {
+        return 0;
+    }
+
+    let mut overlapping = 0;
+    for iteration in 0..=SEPARATION_ITERATIONS {
+        // Balls only overlap others in the cells around their own.
+        let cell_of = |position: &Vector2<f64>| {
+            (
+                (position.x / cell_size).floor() as i64,
+                (position.y / cell_size).floor() as i64,
+            )
+        };
+        let mut cells = FnvHashMap::<(i64, i64), Vec<usize>>::default();
+        for (index, (position, _, _)) in balls.iter().enumerate() {
+            cells.entry(cell_of(position)).or_default().push(index);
+        }
+
+        overlapping = 0;
+        let mut corrections = vec![Vector2::new(0., 0.); balls.len()];
+        for (index0, (position0, radius0, inverse_mass0)) in balls.iter().enumerate() {
+            let (x, y) = cell_of(position0);
+            for cell in (x - 1..=x + 1).flat_map(|i| (y - 1..=y + 1).map(move |j| (i, j))) {
+                for &index1 in cells.get(&cell).into_iter().flatten() {
+                    if index1 <= index0 {
+                        continue;
+                    }
+                    let (position1, radius1, inverse_mass1) = balls[index1];
+                    let dx = position0 - position1;
+                    let distance = dx.norm();
+                    let overlap = radius0 + radius1 - distance;
+                    if overlap <= 0. {
+                        continue;
+                    }
+                    overlapping += 1;
+                    let inverse_mass = inverse_mass0 + inverse_mass1;
+                    if inverse_mass == 0. {
+                        continue;
+                    }
+                    // Concentric balls have no line of centers, pick one.
+                    let normal = if distance > 0. {
+                        dx / distance
+                    } else {
+                        Vector2::new(1., 0.)
+                    };
+                    corrections[index0] += normal * overlap * inverse_mass0 / inverse_mass;
+                    corrections[index1] -= normal * overlap * inverse_mass1 / inverse_mass;
+                }
+            }
+        }
+        if overlapping == 0 || iteration == SEPARATION_ITERATIONS {
+            break;
+        }
+        for ((position, _, _), correction) in balls.iter_mut().zip(corrections.iter()) {
+            *position += correction;
+        }
+    }
+
+    for (entity, (position, _, _)) in entities.iter().zip(balls.iter()) {
+        let mut entry = world.entry(*entity).unwrap();
+        entry.get_component_mut::<Ball>().unwrap().position = *position;
+    }
+    overlapping
+}
@@ -0,0 +1,109 @@
+use legion::{EntityStore, IntoQuery};
+use nalgebra::Vector2;
+use std::fmt;
+
+use crate::{
+    ball::Ball,
+    simulation::SimulationConfig,
+    wall::Wall,
+    world_gen::{GenerationConfig, SEED},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldStats {
+    pub count: usize,
+    // Bounding box of the balls, including their radii. None without balls.
+    pub bounds: Option<(Vector2<f64>, Vector2<f64>)>,
+    // Sum of the areas of the balls, overlaps counted twice.
+    pub total_area: f64,
+    pub mean_radius: f64,
+    pub max_radius: f64,
+    pub mean_speed: f64,
+}
+
+// Summary of the balls of a world, in one pass.
+pub fn world_stats<W: EntityStore>(world: &W) -> WorldStats {
+    let mut stats = WorldStats {
+        count: 0,
+        bounds: None,
+        total_area: 0.,
+        mean_radius: 0.,
+        max_radius: 0.,
+        mean_speed: 0.,
+    };
+    let mut radius_sum = 0.;
+    let mut speed_sum = 0.;
+    for ball in <&Ball>::query().iter(world) {
+        let min = ball.position.add_scalar(-ball.radius);
+        let max = ball.position.add_scalar(ball.radius);
+        stats.bounds = Some(match stats.bounds {
+            Some((bounds_min, bounds_max)) => (bounds_min.inf(&min), bounds_max.sup(&max)),
+            None => (min, max),
+        });
+        stats.count += 1;
+        stats.total_area += std::f64::consts::PI * ball.radius * ball.radius;
+        stats.max_radius = stats.max_radius.max(ball.radius);
+        radius_sum += ball.radius;
+        speed_sum += ball.velocity.norm();
+    }
+    if stats.count > 0 {
+        stats.mean_radius = radius_sum / stats.count as f64;
+        stats.mean_speed = speed_sum / stats.count as f64;
+    }
+    stats
+}
+
+// What a run starts from, logged at launch to help reproduce and report it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SceneSummary {
+    pub balls: usize,
+    pub walls: usize,
+    pub world_width: u32,
+    pub world_height: u32,
+    pub seed: u128,
+    pub time_delta: f64,
+    pub blur: bool,
+    pub kinetic_energy: f64,
+}
+
+pub fn scene_summary<W: EntityStore>(
+    world: &W,
+    generation_config: &GenerationConfig,
+    simulation_config: &SimulationConfig,
+    blur: bool,
+) -> SceneSummary {
+    let mut balls = 0;
+    let mut kinetic_energy = 0.;
+    for ball in <&Ball>::query().iter(world) {
+        balls += 1;
+        kinetic_energy += 0.5 * ball.mass() * ball.velocity.norm_squared();
+    }
+    SceneSummary {
+        balls,
+        walls: <&Wall>::query().iter(world).count(),
+        world_width: generation_config.width,
+        world_height: generation_config.height,
+        seed: SEED,
+        time_delta: simulation_config.time_delta,
+        blur,
+        kinetic_energy,
+    }
+}
+
+impl fmt::Display for SceneSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} balls, {} walls, world {}x{}, seed {:#x}, time delta {}, blur {}, \
+             kinetic energy {:.1}",
+            self.balls,
+            self.walls,
+            self.world_width,
+            self.world_height,
+            self.seed,
+            self.time_delta,
+            if self.blur { "on" } else { "off" },
+            self.kinetic_energy
+        )
+    }
+}
@@ -0,0 +1,54 @@
+use legion::{query::component, *};
+use nalgebra::Vector2;
+
+use crate::ball::Ball;
+
+// Points kept in a trace, the oldest are dropped first.
+pub const MAX_TRACE_POINTS: usize = 10000;
+
+// Marks the ball whose path is traced and highlighted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Selected;
+
+// Position of the selected ball at the end of each frame since it was selected.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct TraceHistory {
+    pub points: Vec<Vector2<f64>>,
+}
+
+// Runs after the balls are advanced.
+#[system(for_each)]
+#[filter(component::<Selected>())]
+pub fn record_trace(ball: &Ball, trace: &mut TraceHistory) {
+    trace.points.push(ball.position);
+    let excess = trace.points.len().saturating_sub(MAX_TRACE_POINTS);
+    trace.points.drain(..excess);
+}
+
+// Ball under a point of the world. Where balls overlap, the one whose center is closest.
+pub fn pick_ball(world: &World, point: Vector2<f64>) -> Option<Entity> {
+    <(Entity, &Ball)>::query()
+        .iter(world)
+        .map(|(entity, ball)| (*entity, (ball.position - point).norm() / ball.radius))
+        .filter(|(_, distance)| *distance <= 1.)
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(entity, _)| entity)
+}
+
+// Selects the ball with an empty trace, or clears the selection with None.
+pub fn select_ball(world: &mut World, entity: Option<Entity>) {
+    let selected = <Entity>::query()
+        .filter(component::<Selected>())
+        .iter(world)
+        .copied()
+        .collect::<Vec<_>>();
+    for previous in selected {
+        let mut entry = world.entry(previous).unwrap();
+        entry.remove_component::<Selected>();
+        entry.remove_component::<TraceHistory>();
+    }
+    if let Some(mut entry) = entity.and_then(|entity| world.entry(entity)) {
+        entry.add_component(Selected);
+        entry.add_component(TraceHistory::default());
+    }
+}
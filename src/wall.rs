@@ -1,9 +1,22 @@
 use nalgebra::Vector2;
 
+// Sides of the wall that balls bounce off. The front is the side the normal points to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WallSide {
+    Both,
+    Front,
+    Back,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Wall {
     pub p0: Vector2<f64>,
     pub p1: Vector2<f64>,
+    pub side: WallSide,
+    // Fraction of the tangential velocity lost on impact, between 0 and 1.
+    pub friction: f64,
+    // Fraction of the normal speed kept on impact. 1 bounces fully, 0 stops the ball against it.
+    pub restitution: f64,
 }
 
 impl Wall {
@@ -11,4 +24,13 @@ impl Wall {
         let diff = self.p1 - self.p0;
         return Vector2::new(-diff.y, diff.x).normalize();
     }
+
+    // Whether a ball on the given side (sign of the distance along the normal) bounces off.
+    pub fn blocks_from(&self, signed_distance: f64) -> bool {
+        match self.side {
+            WallSide::Both => true,
+            WallSide::Front => signed_distance > 0.,
+            WallSide::Back => signed_distance < 0.,
+        }
+    }
 }
@@ -1,20 +1,118 @@
-use crate::wall::Wall;
+use crate::wall::{Wall, WallSide};
 use crate::{
-    ball::{Ball, Trails},
-    collision::collidable::{CollidableType, Generation},
+    ball::{Ball, BallId},
+    collision::{
+        collidable::{CollidableType, Generation},
+        CELL_SIZE,
+    },
+    spawn::{ball_components, BallComponents},
 };
+use fnv::FnvHashMap;
 use legion::World;
+use log::{error, warn};
 use nalgebra::{Vector2, Vector3};
 use rand::Rng;
 use rand_pcg::Pcg64;
+use std::path::Path;
 
+// Radius of balls seeded from an image. Pixels are sampled one diameter apart.
+const IMAGE_BALL_RADIUS: f64 = 8.;
+// Pixels darker than this, from 0 to 1, get no ball.
+const IMAGE_BRIGHTNESS_THRESHOLD: f32 = 0.3;
+// Random positions tried per ball before giving up on filling the world.
+const MAX_PLACEMENT_ATTEMPTS_PER_BALL: usize = 100;
+// Generated worlds are always the same, from this seed.
+pub const SEED: u128 = 0xcafef00dd15ea5e5;
+const RNG_STREAM: u128 = 0xa02bdbf7bb3c0a7ac28fa16a64abf96;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "config_file", derive(serde::Deserialize), serde(default))]
 pub struct GenerationConfig {
     pub width: u32,
     pub height: u32,
+    pub n_balls: usize,
+    // Initial speeds are drawn uniformly from this range, bounds included.
+    pub min_speed: f64,
+    pub max_speed: f64,
+    // Surround the world with walls. Without them, balls leave the world.
+    pub walls: bool,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> GenerationConfig {
+        GenerationConfig {
+            width: 1600,
+            height: 800,
+            n_balls: 150,
+            min_speed: 3.,
+            max_speed: 50.,
+            walls: true,
+        }
+    }
+}
+
+impl GenerationConfig {
+    /// Starts from the defaults, fields that are not set keep them.
+    ///
+    /// ```
+    /// use balls::world_gen::GenerationConfig;
+    ///
+    /// let config = GenerationConfig::builder().n_balls(10).walls(false).build();
+    /// assert_eq!((config.n_balls, config.walls), (10, false));
+    /// assert_eq!((config.width, config.height), (1600, 800));
+    /// ```
+    pub fn builder() -> GenerationConfigBuilder {
+        GenerationConfigBuilder {
+            config: GenerationConfig::default(),
+        }
+    }
+}
+
+// Sets some fields of a GenerationConfig, the others keep their defaults.
+pub struct GenerationConfigBuilder {
+    config: GenerationConfig,
+}
+
+impl GenerationConfigBuilder {
+    pub fn width(mut self, width: u32) -> Self {
+        self.config.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: u32) -> Self {
+        self.config.height = height;
+        self
+    }
+
+    pub fn n_balls(mut self, n_balls: usize) -> Self {
+        self.config.n_balls = n_balls;
+        self
+    }
+
+    pub fn min_speed(mut self, min_speed: f64) -> Self {
+        self.config.min_speed = min_speed;
+        self
+    }
+
+    pub fn max_speed(mut self, max_speed: f64) -> Self {
+        self.config.max_speed = max_speed;
+        self
+    }
+
+    pub fn walls(mut self, walls: bool) -> Self {
+        self.config.walls = walls;
+        self
+    }
+
+    pub fn build(self) -> GenerationConfig {
+        self.config
+    }
 }
 
 pub fn init_world(world: &mut World, config: GenerationConfig) {
-    init_walls(world, &config);
+    if config.walls {
+        init_walls(world, &config);
+    }
     init_balls(world, &config);
 }
 
@@ -33,6 +131,9 @@ fn init_walls(world: &mut World, config: &GenerationConfig) {
                 Wall {
                     p0: points[0],
                     p1: points[1],
+                    side: WallSide::Both,
+                    friction: 0.,
+                    restitution: 1.,
                 },
                 CollidableType::Wall,
                 Generation { generation: 0 },
@@ -41,6 +142,9 @@ fn init_walls(world: &mut World, config: &GenerationConfig) {
                 Wall {
                     p0: points[1],
                     p1: points[2],
+                    side: WallSide::Both,
+                    friction: 0.,
+                    restitution: 1.,
                 },
                 CollidableType::Wall,
                 Generation { generation: 0 },
@@ -49,6 +153,9 @@ fn init_walls(world: &mut World, config: &GenerationConfig) {
                 Wall {
                     p0: points[2],
                     p1: points[3],
+                    side: WallSide::Both,
+                    friction: 0.,
+                    restitution: 1.,
                 },
                 CollidableType::Wall,
                 Generation { generation: 0 },
@@ -57,6 +164,9 @@ fn init_walls(world: &mut World, config: &GenerationConfig) {
                 Wall {
                     p0: points[3],
                     p1: points[0],
+                    side: WallSide::Both,
+                    friction: 0.,
+                    restitution: 1.,
                 },
                 CollidableType::Wall,
                 Generation { generation: 0 },
@@ -67,12 +177,70 @@ fn init_walls(world: &mut World, config: &GenerationConfig) {
     world.extend(walls);
 }
 
-fn init_balls(world: &mut World, config: &GenerationConfig) {
+// Cells covered by the bounding box of a ball, same as the collision grid.
+fn ball_cells(position: &Vector2<f64>, radius: f64) -> impl Iterator<Item = (i32, i32)> {
+    let i0 = ((position.x - radius) / CELL_SIZE).floor() as i32;
+    let i1 = ((position.x + radius) / CELL_SIZE).floor() as i32;
+    let j0 = ((position.y - radius) / CELL_SIZE).floor() as i32;
+    let j1 = ((position.y + radius) / CELL_SIZE).floor() as i32;
+    (i0..=i1).flat_map(move |i| (j0..=j1).map(move |j| (i, j)))
+}
+
+// Balls placed so far, indexed by cell. Overlapping balls always share a cell.
+#[derive(Default)]
+struct Placement {
+    balls: Vec<BallComponents>,
+    cells: FnvHashMap<(i32, i32), Vec<usize>>,
+}
+
+impl Placement {
+    // Adds the ball unless it overlaps an existing one.
+    fn try_place(&mut self, ball: Ball) -> bool {
+        let balls = &self.balls;
+        let found = ball_cells(&ball.position, ball.radius).any(|cell| {
+            self.cells.get(&cell).map_or(false, |indices| {
+                indices.iter().any(|index| {
+                    let other_ball = &balls[*index].0;
+                    (other_ball.position - ball.position).norm() <= other_ball.radius + ball.radius
+                })
+            })
+        });
+        if found {
+            return false;
+        }
+        for cell in ball_cells(&ball.position, ball.radius) {
+            self.cells.entry(cell).or_default().push(self.balls.len());
+        }
+        let id = BallId(self.balls.len() as u64);
+        self.balls.push(ball_components(ball, id));
+        true
+    }
+}
+
+fn new_rng() -> Pcg64 {
     // let mut rng = rand::thread_rng();
-    let mut rng = Pcg64::new(0xcafef00dd15ea5e5, 0xa02bdbf7bb3c0a7ac28fa16a64abf96);
-    let n_balls = 150;
-    let mut balls = std::vec::Vec::<(Ball, Trails, CollidableType, Generation)>::new();
-    balls.reserve(n_balls);
+    Pcg64::new(SEED, RNG_STREAM)
+}
+
+fn random_velocity(rng: &mut Pcg64, config: &GenerationConfig) -> Vector2<f64> {
+    let angle = rng.gen_range(0.0..(std::f64::consts::TAU));
+    // An inverted range is reported by init_balls. All balls then get max_speed.
+    let speed = rng.gen_range(config.min_speed.min(config.max_speed)..=config.max_speed);
+    Vector2::new(speed * angle.cos(), speed * angle.sin())
+}
+
+// Places up to config.n_balls balls, fewer if they do not fit in the world.
+fn init_balls(world: &mut World, config: &GenerationConfig) {
+    if config.min_speed > config.max_speed {
+        error!(
+            "min_speed {} is above max_speed {}",
+            config.min_speed, config.max_speed
+        );
+    }
+    let mut rng = new_rng();
+    let n_balls = config.n_balls;
+    let mut placement = Placement::default();
+    placement.balls.reserve(n_balls);
 
     let colors = vec![
         Vector3::new(0.9, 0.8, 0.7),
@@ -83,38 +251,85 @@ fn init_balls(world: &mut World, config: &GenerationConfig) {
         Vector3::new(0.7, 0.8, 0.9),
     ];
 
-    while balls.len() < n_balls {
-        let angle = rng.gen_range(0.0..(std::f64::consts::TAU));
-        let speed = rng.gen_range(3.0..50.0);
+    for _ in 0..n_balls * MAX_PLACEMENT_ATTEMPTS_PER_BALL {
+        if placement.balls.len() >= n_balls {
+            break;
+        }
+        let velocity = random_velocity(&mut rng, config);
         let radius = rng.gen_range(10.0..30.0);
-        let ball = Ball {
-            position: Vector2::new(
-                rng.gen_range(radius..(config.width as f64 - radius)),
-                rng.gen_range(radius..(config.height as f64 - radius)),
-            ),
-            velocity: Vector2::new(speed * angle.cos(), speed * angle.sin()),
+        if 2. * radius >= config.width.min(config.height) as f64 {
+            continue;
+        }
+        let position = Vector2::new(
+            rng.gen_range(radius..(config.width as f64 - radius)),
+            rng.gen_range(radius..(config.height as f64 - radius)),
+        );
+        let color = colors[rng.gen_range(0..colors.len())];
+        placement.try_place(Ball {
+            position: position,
+            velocity: velocity,
             radius: radius,
+            growth_rate: 0.,
             initial_time: 0.,
-            color: colors[rng.gen_range(0..colors.len())],
-        };
-
-        // Check it doesn't overlap with an existing ball.
-        let mut found = false;
-        for (other_ball, _, _, _) in &balls {
-            if (other_ball.position - ball.position).norm() <= other_ball.radius + ball.radius {
-                found = true;
-                break;
+            color: color,
+            base_color: color,
+            heat: 0.,
+        });
+    }
+    if placement.balls.len() < n_balls {
+        warn!(
+            "Only {} of {} balls fit in the world",
+            placement.balls.len(),
+            n_balls
+        );
+    }
+    world.extend(placement.balls);
+}
+
+// Seeds balls from a picture stretched over the world. The image is sampled on a grid one ball
+// diameter apart, and bright pixels get a ball of their color. config.n_balls is ignored. Returns
+// the number of balls placed.
+pub fn init_balls_from_image<P: AsRef<Path>>(
+    world: &mut World,
+    config: &GenerationConfig,
+    path: P,
+) -> Result<usize, image::ImageError> {
+    let image = image::open(path)?.into_rgb8();
+    let mut rng = new_rng();
+    let mut placement = Placement::default();
+    let radius = IMAGE_BALL_RADIUS;
+    let columns = (config.width as f64 / (2. * radius)).floor() as u32;
+    let rows = (config.height as f64 / (2. * radius)).floor() as u32;
+    for row in 0..rows {
+        for column in 0..columns {
+            let position = Vector2::new(
+                (2 * column + 1) as f64 * radius,
+                (2 * row + 1) as f64 * radius,
+            );
+            let x = (position.x / config.width as f64 * image.width() as f64) as u32;
+            let y = (position.y / config.height as f64 * image.height() as f64) as u32;
+            let pixel = image.get_pixel(x.min(image.width() - 1), y.min(image.height() - 1));
+            let color = Vector3::new(
+                pixel[0] as f32 / 255.,
+                pixel[1] as f32 / 255.,
+                pixel[2] as f32 / 255.,
+            );
+            if color.max() < IMAGE_BRIGHTNESS_THRESHOLD {
+                continue;
             }
+            placement.try_place(Ball {
+                position: position,
+                velocity: random_velocity(&mut rng, config),
+                radius: radius,
+                growth_rate: 0.,
+                initial_time: 0.,
+                color: color,
+                base_color: color,
+                heat: 0.,
+            });
         }
-        if found {
-            continue;
-        }
-        balls.push((
-            ball,
-            Trails::default(),
-            CollidableType::Ball,
-            Generation { generation: 0 },
-        ));
     }
-    world.extend(balls);
+    let count = placement.balls.len();
+    world.extend(placement.balls);
+    Ok(count)
 }
@@ -0,0 +1,52 @@
+mod common;
+
+use balls::{
+    ball::BallId,
+    lifecycle::Lifecycle,
+    simulation::{DtPolicy, SimulationConfig, SimulationData},
+    spawn::ball_components,
+};
+use common::{ball, headless_simulation};
+use legion::World;
+
+fn time_delta(simulation: &balls::simulation::Simulation) -> f64 {
+    let simulation_data = simulation.resources.get::<SimulationData>().unwrap();
+    simulation_data.next_time - simulation_data.time
+}
+
+#[test]
+fn fast_ball_shortens_the_step() {
+    let mut world = World::default();
+    world.push(ball_components(
+        ball((100., 100.), (1., 0.), 10.),
+        BallId(0),
+    ));
+    world.push(ball_components(
+        ball((300., 300.), (0., -2.), 10.),
+        BallId(1),
+    ));
+    let config = SimulationConfig::builder()
+        .cap_frame_time(false)
+        .dt_policy(DtPolicy::Adaptive {
+            max_displacement_fraction: 0.5,
+        })
+        .build();
+    let mut simulation = headless_simulation(world, 400., 400., config);
+    simulation.step();
+    // Slow balls move much less than half a radius in the default time_delta.
+    assert!((time_delta(&simulation) - 0.1).abs() < 1e-12);
+
+    simulation
+        .resources
+        .get_mut::<Lifecycle>()
+        .unwrap()
+        .spawn(ball((200., 200.), (0., 1000.), 5.), None);
+    simulation.step();
+    // Half the smallest radius at the speed of the new ball.
+    let expected = 0.5 * 5. / 1000.;
+    assert!(
+        (time_delta(&simulation) - expected).abs() < 1e-12,
+        "{}",
+        time_delta(&simulation)
+    );
+}
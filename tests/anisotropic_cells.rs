@@ -0,0 +1,64 @@
+mod common;
+
+use balls::{
+    ball::Ball,
+    collision::{CollisionDetectionData, CELL_SIZE},
+    simulation::PerfStats,
+    world_gen::GenerationConfig,
+};
+use common::{balls_by_id, generated_simulation};
+
+// Balls of a wide world after 100 steps, and the number of collisions resolved.
+fn run(cell_size_x: f64, cell_size_y: f64) -> (Vec<Ball>, usize) {
+    let mut simulation = generated_simulation(
+        GenerationConfig::builder()
+            .width(1000)
+            .height(200)
+            .n_balls(60)
+            .build(),
+    );
+    simulation
+        .resources
+        .insert(CollisionDetectionData::with_cell_sizes(
+            1000.,
+            200.,
+            cell_size_x,
+            cell_size_y,
+        ));
+    let mut collisions = 0;
+    for _ in 0..100 {
+        simulation.step();
+        collisions += simulation
+            .resources
+            .get::<PerfStats>()
+            .unwrap()
+            .collisions_last_frame;
+    }
+    let balls = balls_by_id(&simulation)
+        .into_iter()
+        .map(|(_, ball)| ball)
+        .collect();
+    (balls, collisions)
+}
+
+#[test]
+fn anisotropic_cells_find_the_same_collisions() {
+    let (square, square_collisions) = run(CELL_SIZE, CELL_SIZE);
+    assert!(square_collisions > 0);
+    for (cell_size_x, cell_size_y) in
+        [(4. * CELL_SIZE, CELL_SIZE), (CELL_SIZE, 3. * CELL_SIZE)].iter()
+    {
+        let (balls, collisions) = run(*cell_size_x, *cell_size_y);
+        assert_eq!(collisions, square_collisions);
+        for (ball, expected) in balls.iter().zip(square.iter()) {
+            assert!(
+                (ball.position - expected.position).norm() < 1e-6,
+                "{} != {} with {}x{} cells",
+                ball.position,
+                expected.position,
+                cell_size_x,
+                cell_size_y
+            );
+        }
+    }
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use balls::{ball::BallId, collision::CollisionDetectionData};
+use common::{ball, simulation_with_balls};
+use legion::{Entity, IntoQuery};
+
+#[test]
+fn near_pairs_are_candidates_once_and_far_ones_never() {
+    // Two neighbors across a cell boundary, and a ball far from both.
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((95., 100.), (1., 0.), 10.),
+            ball((118., 100.), (-1., 0.), 10.),
+            ball((300., 300.), (0., 0.), 10.),
+        ],
+        400,
+        false,
+    );
+    // Fills the grid without resolving anything.
+    simulation.next_collision_time();
+    let entity = |id: u64| {
+        *<(Entity, &BallId)>::query()
+            .iter(&simulation.world)
+            .find(|(_, ball_id)| **ball_id == BallId(id))
+            .unwrap()
+            .0
+    };
+    let (near0, near1) = (entity(0), entity(1));
+    let collision_detection_data = simulation
+        .resources
+        .get::<CollisionDetectionData>()
+        .unwrap();
+    let pairs = collision_detection_data
+        .candidate_pairs()
+        .collect::<Vec<_>>();
+    assert_eq!(pairs.len(), 1, "{:?}", pairs);
+    let (entity0, entity1) = pairs[0];
+    assert!(
+        (entity0, entity1) == (near0, near1) || (entity0, entity1) == (near1, near0),
+        "{:?}",
+        pairs
+    );
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use balls::{
+    ball::Ball,
+    collision::{ClampPolicy, CollisionDetectionData},
+    simulation::PerfStats,
+};
+use common::{ball, balls_by_id, simulation_with_balls};
+
+// Two balls closing in at x = x0 + 12.5, in a grid covering 0 to 200, and whether they bounced
+// off each other.
+fn bounces(clamp_policy: ClampPolicy, x0: f64) -> (bool, usize) {
+    let balls: [Ball; 2] = [
+        ball((x0, 100.), (10., 0.), 10.),
+        ball((x0 + 25., 100.), (-10., 0.), 10.),
+    ];
+    let mut simulation = simulation_with_balls(&balls, 200, false);
+    simulation
+        .resources
+        .get_mut::<CollisionDetectionData>()
+        .unwrap()
+        .clamp_policy = clamp_policy;
+    // Contact at t = 0.25.
+    let mut out_of_grid = 0;
+    for _ in 0..5 {
+        simulation.step();
+        out_of_grid += simulation
+            .resources
+            .get::<PerfStats>()
+            .unwrap()
+            .skipped_collisions
+            .out_of_grid;
+    }
+    let balls = balls_by_id(&simulation);
+    (balls[0].1.velocity.x < 0., out_of_grid)
+}
+
+#[test]
+fn clamp_to_edge_is_the_default() {
+    assert_eq!(ClampPolicy::default(), ClampPolicy::ClampToEdge);
+}
+
+#[test]
+fn every_policy_handles_balls_near_the_edge() {
+    // Right up to the edge of the grid, still inside.
+    for clamp_policy in [
+        ClampPolicy::Discard,
+        ClampPolicy::ClampToEdge,
+        ClampPolicy::Expand,
+    ]
+    .iter()
+    {
+        assert_eq!(
+            bounces(*clamp_policy, 160.),
+            (true, 0),
+            "{:?}",
+            clamp_policy
+        );
+    }
+}
+
+#[test]
+fn discard_misses_balls_beyond_the_edge() {
+    let (bounced, out_of_grid) = bounces(ClampPolicy::Discard, 260.);
+    assert!(!bounced);
+    assert!(out_of_grid > 0);
+}
+
+#[test]
+fn clamp_to_edge_keeps_balls_beyond_the_edge() {
+    assert_eq!(bounces(ClampPolicy::ClampToEdge, 260.), (true, 0));
+}
+
+#[test]
+fn expand_keeps_balls_beyond_the_edge() {
+    assert_eq!(bounces(ClampPolicy::Expand, 260.), (true, 0));
+}
+
+#[test]
+fn discard_counts_nothing_in_a_quiet_walled_scene() {
+    // The walls lie on the edges of the grid, and the ball stays well inside.
+    let mut simulation = simulation_with_balls(&[ball((100., 100.), (5., 3.), 10.)], 200, true);
+    simulation
+        .resources
+        .get_mut::<CollisionDetectionData>()
+        .unwrap()
+        .clamp_policy = ClampPolicy::Discard;
+    for _ in 0..20 {
+        simulation.step();
+        let perf_stats = simulation.resources.get::<PerfStats>().unwrap();
+        assert_eq!(perf_stats.skipped_collisions.out_of_grid, 0);
+    }
+}
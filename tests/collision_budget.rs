@@ -0,0 +1,85 @@
+mod common;
+
+use balls::{
+    collision::{ClampPolicy, CollisionDetectionData},
+    simulation::{PerfStats, Simulation, SimulationData},
+    spawn::{ball_components, next_ball_id},
+    world_gen::GenerationConfig,
+};
+use common::{ball, generated_simulation, state_bits};
+
+const MAX_COLLISIONS_PER_FRAME: usize = 3;
+
+fn dense_simulation() -> Simulation {
+    let mut simulation = generated_simulation(
+        GenerationConfig::builder()
+            .width(300)
+            .height(300)
+            .n_balls(60)
+            .build(),
+    );
+    simulation
+        .resources
+        .get_mut::<CollisionDetectionData>()
+        .unwrap()
+        .max_collisions_per_frame = Some(MAX_COLLISIONS_PER_FRAME);
+    simulation
+}
+
+#[test]
+fn dense_scene_respects_the_budget() {
+    let mut simulation = dense_simulation();
+    let mut resolved = 0;
+    let mut deferred = 0;
+    for step in 1..=100 {
+        simulation.step();
+        let perf_stats = simulation.resources.get::<PerfStats>().unwrap();
+        assert!(perf_stats.collisions_last_frame <= MAX_COLLISIONS_PER_FRAME);
+        resolved += perf_stats.collisions_last_frame;
+        deferred += perf_stats.skipped_collisions.deferred;
+        // Time keeps moving, whatever is left over.
+        let time = simulation.resources.get::<SimulationData>().unwrap().time;
+        assert!((time - step as f64 * 0.1).abs() < 1e-9, "{}", time);
+    }
+    assert!(resolved > 0);
+    assert!(
+        deferred > 0,
+        "The scene is not dense enough to test the budget"
+    );
+}
+
+#[test]
+fn peeking_keeps_deferred_collisions() {
+    let mut peeked = dense_simulation();
+    let mut reference = dense_simulation();
+    for _ in 0..100 {
+        peeked.next_collision_time();
+        peeked.step();
+        reference.step();
+    }
+    assert_eq!(state_bits(&peeked), state_bits(&reference));
+}
+
+#[test]
+fn under_budgeted_frames_report_what_they_skipped() {
+    let mut simulation = dense_simulation();
+    simulation
+        .resources
+        .get_mut::<CollisionDetectionData>()
+        .unwrap()
+        .clamp_policy = ClampPolicy::Discard;
+    // Past the edge of the 300x300 grid, cut off in every frame.
+    let id = next_ball_id(&simulation.world);
+    simulation
+        .world
+        .push(ball_components(ball((330., 150.), (0., 0.), 10.), id));
+    for _ in 0..100 {
+        simulation.step();
+        let perf_stats = simulation.resources.get::<PerfStats>().unwrap();
+        assert!(perf_stats.skipped_collisions.out_of_grid > 0);
+        if perf_stats.skipped_collisions.deferred > 0 {
+            return;
+        }
+    }
+    panic!("The scene is not dense enough to test the budget");
+}
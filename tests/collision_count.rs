@@ -0,0 +1,20 @@
+mod common;
+
+use balls::ball::CollisionCount;
+use common::{ball, simulation_with_balls};
+use legion::IntoQuery;
+
+#[test]
+fn count_matches_the_bounces_between_two_walls() {
+    // Bounces at 1.8 s, then every 3.6 s.
+    let mut simulation = simulation_with_balls(&[ball((100., 100.), (50., 0.), 10.)], 200, true);
+    // Until 1 s, 3 s, 6 s and 10 s.
+    for (seconds, bounces) in [(1., 0), (2., 1), (3., 2), (4., 3)].iter() {
+        simulation.run_for(*seconds);
+        let counts = <&CollisionCount>::query()
+            .iter(&simulation.world)
+            .map(|count| count.count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![*bounces]);
+    }
+}
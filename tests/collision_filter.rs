@@ -0,0 +1,47 @@
+mod common;
+
+use balls::{ball::BallId, collision::collidable::CollisionFilter};
+use common::{ball, balls_by_id, simulation_with_balls};
+use legion::{Entity, IntoQuery};
+
+#[test]
+fn ghosts_pass_through_each_other_but_bounce_off_walls() {
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((150., 200.), (20., 0.), 10.),
+            ball((250., 200.), (-20., 0.), 10.),
+        ],
+        400,
+        true,
+    );
+    let entities = <(Entity, &BallId)>::query()
+        .iter(&simulation.world)
+        .map(|(entity, _)| *entity)
+        .collect::<Vec<_>>();
+    // Walls keep the default filter, of category 1.
+    let ghost = CollisionFilter {
+        category: 2,
+        mask: 1,
+    };
+    for entity in entities {
+        simulation.world.entry(entity).unwrap().add_component(ghost);
+    }
+
+    // They would meet at t = 2.
+    for _ in 0..30 {
+        simulation.step();
+    }
+    let balls = balls_by_id(&simulation);
+    assert_eq!(balls[0].1.velocity.x, 20.);
+    assert_eq!(balls[1].1.velocity.x, -20.);
+    assert!(balls[0].1.position.x > balls[1].1.position.x);
+
+    // Both reach a wall at t = 12.
+    for _ in 0..120 {
+        simulation.step();
+    }
+    let balls = balls_by_id(&simulation);
+    assert!((balls[0].1.velocity.x + 20.).abs() < 1e-9);
+    assert!((balls[1].1.velocity.x - 20.).abs() < 1e-9);
+    assert!(balls[0].1.position.x < 390. && balls[1].1.position.x > 10.);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use balls::{
+    ball::Ball,
+    collision::{CollisionDetectionData, CollisionRecord},
+    simulation::Simulation,
+};
+use common::{ball, simulation_with_balls};
+
+fn recording_simulation(balls: &[Ball], walls: bool) -> Simulation {
+    let mut simulation = simulation_with_balls(balls, 200, walls);
+    simulation
+        .resources
+        .get_mut::<CollisionDetectionData>()
+        .unwrap()
+        .record_collisions = true;
+    simulation
+}
+
+// Records of the first frame with a collision. They are cleared with every frame.
+fn first_records(simulation: &mut Simulation) -> Vec<CollisionRecord> {
+    for _ in 0..100 {
+        simulation.step();
+        let collision_detection_data = simulation
+            .resources
+            .get::<CollisionDetectionData>()
+            .unwrap();
+        if !collision_detection_data.records().is_empty() {
+            return collision_detection_data.records().to_vec();
+        }
+    }
+    panic!("No collision");
+}
+
+#[test]
+fn wall_impact_speed_is_the_normal_speed() {
+    // Straight into the right wall, and at an angle into the top one.
+    for (velocity, time, contact, speed) in [
+        ((25., 0.), 3.6, (200., 100.), 25.),
+        ((30., 40.), 2.25, (167.5, 200.), 40.),
+    ]
+    .iter()
+    {
+        let mut simulation = recording_simulation(&[ball((100., 100.), *velocity, 10.)], true);
+        let records = first_records(&mut simulation);
+        assert_eq!(records.len(), 1);
+        let record = records[0];
+        assert!((record.time - time).abs() < 1e-9, "{}", record.time);
+        assert!(
+            (record.impact_speed - speed).abs() < 1e-9,
+            "{}",
+            record.impact_speed
+        );
+        let contact = nalgebra::Vector2::new(contact.0, contact.1);
+        assert!(
+            (record.position - contact).norm() < 1e-9,
+            "{}",
+            record.position
+        );
+    }
+}
+
+// Radius of the ball entity at the given time, None for other entities.
+fn radius_at(simulation: &Simulation, entity: legion::Entity, time: f64) -> Option<f64> {
+    let entry = simulation.world.entry_ref(entity).unwrap();
+    let ball = entry.get_component::<Ball>().ok()?;
+    Some(ball.radius + ball.growth_rate * (time - ball.initial_time))
+}
+
+#[test]
+fn records_carry_the_radii_at_the_collision() {
+    // The second ball grows, closing the 85 units between the balls at 11 units/s.
+    let growing = Ball {
+        growth_rate: 1.,
+        ..ball((140., 100.), (0., 0.), 5.)
+    };
+    let mut simulation = recording_simulation(&[ball((40., 100.), (10., 0.), 10.), growing], false);
+    let records = first_records(&mut simulation);
+    assert_eq!(records.len(), 1);
+    let record = records[0];
+    let time = 85. / 11.;
+    assert!((record.time - time).abs() < 1e-9, "{}", record.time);
+    let mut radii = [record.radius0.unwrap(), record.radius1.unwrap()];
+    radii.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert!((radii[0] - 10.).abs() < 1e-9, "{:?}", radii);
+    assert!((radii[1] - (5. + time)).abs() < 1e-9, "{:?}", radii);
+    for (entity, radius) in [
+        (record.entity0, record.radius0),
+        (record.entity1, record.radius1),
+    ]
+    .iter()
+    {
+        let expected = radius_at(&simulation, *entity, record.time).unwrap();
+        assert!((radius.unwrap() - expected).abs() < 1e-9);
+    }
+
+    let mut simulation = recording_simulation(&[ball((100., 100.), (25., 0.), 10.)], true);
+    let records = first_records(&mut simulation);
+    assert_eq!(records.len(), 1);
+    let record = records[0];
+    // Only the ball has a radius, whichever side of the record it is on.
+    let (ball_entity, ball_radius, wall_radius) = if record.radius0.is_some() {
+        (record.entity0, record.radius0, record.radius1)
+    } else {
+        (record.entity1, record.radius1, record.radius0)
+    };
+    assert_eq!(ball_radius, Some(10.));
+    assert_eq!(wall_radius, None);
+    assert_eq!(radius_at(&simulation, ball_entity, record.time), Some(10.));
+}
@@ -0,0 +1,185 @@
+// Helpers shared by the integration tests. Not every test uses all of them.
+#![allow(dead_code)]
+
+use balls::{
+    ball::{Ball, BallId},
+    collision::{
+        collidable::{CollidableType, Generation},
+        CollisionDetectionData,
+    },
+    simulation::{init_simulation, Simulation, SimulationConfig},
+    spawn::ball_components,
+    wall::{Wall, WallSide},
+    world_gen::{init_world, GenerationConfig},
+};
+use legion::{systems::Builder, *};
+
+// Points of headless_schedule_with where the systems of optional features go, as in main.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hook {
+    // Forces and anything else changing velocities before collision detection.
+    BeforeCollision,
+    // Reactions to the collisions of the frame.
+    AfterCollision,
+    // Corrections of the moved balls.
+    AfterAdvance,
+}
+
+// The systems of the main loop that move balls, without rendering and optional features.
+pub fn headless_schedule() -> Schedule {
+    headless_schedule_with(|_, _| {})
+}
+
+// Same as headless_schedule, with the systems added by add at each hook.
+pub fn headless_schedule_with(mut add: impl FnMut(&mut Builder, Hook)) -> Schedule {
+    let mut builder = Schedule::builder();
+    builder
+        .add_system(balls::advance::clear_trails_system(0))
+        .add_system(balls::lifetime::despawn_expired_system())
+        .add_system(balls::emitter::emit_balls_system())
+        .add_system(balls::lifecycle::apply_lifecycle_system())
+        .flush();
+    add(&mut builder, Hook::BeforeCollision);
+    builder
+        .add_system(balls::collision::collision_system())
+        .add_system(balls::collision::collision_handle_system());
+    add(&mut builder, Hook::AfterCollision);
+    builder.add_system(balls::advance::advance_balls_system());
+    add(&mut builder, Hook::AfterAdvance);
+    builder
+        .add_system(balls::lifecycle::apply_lifecycle_system())
+        .flush()
+        .add_system(balls::simulation::advance_time_system())
+        .build()
+}
+
+// Frames are not capped, so steps run as fast as they can.
+pub fn headless_config() -> SimulationConfig {
+    SimulationConfig::builder().cap_frame_time(false).build()
+}
+
+pub fn headless_simulation(
+    world: World,
+    width: f64,
+    height: f64,
+    simulation_config: SimulationConfig,
+) -> Simulation {
+    simulation_with_schedule(world, width, height, simulation_config, headless_schedule())
+}
+
+pub fn simulation_with_schedule(
+    world: World,
+    width: f64,
+    height: f64,
+    simulation_config: SimulationConfig,
+    schedule: Schedule,
+) -> Simulation {
+    let mut resources = Resources::default();
+    init_simulation(&mut resources, simulation_config);
+    resources.insert(CollisionDetectionData::new(width, height));
+    Simulation::new(world, resources, schedule)
+}
+
+pub fn generated_simulation(config: GenerationConfig) -> Simulation {
+    let mut world = World::default();
+    init_world(&mut world, config);
+    headless_simulation(
+        world,
+        config.width as f64,
+        config.height as f64,
+        headless_config(),
+    )
+}
+
+// Only the given balls, numbered in order, in a square world of the given size.
+pub fn simulation_with_balls(balls: &[Ball], size: u32, walls: bool) -> Simulation {
+    let config = GenerationConfig::builder()
+        .width(size)
+        .height(size)
+        .n_balls(0)
+        .walls(walls)
+        .build();
+    let mut world = World::default();
+    init_world(&mut world, config);
+    for (i, ball) in balls.iter().enumerate() {
+        world.push(ball_components(*ball, BallId(i as u64)));
+    }
+    headless_simulation(world, size as f64, size as f64, headless_config())
+}
+
+pub fn ball(position: (f64, f64), velocity: (f64, f64), radius: f64) -> Ball {
+    let color = nalgebra::Vector3::new(1., 1., 1.);
+    Ball {
+        position: nalgebra::Vector2::new(position.0, position.1),
+        velocity: nalgebra::Vector2::new(velocity.0, velocity.1),
+        radius,
+        growth_rate: 0.,
+        initial_time: 0.,
+        color,
+        base_color: color,
+        heat: 0.,
+    }
+}
+
+// A frictionless wall bouncing balls fully from both sides, like the borders of init_world.
+pub fn wall(p0: (f64, f64), p1: (f64, f64)) -> Wall {
+    Wall {
+        p0: nalgebra::Vector2::new(p0.0, p0.1),
+        p1: nalgebra::Vector2::new(p1.0, p1.1),
+        side: WallSide::Both,
+        friction: 0.,
+        restitution: 1.,
+    }
+}
+
+pub fn push_wall(simulation: &mut Simulation, wall: Wall) {
+    simulation
+        .world
+        .push((wall, CollidableType::Wall, Generation { generation: 0 }));
+}
+
+// Adds a component to the ball with the given id, like the Kinematic and Pinned markers.
+pub fn mark_ball<T: legion::storage::Component>(
+    simulation: &mut Simulation,
+    id: BallId,
+    component: T,
+) {
+    let entity = *<(Entity, &BallId)>::query()
+        .iter(&simulation.world)
+        .find(|(_, ball_id)| **ball_id == id)
+        .unwrap()
+        .0;
+    simulation
+        .world
+        .entry(entity)
+        .unwrap()
+        .add_component(component);
+}
+
+// Balls by id, which unlike entities are the same in every run.
+pub fn balls_by_id(simulation: &Simulation) -> Vec<(BallId, Ball)> {
+    let mut balls = <(&BallId, &Ball)>::query()
+        .iter(&simulation.world)
+        .map(|(id, ball)| (*id, *ball))
+        .collect::<Vec<_>>();
+    balls.sort_by_key(|(id, _)| *id);
+    balls
+}
+
+// Exact bits of the positions and velocities, for comparisons that must not allow any rounding.
+pub fn state_bits(simulation: &Simulation) -> Vec<(BallId, [u64; 4])> {
+    balls_by_id(simulation)
+        .into_iter()
+        .map(|(id, ball)| {
+            (
+                id,
+                [
+                    ball.position.x.to_bits(),
+                    ball.position.y.to_bits(),
+                    ball.velocity.x.to_bits(),
+                    ball.velocity.y.to_bits(),
+                ],
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "config_file")]
+
+use balls::{
+    collision::collidable::PhysicsConfig,
+    config::{load_config, AppConfig},
+    simulation::SimulationConfig,
+};
+
+#[test]
+fn omitted_tables_and_fields_keep_their_defaults() {
+    let path = std::env::temp_dir().join(format!("balls_partial_{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        "[simulation]\ntime_delta = 0.05\n\n[physics]\nrestitution = 0.5\n",
+    )
+    .unwrap();
+    let config = load_config(&path);
+    std::fs::remove_file(&path).unwrap();
+    let config = config.unwrap();
+
+    let defaults = AppConfig::default();
+    assert_eq!(
+        config.simulation,
+        SimulationConfig {
+            time_delta: 0.05,
+            ..defaults.simulation
+        }
+    );
+    assert_eq!(
+        config.physics,
+        PhysicsConfig {
+            restitution: 0.5,
+            ..defaults.physics
+        }
+    );
+    assert_eq!(config.generation.n_balls, defaults.generation.n_balls);
+    assert_eq!(config.generation.width, defaults.generation.width);
+    assert_eq!(config.display.width, defaults.display.width);
+    assert_eq!(config.display.world_width, defaults.display.world_width);
+}
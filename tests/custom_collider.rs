@@ -0,0 +1,64 @@
+mod common;
+
+use balls::{
+    ball::Ball,
+    collision::{
+        collidable::{CollidableType, Generation},
+        Collider, CustomCollider,
+    },
+};
+use common::{ball, balls_by_id, simulation_with_balls};
+use nalgebra::Vector2;
+
+// Vertical barrier at x, from y = 0 to height, that balls only hit from the left.
+struct Barrier {
+    x: f64,
+    height: f64,
+}
+
+impl Collider for Barrier {
+    fn bounding_box(&self, _next_time: f64) -> (Vector2<f64>, Vector2<f64>) {
+        (Vector2::new(self.x, 0.), Vector2::new(self.x, self.height))
+    }
+
+    fn solve_against(&self, ball: &Ball, _epsilon: f64) -> Option<(f64, f64)> {
+        let vx = ball.velocity.x;
+        if vx <= 0. || ball.position.x > self.x {
+            return None;
+        }
+        Some((
+            (self.x - ball.radius - ball.position.x) / vx + ball.initial_time,
+            (self.x + ball.radius - ball.position.x) / vx + ball.initial_time,
+        ))
+    }
+
+    fn respond(&self, ball: &Ball, _t: f64) -> Option<Vector2<f64>> {
+        if ball.velocity.x > 0. {
+            Some(Vector2::new(-2. * ball.velocity.x, 0.))
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn ball_bounces_off_a_custom_collider() {
+    let mut simulation = simulation_with_balls(&[ball((50., 100.), (10., 0.), 10.)], 200, false);
+    simulation.world.push((
+        CustomCollider {
+            collider: Box::new(Barrier {
+                x: 150.,
+                height: 200.,
+            }),
+        },
+        CollidableType::Custom,
+        Generation { generation: 0 },
+    ));
+
+    // Contact at 9 s, 30 units back to the left at 12 s.
+    simulation.run_for(12.);
+    let moved = balls_by_id(&simulation)[0].1;
+    assert!((moved.velocity.x + 10.).abs() < 1e-9, "{}", moved.velocity);
+    assert_eq!(moved.velocity.y, 0.);
+    assert!((moved.position.x - 110.).abs() < 1e-6, "{}", moved.position);
+}
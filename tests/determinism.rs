@@ -0,0 +1,26 @@
+mod common;
+
+use balls::world_gen::GenerationConfig;
+use common::{generated_simulation, state_bits};
+
+fn run(steps: usize) -> Vec<(balls::ball::BallId, [u64; 4])> {
+    let mut simulation = generated_simulation(
+        GenerationConfig::builder()
+            .width(400)
+            .height(400)
+            .n_balls(40)
+            .build(),
+    );
+    for _ in 0..steps {
+        simulation.step();
+    }
+    state_bits(&simulation)
+}
+
+#[test]
+fn same_seed_gives_identical_positions() {
+    let first = run(5000);
+    let second = run(5000);
+    assert_eq!(first.len(), 40);
+    assert_eq!(first, second);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use balls::{ball::Ball, emitter::Emitter, simulation::Simulation};
+use common::simulation_with_balls;
+use legion::IntoQuery;
+use nalgebra::{Vector2, Vector3};
+
+fn emitter(ttl: Option<f64>) -> Emitter {
+    Emitter {
+        position: Vector2::new(100., 200.),
+        velocity: Vector2::new(10., 0.),
+        radius: 2.,
+        color: Vector3::new(1., 1., 1.),
+        interval: 1.,
+        remaining: 100,
+        next_spawn_time: 0.,
+        ttl,
+    }
+}
+
+fn ball_count(simulation: &Simulation) -> usize {
+    <&Ball>::query().iter(&simulation.world).count()
+}
+
+#[test]
+fn emitter_spawns_one_ball_per_interval() {
+    let mut simulation = simulation_with_balls(&[], 400, false);
+    simulation.world.push((emitter(None),));
+    simulation.run_for(10.);
+    let count = ball_count(&simulation);
+    assert!((9..=11).contains(&count), "{}", count);
+}
+
+#[test]
+fn lifetime_bounds_the_live_balls_of_an_emitter() {
+    let mut simulation = simulation_with_balls(&[], 400, false);
+    // One ball per second, each living 3 s.
+    simulation.world.push((emitter(Some(3.)),));
+    for _ in 0..200 {
+        simulation.step();
+        let count = ball_count(&simulation);
+        assert!(count <= 4, "{}", count);
+    }
+    assert!(ball_count(&simulation) >= 2);
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use balls::{energy_log::EnergyLog, world_gen::GenerationConfig};
+use common::generated_simulation;
+use legion::Schedule;
+
+// Value of a top level number field of a JSON object written by the energy log.
+fn field<'a>(line: &'a str, name: &str) -> &'a str {
+    let start = line.find(&format!("\"{}\":", name)).unwrap() + name.len() + 3;
+    let end = line[start..].find(|c| c == ',' || c == '}').unwrap() + start;
+    &line[start..end]
+}
+
+#[test]
+fn energy_log_writes_an_object_per_frame() {
+    let path = std::env::temp_dir().join(format!("balls_energy_{}.jsonl", std::process::id()));
+    let mut simulation = generated_simulation(
+        GenerationConfig::builder()
+            .width(200)
+            .height(200)
+            .n_balls(10)
+            .build(),
+    );
+    simulation
+        .resources
+        .insert(EnergyLog::create(&path).unwrap());
+    // After advance_time, as in main.
+    let mut log_schedule = Schedule::builder()
+        .add_system(balls::energy_log::log_energy_system())
+        .build();
+    for _ in 0..20 {
+        simulation.step();
+        log_schedule.execute(&mut simulation.world, &mut simulation.resources);
+    }
+    simulation
+        .resources
+        .get_mut::<EnergyLog>()
+        .unwrap()
+        .flush()
+        .unwrap();
+    let text = std::fs::read_to_string(&path);
+    std::fs::remove_file(&path).unwrap();
+    let text = text.unwrap();
+
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 20);
+    let mut last_time = 0.;
+    for (i, line) in lines.iter().enumerate() {
+        assert!(line.starts_with('{') && line.ends_with('}'), "{}", line);
+        assert_eq!(line.matches('{').count(), 1, "{}", line);
+        assert_eq!(field(line, "frame").parse::<u64>().unwrap(), i as u64);
+        let time = field(line, "time").parse::<f64>().unwrap();
+        assert!(time > last_time, "{}", line);
+        last_time = time;
+        assert_eq!(field(line, "ball_count"), "10");
+        assert!(field(line, "total_ke").parse::<f64>().unwrap() >= 0.);
+    }
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use balls::{
+    export::{export_frames, FrameExporter},
+    render::{init_headless_graphics, DisplayConfig},
+    world_gen::GenerationConfig,
+};
+use common::{generated_simulation, headless_schedule_with, Hook};
+use image::{codecs::gif::GifDecoder, AnimationDecoder, Frame, RgbaImage};
+use std::{fs::File, io::BufReader, path::Path};
+
+fn decode_gif(path: &Path) -> Vec<Frame> {
+    GifDecoder::new(BufReader::new(File::open(path).unwrap()))
+        .unwrap()
+        .into_frames()
+        .collect_frames()
+        .unwrap()
+}
+
+#[test]
+fn gif_keeps_every_frame() {
+    let path = std::env::temp_dir().join(format!("balls_frames_{}.gif", std::process::id()));
+    let mut exporter = FrameExporter::create(&path, 16, 8).unwrap();
+    for i in 0..10 {
+        exporter
+            .add_frame(RgbaImage::from_pixel(
+                16,
+                8,
+                image::Rgba([25 * i, 0, 0, 255]),
+            ))
+            .unwrap();
+    }
+    exporter.finish().unwrap();
+    let frames = decode_gif(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(frames.len(), 10);
+    assert!(frames
+        .iter()
+        .all(|frame| frame.buffer().dimensions() == (16, 8)));
+}
+
+// Needs a Vulkan device, run with `cargo test -- --ignored`.
+#[test]
+#[ignore]
+fn export_frames_writes_a_gif() {
+    let config = GenerationConfig::builder()
+        .width(200)
+        .height(100)
+        .n_balls(10)
+        .build();
+    let mut simulation = generated_simulation(config);
+    simulation.schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::AfterAdvance {
+            builder.add_thread_local(balls::render::render_balls_system());
+        }
+    });
+    simulation.resources.insert(init_headless_graphics(
+        DisplayConfig::builder()
+            .width(64)
+            .height(32)
+            .world_width(200.)
+            .world_height(100.)
+            .build(),
+    ));
+    let path = std::env::temp_dir().join(format!("balls_export_{}.gif", std::process::id()));
+    export_frames(&mut simulation, &path, 10).unwrap();
+    let frames = decode_gif(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(frames.len(), 10);
+    assert!(frames
+        .iter()
+        .all(|frame| frame.buffer().dimensions() == (64, 32)));
+}
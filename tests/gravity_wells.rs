@@ -0,0 +1,58 @@
+mod common;
+
+use balls::{
+    ball::BallId,
+    collision::CollisionDetectionData,
+    gravity::Gravitational,
+    simulation::{init_simulation, Simulation},
+    spawn::ball_components,
+};
+use common::{ball, balls_by_id, headless_config};
+use legion::{Resources, Schedule, World};
+use nalgebra::Vector2;
+
+const WELL_MASS: f64 = 1e5;
+const ORBIT_RADIUS: f64 = 100.;
+
+#[test]
+fn tangential_ball_orbits_a_well() {
+    let center = Vector2::new(500., 500.);
+    // Circular orbit speed, where the pull of the well is the centripetal acceleration.
+    let speed = (WELL_MASS / ORBIT_RADIUS).sqrt();
+    let start = (center.x + ORBIT_RADIUS, center.y);
+    let mut world = World::default();
+    world.push((Gravitational {
+        position: center,
+        mass: WELL_MASS,
+        softening: 1.,
+    },));
+    world.push(ball_components(ball(start, (0., speed), 5.), BallId(0)));
+    let mut resources = Resources::default();
+    init_simulation(&mut resources, headless_config());
+    resources.insert(CollisionDetectionData::new(1000., 1000.));
+    let schedule = Schedule::builder()
+        .add_system(balls::gravity::gravity_wells_system())
+        .add_system(balls::collision::collision_system())
+        .add_system(balls::collision::collision_handle_system())
+        .add_system(balls::advance::advance_balls_system())
+        .add_system(balls::simulation::advance_time_system())
+        .build();
+    let mut simulation = Simulation::new(world, resources, schedule);
+
+    // One period at the default time_delta of 0.1.
+    let period = 2. * std::f64::consts::PI * ORBIT_RADIUS / speed;
+    let steps = (period / 0.1).round() as usize;
+    for _ in 0..steps {
+        simulation.step();
+        let position = balls_by_id(&simulation)[0].1.position;
+        let radius = (position - center).norm();
+        assert!(
+            (radius - ORBIT_RADIUS).abs() < 0.1 * ORBIT_RADIUS,
+            "{}",
+            radius
+        );
+    }
+    let position = balls_by_id(&simulation)[0].1.position;
+    let distance = (position - Vector2::new(start.0, start.1)).norm();
+    assert!(distance < 0.1 * ORBIT_RADIUS, "{}", distance);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use balls::{
+    collision::{CollisionDetectionData, PhysicsConfig},
+    simulation::Simulation,
+    wall::Wall,
+};
+use common::{ball, balls_by_id, push_wall, simulation_with_balls, wall};
+
+fn speeds(simulation: &Simulation) -> Vec<f64> {
+    balls_by_id(simulation)
+        .iter()
+        .map(|(_, ball)| ball.velocity.norm())
+        .collect()
+}
+
+#[test]
+fn balls_squeezed_into_a_corner_come_to_rest() {
+    // The corner ball hits both walls at once, the others the wall below or beside it, then
+    // slide along it into the corner ball together.
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((12., 12.), (-4., -4.), 10.),
+            ball((40., 12.), (-4., -4.), 10.),
+            ball((12., 40.), (-4., -4.), 10.),
+        ],
+        200,
+        false,
+    );
+    for (p0, p1) in [
+        ((0., 0.), (200., 0.)),
+        ((200., 0.), (200., 200.)),
+        ((200., 200.), (0., 200.)),
+        ((0., 200.), (0., 0.)),
+    ]
+    .iter()
+    {
+        push_wall(
+            &mut simulation,
+            Wall {
+                restitution: 0.,
+                ..wall(*p0, *p1)
+            },
+        );
+    }
+    simulation
+        .resources
+        .get_mut::<CollisionDetectionData>()
+        .unwrap()
+        .group_simultaneous_contacts = true;
+    simulation
+        .resources
+        .get_mut::<PhysicsConfig>()
+        .unwrap()
+        .restitution = 0.;
+
+    let initial_speed = 32f64.sqrt();
+    for _ in 0..100 {
+        simulation.step();
+        for speed in speeds(&simulation) {
+            assert!(speed <= initial_speed + 1e-9, "{}", speed);
+        }
+    }
+    for speed in speeds(&simulation) {
+        assert!(speed < 1e-3, "{}", speed);
+    }
+    // Still packed in the corner.
+    for (_, ball) in balls_by_id(&simulation) {
+        assert!(
+            ball.position.x < 50. && ball.position.y < 50.,
+            "{}",
+            ball.position
+        );
+    }
+}
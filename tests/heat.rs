@@ -0,0 +1,49 @@
+mod common;
+
+use balls::heat::HeatConfig;
+use common::{ball, balls_by_id, headless_schedule_with, simulation_with_balls, Hook};
+use nalgebra::Vector3;
+
+#[test]
+fn heat_rises_on_collision_then_decays() {
+    // Contact after 2 s, at the default time_delta of 0.1.
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((150., 200.), (20., 0.), 10.),
+            ball((250., 200.), (-20., 0.), 10.),
+        ],
+        400,
+        false,
+    );
+    simulation.resources.insert(HeatConfig {
+        decay_rate: 1.,
+        saturation: 1.,
+        hot_color: Vector3::new(1., 0., 0.),
+    });
+    simulation.schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::AfterCollision {
+            builder.add_system(balls::heat::cool_balls_system());
+        }
+    });
+    let heat = |simulation: &balls::simulation::Simulation| balls_by_id(simulation)[0].1.heat;
+
+    for _ in 0..15 {
+        simulation.step();
+    }
+    assert_eq!(heat(&simulation), 0.);
+    let mut hottest = 0f32;
+    for _ in 0..10 {
+        simulation.step();
+        hottest = hottest.max(heat(&simulation));
+    }
+    assert!(hottest > 0.);
+    // Separating, they do not meet again.
+    let mut last = heat(&simulation);
+    for _ in 0..100 {
+        simulation.step();
+        let current = heat(&simulation);
+        assert!(current <= last, "{} > {}", current, last);
+        last = current;
+    }
+    assert!(last < 1e-3 * hottest, "{}", last);
+}
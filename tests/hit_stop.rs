@@ -0,0 +1,65 @@
+mod common;
+
+use balls::{
+    collision::CollisionDetectionData,
+    hit_stop::{HitStop, HitStopConfig},
+    simulation::SimulationData,
+};
+use common::{ball, headless_schedule_with, simulation_with_balls, Hook};
+
+// Duration of each of the first steps of two balls meeting head on at t = 1.
+fn step_durations(speed: f64) -> Vec<f64> {
+    let gap = 2. * speed;
+    let balls = [
+        ball((100. - 10. - gap / 2., 100.), (speed, 0.), 10.),
+        ball((100. + 10. + gap / 2., 100.), (-speed, 0.), 10.),
+    ];
+    let mut simulation = simulation_with_balls(&balls, 200, false);
+    simulation.schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::AfterCollision {
+            builder.add_system(balls::hit_stop::hit_stop_system());
+        }
+    });
+    simulation
+        .resources
+        .get_mut::<CollisionDetectionData>()
+        .unwrap()
+        .record_collisions = true;
+    simulation.resources.insert(HitStopConfig {
+        impact_speed: 5.,
+        time_scale: 0.1,
+        hold_frames: 3,
+        ramp_frames: 2,
+    });
+    simulation.resources.insert(HitStop::default());
+    (0..30)
+        .map(|_| {
+            simulation.step();
+            let simulation_data = simulation.resources.get::<SimulationData>().unwrap();
+            simulation_data.next_time - simulation_data.time
+        })
+        .collect()
+}
+
+#[test]
+fn strong_impact_slows_time_down() {
+    let durations = step_durations(10.);
+    let slowest = durations.iter().cloned().fold(f64::INFINITY, f64::min);
+    assert!((slowest - 0.01).abs() < 1e-12, "{:?}", durations);
+    // Back to normal speed once the hold and the ramp are over.
+    assert!(
+        (durations.last().unwrap() - 0.1).abs() < 1e-12,
+        "{:?}",
+        durations
+    );
+}
+
+#[test]
+fn weak_impact_keeps_normal_speed() {
+    let durations = step_durations(1.);
+    assert!(
+        durations.iter().all(|dt| (dt - 0.1).abs() < 1e-12),
+        "{:?}",
+        durations
+    );
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use balls::{
+    ball::{Ball, BallId},
+    integrator::{Acceleration, IntegratorKind},
+    simulation::SimulationConfig,
+    world_gen::{init_world, GenerationConfig},
+};
+use common::{headless_schedule_with, simulation_with_schedule, state_bits, Hook};
+use legion::{Entity, IntoQuery, World};
+use nalgebra::Vector2;
+
+fn run_without_forces(integrator: IntegratorKind) -> Vec<(BallId, [u64; 4])> {
+    let config = GenerationConfig::builder()
+        .width(400)
+        .height(400)
+        .n_balls(50)
+        .build();
+    let mut world = World::default();
+    init_world(&mut world, config);
+    let entities = <(Entity, &Ball)>::query()
+        .iter(&world)
+        .map(|(entity, _)| *entity)
+        .collect::<Vec<_>>();
+    for entity in entities {
+        world.entry(entity).unwrap().add_component(Acceleration {
+            value: Vector2::new(0., 0.),
+        });
+    }
+    let schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::BeforeCollision {
+            builder.add_system(balls::integrator::integrate_accelerations_system());
+        }
+    });
+    let simulation_config = SimulationConfig::builder()
+        .cap_frame_time(false)
+        .integrator(integrator)
+        .build();
+    let mut simulation = simulation_with_schedule(world, 400., 400., simulation_config, schedule);
+    for _ in 0..100 {
+        simulation.step();
+    }
+    state_bits(&simulation)
+}
+
+#[test]
+fn integrators_agree_without_forces() {
+    let analytic = run_without_forces(IntegratorKind::AnalyticLinear);
+    let euler = run_without_forces(IntegratorKind::SemiImplicitEuler);
+    assert_eq!(analytic.len(), 50);
+    assert_eq!(analytic, euler);
+}
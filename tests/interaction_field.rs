@@ -0,0 +1,88 @@
+mod common;
+
+use balls::{
+    ball::BallId,
+    forces::{InteractionField, InteractionMode},
+    world_gen::GenerationConfig,
+};
+use common::{
+    ball, balls_by_id, generated_simulation, headless_schedule_with, simulation_with_balls,
+    state_bits, Hook,
+};
+
+#[test]
+fn attraction_pulls_two_balls_together() {
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((150., 200.), (0., 0.), 5.),
+            ball((250., 200.), (0., 0.), 5.),
+        ],
+        400,
+        false,
+    );
+    simulation.resources.insert(InteractionField {
+        strength: 100.,
+        softening: 1.,
+        mode: InteractionMode::Attract,
+        cutoff: 200.,
+    });
+    simulation.schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::BeforeCollision {
+            builder.add_system(balls::forces::interaction_forces_system());
+        }
+    });
+
+    // 1 s at the default time_delta of 0.1, far from contact.
+    for _ in 0..10 {
+        simulation.step();
+    }
+    let balls = balls_by_id(&simulation);
+    let (ball0, ball1) = (balls[0].1, balls[1].1);
+    assert!(ball0.velocity.x > 0., "{}", ball0.velocity);
+    assert!(ball1.velocity.x < 0., "{}", ball1.velocity);
+    let distance = (ball1.position - ball0.position).norm();
+    assert!(distance < 100., "{}", distance);
+    // Equal masses, pulled equally.
+    assert!((ball0.velocity + ball1.velocity).norm() < 1e-9);
+}
+
+// Many attracting balls, stepped on a pool of the given number of threads.
+fn run_field(threads: usize) -> Vec<(BallId, [u64; 4])> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    pool.install(|| {
+        let mut simulation = generated_simulation(
+            GenerationConfig::builder()
+                .width(800)
+                .height(800)
+                .n_balls(100)
+                .build(),
+        );
+        simulation.resources.insert(InteractionField {
+            strength: 10.,
+            softening: 5.,
+            mode: InteractionMode::Attract,
+            cutoff: 100.,
+        });
+        simulation.schedule = headless_schedule_with(|builder, hook| {
+            if hook == Hook::BeforeCollision {
+                builder.add_system(balls::forces::interaction_forces_system());
+            }
+        });
+        for _ in 0..200 {
+            simulation.step();
+        }
+        state_bits(&simulation)
+    })
+}
+
+#[test]
+fn forces_do_not_depend_on_the_thread_count() {
+    let single = run_field(1);
+    let parallel = run_field(4);
+    assert_eq!(single.len(), 100);
+    assert_eq!(single, parallel);
+    assert_eq!(parallel, run_field(4));
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use balls::{
+    jitter::Jitter,
+    simulation::Simulation,
+    world_gen::{init_world, GenerationConfig},
+};
+use common::{headless_config, headless_simulation, simulation_with_schedule, state_bits};
+use legion::{Schedule, World};
+
+const STEPS: usize = 200;
+
+fn jitter_schedule() -> Schedule {
+    Schedule::builder()
+        .add_system(balls::jitter::jitter_balls_system())
+        .add_system(balls::collision::collision_system())
+        .add_system(balls::collision::collision_handle_system())
+        .add_system(balls::advance::advance_balls_system())
+        .add_system(balls::lifecycle::apply_lifecycle_system())
+        .flush()
+        .add_system(balls::simulation::advance_time_system())
+        .build()
+}
+
+// With padding entities created first, so the balls get other entities.
+fn world(padding: usize) -> World {
+    let mut world = World::default();
+    for _ in 0..padding {
+        world.push((0u8,));
+    }
+    init_world(
+        &mut world,
+        GenerationConfig::builder()
+            .width(300)
+            .height(300)
+            .n_balls(20)
+            .build(),
+    );
+    world
+}
+
+fn run(mut simulation: Simulation) -> Simulation {
+    for _ in 0..STEPS {
+        simulation.step();
+    }
+    simulation
+}
+
+fn jittered(jitter: Jitter, padding: usize) -> Simulation {
+    let mut simulation = simulation_with_schedule(
+        world(padding),
+        300.,
+        300.,
+        headless_config(),
+        jitter_schedule(),
+    );
+    simulation.resources.insert(jitter);
+    run(simulation)
+}
+
+#[test]
+fn fixed_seed_is_reproducible() {
+    let jitter = Jitter {
+        magnitude: 5.,
+        seed: 7,
+    };
+    let first = state_bits(&jittered(jitter, 0));
+    assert_eq!(first, state_bits(&jittered(jitter, 0)));
+    // Kicks follow the balls, not their entities.
+    assert_eq!(first, state_bits(&jittered(jitter, 3)));
+    let unjittered = run(headless_simulation(world(0), 300., 300., headless_config()));
+    assert_ne!(first, state_bits(&unjittered));
+}
+
+#[test]
+fn zero_magnitude_changes_nothing() {
+    let jitter = Jitter {
+        magnitude: 0.,
+        seed: 7,
+    };
+    let unjittered = run(headless_simulation(world(0), 300., 300., headless_config()));
+    assert_eq!(state_bits(&jittered(jitter, 0)), state_bits(&unjittered));
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use balls::bounds::KeepInBounds;
+use common::{ball, balls_by_id, headless_schedule_with, simulation_with_balls, Hook};
+
+#[test]
+fn escaped_ball_is_moved_back_within_a_step() {
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((-50., 100.), (-10., 0.), 5.),
+            ball((100., 100.), (0., 10.), 5.),
+        ],
+        200,
+        false,
+    );
+    simulation.resources.insert(KeepInBounds {
+        width: 200.,
+        height: 200.,
+    });
+    simulation.schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::AfterAdvance {
+            builder.add_system(balls::bounds::keep_in_bounds_system());
+        }
+    });
+
+    simulation.step();
+    let balls = balls_by_id(&simulation);
+    let (escaped, inside) = (balls[0].1, balls[1].1);
+    assert!(escaped.position.x >= escaped.radius, "{}", escaped.position);
+    assert!(escaped.position.x <= 200. - escaped.radius);
+    assert_eq!(escaped.position.y, 100.);
+    assert_eq!(escaped.velocity.x, 10.);
+    // Untouched.
+    assert_eq!(inside.position.x, 100.);
+    assert!((inside.position.y - 101.).abs() < 1e-9);
+    assert_eq!(inside.velocity.y, 10.);
+}
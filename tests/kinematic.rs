@@ -0,0 +1,24 @@
+mod common;
+
+use balls::ball::{BallId, Kinematic};
+use common::{ball, balls_by_id, mark_ball, simulation_with_balls};
+
+#[test]
+fn kinematic_ball_plows_through_light_balls() {
+    let mut balls = vec![ball((50., 200.), (30., 0.), 20.)];
+    balls.extend((0..4).map(|i| ball((150. + 50. * i as f64, 200.), (0., 0.), 3.)));
+    let mut simulation = simulation_with_balls(&balls, 600, false);
+    mark_ball(&mut simulation, BallId(0), Kinematic);
+
+    for step in 1..=100 {
+        simulation.step();
+        let kinematic = balls_by_id(&simulation)[0].1;
+        assert_eq!(kinematic.velocity, nalgebra::Vector2::new(30., 0.));
+        let expected = 50. + 30. * 0.1 * step as f64;
+        assert!((kinematic.position.x - expected).abs() < 1e-6);
+    }
+    // Every light ball was hit, and bounced off as from an infinite mass.
+    for (_, light) in balls_by_id(&simulation).iter().skip(1) {
+        assert!((light.velocity.x - 60.).abs() < 1e-6, "{}", light.velocity);
+    }
+}
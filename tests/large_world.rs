@@ -0,0 +1,25 @@
+mod common;
+
+use common::{ball, balls_by_id, simulation_with_balls};
+
+// Far past 100 cells of CELL_SIZE, where the grid used to end whatever the size of the world.
+const SIZE: u32 = 3000;
+
+#[test]
+fn balls_collide_at_the_far_edge_of_a_large_world() {
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((2800., 2900.), (20., 0.), 10.),
+            ball((2900., 2900.), (0., 0.), 10.),
+        ],
+        SIZE,
+        true,
+    );
+    // The first ball stops the second one at 4 s, which hits the wall at 8.5 s.
+    simulation.run_for(10.);
+    let balls = balls_by_id(&simulation);
+    let (ball0, ball1) = (balls[0].1, balls[1].1);
+    assert!(ball0.velocity.norm() < 1e-9, "{}", ball0.velocity);
+    assert!((ball1.velocity.x + 20.).abs() < 1e-9, "{}", ball1.velocity);
+    assert!(ball1.position.x < SIZE as f64 - ball1.radius);
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use balls::ball::{BallId, LastImpulse};
+use common::{ball, simulation_with_balls};
+use legion::IntoQuery;
+
+// Impulse received by a resting ball hit by another one whose path is offset by the given
+// distance between centers.
+fn impulse_of_hit(offset: f64) -> LastImpulse {
+    let balls = [
+        ball((50., 100.), (10., 0.), 10.),
+        ball((100., 100. + offset), (0., 0.), 10.),
+    ];
+    let mut simulation = simulation_with_balls(&balls, 200, false);
+    // The hit comes before 4 s.
+    simulation.run_for(4.);
+    let last_impulse = <(&BallId, &LastImpulse)>::query()
+        .iter(&simulation.world)
+        .find(|(id, _)| **id == BallId(1))
+        .unwrap()
+        .1;
+    *last_impulse
+}
+
+#[test]
+fn head_on_hit_gives_a_larger_impulse() {
+    let head_on = impulse_of_hit(0.);
+    let glancing = impulse_of_hit(18.);
+    assert!(glancing.magnitude > 0.);
+    assert!(
+        head_on.magnitude > glancing.magnitude,
+        "{} <= {}",
+        head_on.magnitude,
+        glancing.magnitude
+    );
+    // Equal masses exchange the whole velocity head on.
+    assert!(
+        (head_on.magnitude - 10. * 100.).abs() < 1e-6,
+        "{:?}",
+        head_on
+    );
+    assert!((head_on.time - 3.).abs() < 1e-9, "{:?}", head_on);
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use balls::{
+    ball::BallId,
+    collision::collidable::{CollisionResponse, PhysicsConfig},
+};
+use common::{ball, balls_by_id, simulation_with_balls};
+use nalgebra::Vector2;
+
+#[test]
+fn merging_conserves_momentum_and_area() {
+    let balls = [
+        ball((150., 200.), (20., 5.), 10.),
+        ball((240., 200.), (-10., 5.), 5.),
+    ];
+    let mut simulation = simulation_with_balls(&balls, 400, true);
+    simulation
+        .resources
+        .get_mut::<PhysicsConfig>()
+        .unwrap()
+        .collision_response = CollisionResponse::Merge;
+    let momentum = balls[0].mass() * balls[0].velocity + balls[1].mass() * balls[1].velocity;
+    let area = balls[0].radius.powi(2) + balls[1].radius.powi(2);
+
+    // Contact at t = 2.5, before either reaches a wall.
+    for _ in 0..40 {
+        simulation.step();
+    }
+    let merged = balls_by_id(&simulation);
+    assert_eq!(merged.len(), 1);
+    // The heavier ball absorbs the other one.
+    let (id, ball) = merged[0];
+    assert_eq!(id, BallId(0));
+    assert!((ball.radius.powi(2) - area).abs() < 1e-9, "{}", ball.radius);
+    assert!((ball.mass() * ball.velocity - momentum).norm() < 1e-9);
+    assert!(
+        (ball.velocity - Vector2::new(14., 5.)).norm() < 1e-9,
+        "{}",
+        ball.velocity
+    );
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use balls::collision::CollisionDetectionData;
+use common::{ball, balls_by_id, simulation_with_balls};
+
+#[test]
+fn peeked_time_is_the_first_solved_time() {
+    // Surfaces 78 apart, closing at 40: contact at t = 1.95, within the 20th step.
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((151., 200.), (20., 0.), 10.),
+            ball((249., 200.), (-20., 0.), 10.),
+        ],
+        400,
+        false,
+    );
+    for _ in 0..19 {
+        simulation.step();
+    }
+    let peeked = simulation.next_collision_time().unwrap();
+    assert!((peeked - 1.95).abs() < 1e-9, "{}", peeked);
+    assert_eq!(
+        simulation
+            .resources
+            .get::<CollisionDetectionData>()
+            .unwrap()
+            .peek_next_collision_time(),
+        Some(peeked)
+    );
+
+    // Stepping resolves that collision first, at that time.
+    assert_eq!(simulation.step_to_next_collision(), Some(peeked));
+    let balls = balls_by_id(&simulation);
+    assert!(balls[0].1.velocity.x < 0. && balls[1].1.velocity.x > 0.);
+    assert!((balls[0].1.position.x - 190.).abs() < 1e-9);
+}
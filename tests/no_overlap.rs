@@ -0,0 +1,111 @@
+// Random scenes checked for overlapping balls and balls through walls at the end of every frame.
+// A failing scene is shrunk to the fewest and slowest balls that still fail.
+mod common;
+
+use balls::{
+    ball::BallId,
+    collision::collidable::EPSILON,
+    spawn::ball_components,
+    world_gen::{init_world, GenerationConfig},
+};
+use common::{ball, balls_by_id, headless_config, headless_simulation};
+use legion::World;
+use proptest::prelude::*;
+
+const SIZE: u32 = 400;
+// Balls start in distinct cells of a grid of this size, so they never start overlapping.
+const CELL: f64 = 50.;
+const MAX_RADIUS: f64 = 20.;
+const STEPS: usize = 200;
+// Speeds known to be resolved without penetration. Raise as the solver improves.
+const MAX_SPEED: f64 = 50.;
+
+// Cell, velocity and radius of each ball.
+fn scene() -> impl Strategy<Value = Vec<(usize, f64, f64, f64)>> {
+    let cells = (SIZE as f64 / CELL) as usize;
+    prop::collection::vec(
+        (
+            0..cells * cells,
+            -MAX_SPEED..MAX_SPEED,
+            -MAX_SPEED..MAX_SPEED,
+            5.0..MAX_RADIUS,
+        ),
+        1..12,
+    )
+}
+
+fn check_scene(scene: Vec<(usize, f64, f64, f64)>) -> Result<(), TestCaseError> {
+    let mut world = World::default();
+    init_world(
+        &mut world,
+        GenerationConfig::builder()
+            .width(SIZE)
+            .height(SIZE)
+            .n_balls(0)
+            .build(),
+    );
+    let cells = (SIZE as f64 / CELL) as usize;
+    let mut used = vec![false; cells * cells];
+    let mut next_id = 0;
+    for (cell, vx, vy, radius) in scene {
+        if used[cell] {
+            continue;
+        }
+        used[cell] = true;
+        let position = (
+            ((cell % cells) as f64 + 0.5) * CELL,
+            ((cell / cells) as f64 + 0.5) * CELL,
+        );
+        world.push(ball_components(
+            ball(position, (vx, vy), radius),
+            BallId(next_id),
+        ));
+        next_id += 1;
+    }
+    let mut simulation = headless_simulation(world, SIZE as f64, SIZE as f64, headless_config());
+
+    for step in 0..STEPS {
+        simulation.step();
+        let balls = balls_by_id(&simulation);
+        for (i, (id0, ball0)) in balls.iter().enumerate() {
+            for (id1, ball1) in balls[i + 1..].iter() {
+                let overlap =
+                    ball0.radius + ball1.radius - (ball0.position - ball1.position).norm();
+                prop_assert!(
+                    overlap <= EPSILON,
+                    "{:?} and {:?} overlap by {} after step {}",
+                    id0,
+                    id1,
+                    overlap,
+                    step
+                );
+            }
+            let penetration = [
+                ball0.radius - ball0.position.x,
+                ball0.radius - ball0.position.y,
+                ball0.position.x + ball0.radius - SIZE as f64,
+                ball0.position.y + ball0.radius - SIZE as f64,
+            ]
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+            prop_assert!(
+                penetration <= EPSILON,
+                "{:?} is {} into a wall after step {}",
+                id0,
+                penetration,
+                step
+            );
+        }
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn balls_never_overlap(scene in scene()) {
+        check_scene(scene)?;
+    }
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use balls::wall::{Wall, WallSide};
+use common::{ball, balls_by_id, push_wall, simulation_with_balls, wall};
+
+#[test]
+fn front_only_wall_blocks_from_the_front() {
+    let mut simulation = simulation_with_balls(
+        &[
+            // Comes from the front, where the normal points.
+            ball((100., 100.), (20., 0.), 5.),
+            // Comes from the back.
+            ball((300., 300.), (-20., 0.), 5.),
+        ],
+        400,
+        false,
+    );
+    // The normal points to -x.
+    let one_way = Wall {
+        side: WallSide::Front,
+        ..wall((200., 0.), (200., 400.))
+    };
+    assert!(one_way.normal().x < 0.);
+    push_wall(&mut simulation, one_way);
+
+    simulation.run_for(6.);
+    let balls = balls_by_id(&simulation);
+    let (front, back) = (balls[0].1, balls[1].1);
+    assert!((front.velocity.x + 20.).abs() < 1e-9, "{}", front.velocity);
+    assert!(front.position.x < 200., "{}", front.position);
+    assert!((back.velocity.x + 20.).abs() < 1e-9, "{}", back.velocity);
+    assert!(back.position.x < 200., "{}", back.position);
+}
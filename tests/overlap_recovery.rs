@@ -0,0 +1,54 @@
+mod common;
+
+use balls::spawn::separate_overlaps;
+use common::{ball, balls_by_id, simulation_with_balls};
+
+#[test]
+fn marginally_overlapping_balls_are_separated() {
+    // Overlapping by 1e-3, and closing far slower than epsilon.
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((100., 100.), (5e-7, 0.), 10.),
+            ball((119.999, 100.), (-5e-7, 0.), 10.),
+        ],
+        200,
+        false,
+    );
+    for _ in 0..5 {
+        simulation.step();
+    }
+    let balls = balls_by_id(&simulation);
+    let distance = (balls[0].1.position - balls[1].1.position).norm();
+    assert!(distance >= 20. - 1e-9, "{}", distance);
+    // Pushed apart, not launched.
+    for (_, ball) in balls.iter() {
+        assert!(ball.velocity.norm() < 1e-5, "{}", ball.velocity);
+    }
+}
+
+#[test]
+fn separated_balls_just_touch_at_rest() {
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((100., 100.), (0., 0.), 10.),
+            ball((110., 100.), (0., 0.), 10.),
+        ],
+        200,
+        false,
+    );
+    assert_eq!(separate_overlaps(&mut simulation.world), 0);
+    let balls = balls_by_id(&simulation);
+    let (ball0, ball1) = (balls[0].1, balls[1].1);
+    assert!(
+        ((ball1.position - ball0.position).norm() - 20.).abs() < 1e-9,
+        "{} {}",
+        ball0.position,
+        ball1.position
+    );
+    // Equal masses move by the same amount.
+    assert!(
+        ((ball0.position + ball1.position) / 2. - nalgebra::Vector2::new(105., 100.)).norm() < 1e-9
+    );
+    assert_eq!(ball0.velocity.norm(), 0.);
+    assert_eq!(ball1.velocity.norm(), 0.);
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use balls::collision::CollisionDetectionData;
+use common::{ball, balls_by_id, simulation_with_balls};
+
+#[test]
+fn giant_ball_collides_with_small_balls() {
+    // One small ball listed before the giant one and one after, so each side of the broad phase
+    // is tested. They reach it at 2 s, in cells the giant ball is not bucketed into.
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((450., 250.), (-20., 0.), 10.),
+            ball((250., 250.), (0., 0.), 150.),
+            ball((250., 50.), (0., 20.), 10.),
+        ],
+        500,
+        false,
+    );
+    assert!(
+        simulation
+            .resources
+            .get::<CollisionDetectionData>()
+            .unwrap()
+            .max_bucketed_radius
+            < 150.
+    );
+    simulation.run_for(3.);
+    let balls = balls_by_id(&simulation);
+    let (right, giant, below) = (balls[0].1, balls[1].1, balls[2].1);
+    assert!(right.velocity.x > 0., "{}", right.velocity);
+    assert!(below.velocity.y < 0., "{}", below.velocity);
+    // Pushed away from both, left and up.
+    assert!(
+        giant.velocity.x < 0. && giant.velocity.y > 0.,
+        "{}",
+        giant.velocity
+    );
+    for small in [right, below].iter() {
+        let gap = (small.position - giant.position).norm() - small.radius - giant.radius;
+        assert!(gap > 0., "{}", gap);
+    }
+}
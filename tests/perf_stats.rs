@@ -0,0 +1,26 @@
+mod common;
+
+use balls::{simulation::PerfStats, world_gen::GenerationConfig};
+use common::generated_simulation;
+
+#[test]
+fn steps_are_counted_without_rendering() {
+    let mut simulation = generated_simulation(
+        GenerationConfig::builder()
+            .width(200)
+            .height(200)
+            .n_balls(10)
+            .build(),
+    );
+    for _ in 0..100 {
+        simulation.step();
+    }
+    let perf_stats = simulation.resources.get::<PerfStats>().unwrap();
+    assert!(
+        perf_stats.steps_per_second > 0. && perf_stats.steps_per_second.is_finite(),
+        "{}",
+        perf_stats.steps_per_second
+    );
+    // No frame was rendered.
+    assert_eq!(perf_stats.frame_time, 0.);
+}
@@ -0,0 +1,47 @@
+use balls::{
+    collision::{PhysicsConfig, PhysicsParameter},
+    simulation::{adjust_physics_parameter, init_simulation, SimulationConfig},
+};
+use legion::Resources;
+
+#[test]
+fn adjustments_stay_within_range() {
+    let mut resources = Resources::default();
+    init_simulation(&mut resources, SimulationConfig::default());
+    adjust_physics_parameter(&mut resources, PhysicsParameter::Restitution, -0.05);
+    adjust_physics_parameter(&mut resources, PhysicsParameter::GravityScale, 0.1);
+    adjust_physics_parameter(&mut resources, PhysicsParameter::Drag, 0.01);
+    {
+        let physics_config = resources.get::<PhysicsConfig>().unwrap();
+        assert!((physics_config.restitution - 0.95).abs() < 1e-12);
+        assert!((physics_config.gravity_scale - 1.1).abs() < 1e-12);
+        assert!((physics_config.drag - 0.01).abs() < 1e-12);
+    }
+    // Clamped to the range of each parameter.
+    adjust_physics_parameter(&mut resources, PhysicsParameter::Restitution, 1.);
+    adjust_physics_parameter(&mut resources, PhysicsParameter::GravityScale, 100.);
+    adjust_physics_parameter(&mut resources, PhysicsParameter::Drag, -1.);
+    let physics_config = resources.get::<PhysicsConfig>().unwrap();
+    assert_eq!(physics_config.restitution, 1.);
+    assert_eq!(physics_config.gravity_scale, 10.);
+    assert_eq!(physics_config.drag, 0.);
+    // Nothing else changed.
+    assert_eq!(physics_config.epsilon, PhysicsConfig::default().epsilon);
+}
+
+#[test]
+fn parameters_are_found_by_name() {
+    for parameter in [
+        PhysicsParameter::Restitution,
+        PhysicsParameter::GravityScale,
+        PhysicsParameter::Drag,
+    ]
+    .iter()
+    {
+        assert_eq!(
+            PhysicsParameter::from_name(parameter.name()),
+            Some(*parameter)
+        );
+    }
+    assert_eq!(PhysicsParameter::from_name("gravity"), None);
+}
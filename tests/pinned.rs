@@ -0,0 +1,57 @@
+mod common;
+
+use balls::{
+    ball::{BallId, Pinned},
+    simulation::PerfStats,
+};
+use common::{ball, balls_by_id, headless_schedule_with, mark_ball, simulation_with_balls, Hook};
+use nalgebra::Vector2;
+
+#[test]
+fn ball_bounces_off_a_pinned_ball() {
+    let balls = [
+        ball((50., 100.), (20., 0.), 10.),
+        ball((150., 100.), (0., 0.), 10.),
+    ];
+    let mut simulation = simulation_with_balls(&balls, 200, false);
+    mark_ball(&mut simulation, BallId(1), Pinned);
+    simulation.schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::BeforeCollision {
+            builder.add_system(balls::advance::hold_pinned_balls_system());
+        }
+    });
+
+    // Contact at 4 s.
+    for _ in 0..60 {
+        simulation.step();
+        let pin = balls_by_id(&simulation)[1].1;
+        assert_eq!(pin.position, Vector2::new(150., 100.));
+        assert_eq!(pin.velocity, Vector2::new(0., 0.));
+    }
+    // Bounced back as from an infinite mass.
+    let moving = balls_by_id(&simulation)[0].1;
+    assert!(
+        (moving.velocity.x + 20.).abs() < 1e-9,
+        "{}",
+        moving.velocity
+    );
+    assert!(moving.position.x < 130. - 1e-9, "{}", moving.position);
+}
+
+#[test]
+fn overlapping_pinned_balls_do_not_collide() {
+    // Used to be predicted colliding right away in every pass, without ever separating.
+    let balls = [
+        ball((100., 100.), (0., 0.), 10.),
+        ball((105., 100.), (0., 0.), 10.),
+    ];
+    let mut simulation = simulation_with_balls(&balls, 200, false);
+    mark_ball(&mut simulation, BallId(0), Pinned);
+    mark_ball(&mut simulation, BallId(1), Pinned);
+    for _ in 0..10 {
+        simulation.step();
+        let perf_stats = simulation.resources.get::<PerfStats>().unwrap();
+        assert_eq!(perf_stats.collisions_last_frame, 0);
+    }
+    assert_eq!(simulation.next_collision_time(), None);
+}
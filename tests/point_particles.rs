@@ -0,0 +1,81 @@
+mod common;
+
+use balls::lifecycle::Lifecycle;
+use common::{ball, balls_by_id, simulation_with_balls};
+
+#[test]
+fn concentric_balls_are_pushed_apart() {
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((200., 200.), (0., 0.), 10.),
+            ball((200., 200.), (0., 0.), 10.),
+        ],
+        400,
+        true,
+    );
+    for _ in 0..10 {
+        simulation.step();
+    }
+    let balls = balls_by_id(&simulation);
+    for (_, ball) in balls.iter() {
+        assert!(
+            ball.position.iter().all(|x| x.is_finite()),
+            "{}",
+            ball.position
+        );
+        assert!(
+            ball.velocity.iter().all(|x| x.is_finite()),
+            "{}",
+            ball.velocity
+        );
+    }
+    let distance = (balls[0].1.position - balls[1].1.position).norm();
+    assert!(distance >= 20. - 1e-6, "{}", distance);
+}
+
+#[test]
+fn point_particles_collide_elastically() {
+    let mut simulation = simulation_with_balls(&[], 400, true);
+    {
+        let mut lifecycle = simulation.resources.get_mut::<Lifecycle>().unwrap();
+        assert!(!lifecycle.spawn(ball((200., 100.), (0., 0.), 0.), None));
+        assert!(!lifecycle.spawn(ball((200., 100.), (0., 0.), -1.), None));
+        // Surfaces 9.8 apart, closing at 20.
+        assert!(lifecycle.spawn(ball((100., 200.), (10., 5.), 0.1), None));
+        assert!(lifecycle.spawn(ball((110., 200.), (-10., 5.), 0.1), None));
+    }
+    let initial_energy = 2. * (10f64.powi(2) + 5f64.powi(2));
+    for _ in 0..10 {
+        simulation.step();
+    }
+    let balls = balls_by_id(&simulation);
+    assert_eq!(balls.len(), 2);
+    for (_, ball) in balls.iter() {
+        assert!(
+            ball.position.iter().all(|x| x.is_finite()),
+            "{}",
+            ball.position
+        );
+        assert!(
+            ball.velocity.iter().all(|x| x.is_finite()),
+            "{}",
+            ball.velocity
+        );
+    }
+    // They hit head on and have the same mass, so they exchange their velocities along x.
+    assert!(
+        (balls[0].1.velocity.x + 10.).abs() < 1e-9,
+        "{}",
+        balls[0].1.velocity
+    );
+    assert!(
+        (balls[1].1.velocity.x - 10.).abs() < 1e-9,
+        "{}",
+        balls[1].1.velocity
+    );
+    let energy = balls
+        .iter()
+        .map(|(_, ball)| ball.velocity.norm_squared())
+        .sum::<f64>();
+    assert!((energy - initial_energy).abs() < 1e-9 * initial_energy);
+}
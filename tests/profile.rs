@@ -0,0 +1,32 @@
+mod common;
+
+use balls::{
+    profile::{ProfileData, Stage},
+    world_gen::GenerationConfig,
+};
+use common::generated_simulation;
+use std::time::Duration;
+
+#[test]
+fn headless_stages_are_timed() {
+    let mut simulation = generated_simulation(
+        GenerationConfig::builder()
+            .width(800)
+            .height(800)
+            .n_balls(300)
+            .build(),
+    );
+    for _ in 0..10 {
+        simulation.step();
+    }
+    let profile_data = simulation.resources.get::<ProfileData>().unwrap();
+    for stage in [Stage::BroadPhase, Stage::NarrowPhase, Stage::Advance].iter() {
+        assert!(
+            profile_data.timing(*stage).last > Duration::default(),
+            "{:?}",
+            stage
+        );
+    }
+    // Nothing was rendered.
+    assert_eq!(profile_data.timing(Stage::Render).last, Duration::default());
+}
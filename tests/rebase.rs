@@ -0,0 +1,90 @@
+mod common;
+
+use balls::{
+    collision::CollisionDetectionData,
+    simulation::{rebase_time, Simulation, SimulationData},
+    world_gen::GenerationConfig,
+};
+use common::{balls_by_id, generated_simulation, state_bits};
+
+// Dense enough that the collision budget defers some collisions across the rebase.
+fn simulation() -> Simulation {
+    let mut simulation = generated_simulation(
+        GenerationConfig::builder()
+            .width(300)
+            .height(300)
+            .n_balls(40)
+            .build(),
+    );
+    simulation
+        .resources
+        .get_mut::<CollisionDetectionData>()
+        .unwrap()
+        .max_collisions_per_frame = Some(2);
+    simulation
+}
+
+fn absolute_next_collision_time(simulation: &mut Simulation) -> Option<f64> {
+    let time_offset = simulation
+        .resources
+        .get::<SimulationData>()
+        .unwrap()
+        .time_offset;
+    simulation
+        .next_collision_time()
+        .map(|time| time + time_offset)
+}
+
+#[test]
+fn rebase_preserves_the_motion() {
+    let mut rebased = simulation();
+    let mut reference = simulation();
+    for _ in 0..50 {
+        rebased.step();
+        reference.step();
+    }
+    {
+        let resources = &mut rebased.resources;
+        let mut simulation_data = resources.get_mut::<SimulationData>().unwrap();
+        let mut collision_detection_data = resources.get_mut::<CollisionDetectionData>().unwrap();
+        rebase_time(
+            &mut rebased.world,
+            &mut simulation_data,
+            &mut collision_detection_data,
+        );
+        assert_eq!(simulation_data.time, 0.);
+    }
+    assert_eq!(state_bits(&rebased), state_bits(&reference));
+
+    // Rounding differs after the rebase, keep the runs short enough not to amplify it much.
+    for _ in 0..20 {
+        let expected = absolute_next_collision_time(&mut reference);
+        let actual = absolute_next_collision_time(&mut rebased);
+        match (actual, expected) {
+            (Some(actual), Some(expected)) => {
+                assert!(
+                    (actual - expected).abs() < 1e-9,
+                    "{} != {}",
+                    actual,
+                    expected
+                )
+            }
+            _ => assert_eq!(actual, expected),
+        }
+        rebased.step();
+        reference.step();
+    }
+    for ((id, ball), (_, expected)) in balls_by_id(&rebased)
+        .iter()
+        .zip(balls_by_id(&reference).iter())
+    {
+        assert!(
+            (ball.position - expected.position).norm() < 1e-6,
+            "{:?}: {} != {}",
+            id,
+            ball.position,
+            expected.position
+        );
+        assert!((ball.velocity - expected.velocity).norm() < 1e-6);
+    }
+}
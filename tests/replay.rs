@@ -0,0 +1,148 @@
+mod common;
+
+use balls::{
+    collision::collidable::{CollisionResponse, PhysicsConfig},
+    emitter::Emitter,
+    replay::{dispatch_input, InputEvent, Recorder, Replayer},
+    simulation::Simulation,
+    world_gen::GenerationConfig,
+};
+use common::{generated_simulation, state_bits};
+use nalgebra::{Vector2, Vector3};
+
+const STEPS: usize = 1000;
+
+fn scene() -> GenerationConfig {
+    GenerationConfig::builder()
+        .width(400)
+        .height(400)
+        .n_balls(30)
+        .build()
+}
+
+// Inputs given at some steps of the recorded session.
+fn input_at(step: usize) -> Option<InputEvent> {
+    match step {
+        300 => Some(InputEvent::SpawnBall {
+            position: Vector2::new(200., 200.),
+            velocity: Vector2::new(20., -10.),
+            radius: 5.,
+            color: Vector3::new(1., 1., 1.),
+        }),
+        500 => Some(InputEvent::AdjustSpeed { factor: 1.1 }),
+        600 => Some(InputEvent::Reverse),
+        700 => Some(InputEvent::StepToCollision),
+        _ => None,
+    }
+}
+
+#[test]
+fn replay_reproduces_recorded_session() {
+    let path = std::env::temp_dir().join(format!("balls_replay_{}.txt", std::process::id()));
+
+    let mut recorded = generated_simulation(scene());
+    recorded
+        .resources
+        .insert(Recorder::create(&path).expect("Failed to create recording"));
+    for step in 0..STEPS {
+        if let Some(event) = input_at(step) {
+            dispatch_input(&mut recorded, event);
+        }
+        recorded.step();
+    }
+    recorded
+        .resources
+        .get_mut::<Recorder>()
+        .unwrap()
+        .flush()
+        .unwrap();
+
+    let mut replayed = generated_simulation(scene());
+    let mut replayer = Replayer::load(&path).expect("Failed to load recording");
+    for _ in 0..STEPS {
+        replayer.apply_due(&mut replayed);
+        replayed.step();
+    }
+    std::fs::remove_file(&path).ok();
+
+    assert!(replayer.is_finished());
+    let final_state = state_bits(&recorded);
+    // The spawned ball is there.
+    assert_eq!(final_state.len(), 31);
+    assert_eq!(final_state, state_bits(&replayed));
+}
+
+// Balls come and go: an emitter spawns balls that expire, balls merge, and input events spawn
+// more.
+fn lifecycle_simulation() -> Simulation {
+    let mut simulation = generated_simulation(scene());
+    simulation
+        .resources
+        .get_mut::<PhysicsConfig>()
+        .unwrap()
+        .collision_response = CollisionResponse::Merge;
+    simulation.world.push((Emitter {
+        position: Vector2::new(50., 350.),
+        velocity: Vector2::new(20., -5.),
+        radius: 4.,
+        color: Vector3::new(1., 1., 1.),
+        interval: 0.5,
+        remaining: 40,
+        next_spawn_time: 0.,
+        ttl: Some(6.),
+    },));
+    simulation
+}
+
+fn lifecycle_input_at(step: usize) -> Option<InputEvent> {
+    let position = match step {
+        100 => Vector2::new(100., 100.),
+        250 => Vector2::new(300., 100.),
+        400 => Vector2::new(200., 300.),
+        _ => return None,
+    };
+    Some(InputEvent::SpawnBall {
+        position,
+        velocity: Vector2::new(-15., 10.),
+        radius: 6.,
+        color: Vector3::new(1., 1., 1.),
+    })
+}
+
+#[test]
+fn replay_reproduces_spawns_and_despawns() {
+    let path =
+        std::env::temp_dir().join(format!("balls_replay_lifecycle_{}.txt", std::process::id()));
+
+    let mut recorded = lifecycle_simulation();
+    recorded
+        .resources
+        .insert(Recorder::create(&path).expect("Failed to create recording"));
+    for step in 0..STEPS {
+        if let Some(event) = lifecycle_input_at(step) {
+            dispatch_input(&mut recorded, event);
+        }
+        recorded.step();
+    }
+    recorded
+        .resources
+        .get_mut::<Recorder>()
+        .unwrap()
+        .flush()
+        .unwrap();
+
+    let mut replayed = lifecycle_simulation();
+    let mut replayer = Replayer::load(&path).expect("Failed to load recording");
+    for _ in 0..STEPS {
+        replayer.apply_due(&mut replayed);
+        replayed.step();
+    }
+    std::fs::remove_file(&path).ok();
+
+    let final_state = state_bits(&recorded);
+    // Balls were spawned after the generated ones, and some balls are gone.
+    let largest_id = final_state.iter().map(|(id, _)| id.0).max().unwrap();
+    assert!(largest_id >= 30);
+    assert!(final_state.len() < largest_id as usize + 1);
+    assert_eq!(final_state, state_bits(&replayed));
+}
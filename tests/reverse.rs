@@ -0,0 +1,60 @@
+mod common;
+
+use balls::ball::{Ball, Spin};
+use common::{ball, balls_by_id, simulation_with_balls};
+use legion::IntoQuery;
+
+const STEPS: usize = 100;
+
+// Steps forward, reverses and steps as long again, then checks every ball came back to where it
+// started, moving the other way.
+fn assert_retraces(balls: &[Ball]) {
+    let mut simulation = simulation_with_balls(balls, 1000, false);
+    let start = balls_by_id(&simulation);
+    for _ in 0..STEPS {
+        simulation.step();
+    }
+    simulation.reverse();
+    for _ in 0..STEPS {
+        simulation.step();
+    }
+    for ((id, ball), (_, initial)) in balls_by_id(&simulation).iter().zip(start.iter()) {
+        assert!(
+            (ball.position - initial.position).norm() < 1e-6,
+            "{:?}: {} != {}",
+            id,
+            ball.position,
+            initial.position
+        );
+        assert!((ball.velocity + initial.velocity).norm() < 1e-6);
+    }
+}
+
+#[test]
+fn reversal_retraces_free_motion() {
+    assert_retraces(&[
+        ball((100., 100.), (5., 3.), 10.),
+        ball((300., 300.), (-4., 1.), 10.),
+    ]);
+}
+
+#[test]
+fn reversal_retraces_a_collision() {
+    // Collide at t = 1.825 and part, within the 10 s of each run.
+    assert_retraces(&[
+        ball((451., 500.), (20., 0.), 10.),
+        ball((549., 500.), (-20., 0.), 15.),
+    ]);
+}
+
+#[test]
+fn reversal_negates_spin() {
+    let mut simulation = simulation_with_balls(&[ball((200., 200.), (0., 0.), 10.)], 400, false);
+    for spin in <&mut Spin>::query().iter_mut(&mut simulation.world) {
+        spin.angular_velocity = 2.;
+    }
+    simulation.reverse();
+    for spin in <&Spin>::query().iter(&simulation.world) {
+        assert_eq!(spin.angular_velocity, -2.);
+    }
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use balls::{simulation::PerfStats, world_gen::GenerationConfig};
+use common::{generated_simulation, simulation_with_balls};
+
+fn simulation() -> balls::simulation::Simulation {
+    generated_simulation(
+        GenerationConfig::builder()
+            .width(200)
+            .height(200)
+            .n_balls(10)
+            .build(),
+    )
+}
+
+#[test]
+fn run_for_takes_whole_steps() {
+    // The default time_delta is 0.1.
+    let mut simulation = simulation();
+    assert_eq!(simulation.run_for(2.0), 20);
+    assert_eq!(simulation.run_for(0.95), 10);
+}
+
+// Nothing collides, but every system still runs and time advances.
+fn runs_without_balls(walls: bool) {
+    let mut simulation = simulation_with_balls(&[], 200, walls);
+    assert_eq!(simulation.run_for(1.0), 10);
+    assert_eq!(simulation.next_collision_time(), None);
+    let perf_stats = simulation.resources.get::<PerfStats>().unwrap();
+    assert_eq!(perf_stats.collisions_last_frame, 0);
+}
+
+#[test]
+fn empty_world_runs() {
+    runs_without_balls(false);
+}
+
+#[test]
+fn walls_only_world_runs() {
+    runs_without_balls(true);
+}
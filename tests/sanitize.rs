@@ -0,0 +1,32 @@
+mod common;
+
+use balls::sanitize::SanitizeAction;
+use common::{ball, balls_by_id, headless_schedule_with, Hook};
+
+#[test]
+fn nan_velocity_is_quarantined() {
+    let balls = [
+        ball((50., 50.), (10., 0.), 10.),
+        ball((100., 100.), (f64::NAN, 0.), 10.),
+        ball((150., 150.), (0., -10.), 10.),
+    ];
+    let mut simulation = common::simulation_with_balls(&balls, 200, true);
+    simulation.schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::AfterAdvance {
+            builder.add_system(balls::sanitize::sanitize_balls_system());
+        }
+    });
+    simulation.resources.insert(SanitizeAction::Despawn);
+    for _ in 0..100 {
+        simulation.step();
+    }
+    let balls = balls_by_id(&simulation);
+    assert_eq!(
+        balls.iter().map(|(id, _)| id.0).collect::<Vec<_>>(),
+        vec![0, 2]
+    );
+    for (_, ball) in balls {
+        assert!(ball.position.iter().all(|x| x.is_finite()));
+        assert!(ball.velocity.iter().all(|x| x.is_finite()));
+    }
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use balls::{collision::PhysicsConfig, wall::Wall, world_gen::GenerationConfig};
+use common::{balls_by_id, generated_simulation, headless_schedule_with, Hook};
+use legion::IntoQuery;
+
+fn boxed_balls() -> balls::simulation::Simulation {
+    generated_simulation(
+        GenerationConfig::builder()
+            .width(400)
+            .height(400)
+            .n_balls(20)
+            .build(),
+    )
+}
+
+#[test]
+fn lossy_balls_in_a_box_settle() {
+    // There is no uniform gravity, energy is lost to drag and inelastic bounces instead.
+    let mut simulation = boxed_balls();
+    simulation.resources.insert(PhysicsConfig {
+        restitution: 0.8,
+        drag: 0.5,
+        ..PhysicsConfig::default()
+    });
+    for wall in <&mut Wall>::query().iter_mut(&mut simulation.world) {
+        wall.restitution = 0.8;
+    }
+    simulation.schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::BeforeCollision {
+            builder.add_system(balls::advance::apply_drag_system());
+        }
+    });
+    assert!(simulation.run_until_settled(1000, 0.1));
+    for (id, ball) in balls_by_id(&simulation) {
+        assert!(ball.velocity.norm() < 0.1, "{:?} {}", id, ball.velocity);
+    }
+}
+
+#[test]
+fn elastic_balls_never_settle() {
+    let mut simulation = boxed_balls();
+    assert!(!simulation.run_until_settled(200, 0.1));
+}
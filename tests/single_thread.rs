@@ -0,0 +1,27 @@
+// The global pool can only be set up once per process, so this file holds a single test.
+mod common;
+
+use balls::{simulation::init_thread_pool, world_gen::GenerationConfig};
+use common::{generated_simulation, state_bits};
+
+#[test]
+fn simulation_runs_on_a_single_thread() {
+    init_thread_pool(1);
+    assert_eq!(rayon::current_num_threads(), 1);
+    let run = || {
+        let mut simulation = generated_simulation(
+            GenerationConfig::builder()
+                .width(400)
+                .height(400)
+                .n_balls(40)
+                .build(),
+        );
+        for _ in 0..200 {
+            simulation.step();
+        }
+        state_bits(&simulation)
+    };
+    let first = run();
+    assert_eq!(first.len(), 40);
+    assert_eq!(first, run());
+}
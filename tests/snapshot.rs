@@ -0,0 +1,72 @@
+mod common;
+
+use balls::{
+    ball::{Ball, BallId},
+    lifetime::Lifetime,
+    simulation::Simulation,
+    world_gen::GenerationConfig,
+};
+use common::{balls_by_id, generated_simulation, state_bits};
+use legion::*;
+
+// Some balls expire during the extra steps, so restoring has to bring them back.
+fn scene() -> Simulation {
+    let mut simulation = generated_simulation(
+        GenerationConfig::builder()
+            .width(400)
+            .height(400)
+            .n_balls(30)
+            .build(),
+    );
+    let expiring = <(Entity, &BallId)>::query()
+        .iter(&simulation.world)
+        .filter(|(_, id)| id.0 < 5)
+        .map(|(entity, _)| *entity)
+        .collect::<Vec<_>>();
+    for entity in expiring {
+        simulation
+            .world
+            .entry(entity)
+            .unwrap()
+            .add_component(Lifetime {
+                spawn_time: 0.,
+                ttl: 1.,
+            });
+    }
+    simulation
+}
+
+fn run(simulation: &mut Simulation, steps: usize) {
+    for _ in 0..steps {
+        simulation.step();
+    }
+}
+
+fn assert_close(left: &[(BallId, Ball)], right: &[(BallId, Ball)]) {
+    assert_eq!(left.len(), right.len());
+    for ((id0, ball0), (id1, ball1)) in left.iter().zip(right.iter()) {
+        assert_eq!(id0, id1);
+        assert!((ball0.position - ball1.position).norm() < 1e-6, "{:?}", id0);
+        assert!((ball0.velocity - ball1.velocity).norm() < 1e-6, "{:?}", id0);
+    }
+}
+
+#[test]
+fn restore_undoes_extra_steps() {
+    let mut reference = scene();
+    run(&mut reference, 5);
+    run(&mut reference, 20);
+
+    let mut restored = scene();
+    run(&mut restored, 5);
+    let snapshot = restored.snapshot();
+    let snapshot_state = state_bits(&restored);
+    run(&mut restored, 10);
+    // The expiring balls are gone.
+    assert_eq!(balls_by_id(&restored).len(), 25);
+    restored.restore(&snapshot);
+    assert_eq!(state_bits(&restored), snapshot_state);
+    run(&mut restored, 20);
+
+    assert_close(&balls_by_id(&restored), &balls_by_id(&reference));
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use balls::{
+    ball::BallId, collision::CollisionDetectionData, lifecycle::Lifecycle, spawn::spawn_ball,
+    world_gen::GenerationConfig,
+};
+use common::{ball, balls_by_id, generated_simulation, simulation_with_balls};
+use legion::{Entity, IntoQuery};
+use std::collections::HashMap;
+
+#[test]
+fn ball_spawned_later_collides_at_the_right_time() {
+    let mut simulation = simulation_with_balls(&[ball((300., 100.), (0., 0.), 10.)], 400, false);
+    simulation
+        .resources
+        .get_mut::<CollisionDetectionData>()
+        .unwrap()
+        .record_collisions = true;
+    simulation.run_for(5.);
+
+    // Stamped with the wrong time on purpose. Spawned at 5 s, it reaches the other ball 9 s later.
+    let spawned = ball((100., 100.), (20., 0.), 10.);
+    assert_eq!(spawned.initial_time, 0.);
+    assert!(spawn_ball(
+        &mut simulation.resources.get_mut::<Lifecycle>().unwrap(),
+        spawned
+    ));
+    for _ in 0..200 {
+        simulation.step();
+        let collision_detection_data = simulation
+            .resources
+            .get::<CollisionDetectionData>()
+            .unwrap();
+        if let Some(record) = collision_detection_data.records().first() {
+            assert!((record.time - 14.).abs() < 1e-6, "{}", record.time);
+            return;
+        }
+    }
+    panic!("No collision");
+}
+
+fn ids_by_entity(simulation: &balls::simulation::Simulation) -> HashMap<Entity, BallId> {
+    <(Entity, &BallId)>::query()
+        .iter(&simulation.world)
+        .map(|(entity, id)| (*entity, *id))
+        .collect()
+}
+
+#[test]
+fn ball_ids_are_unique_and_stable() {
+    let mut simulation = generated_simulation(
+        GenerationConfig::builder()
+            .width(400)
+            .height(400)
+            .n_balls(30)
+            .build(),
+    );
+    let ids = ids_by_entity(&simulation);
+    let mut unique = ids.values().copied().collect::<Vec<_>>();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), 30);
+
+    simulation.run_for(5.);
+    assert_eq!(ids_by_entity(&simulation), ids);
+}
+
+#[test]
+fn spawned_balls_get_fresh_ids() {
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((100., 100.), (0., 0.), 10.),
+            ball((300., 300.), (0., 0.), 10.),
+        ],
+        400,
+        false,
+    );
+    let old_ids = balls_by_id(&simulation)
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+
+    let spawn = |simulation: &mut balls::simulation::Simulation| {
+        let before = ids_by_entity(simulation);
+        assert!(spawn_ball(
+            &mut simulation.resources.get_mut::<Lifecycle>().unwrap(),
+            ball((200., 200.), (0., 0.), 10.)
+        ));
+        simulation.step();
+        let mut new_ids = ids_by_entity(simulation)
+            .into_iter()
+            .filter(|(entity, _)| !before.contains_key(entity))
+            .map(|(_, id)| id);
+        let id = new_ids.next().unwrap();
+        assert_eq!(new_ids.next(), None);
+        id
+    };
+    let first = spawn(&mut simulation);
+    assert!(!old_ids.contains(&first));
+
+    // Not reused once the ball that had it is gone.
+    simulation
+        .resources
+        .get_mut::<Lifecycle>()
+        .unwrap()
+        .despawn(first);
+    simulation.step();
+    assert!(balls_by_id(&simulation).iter().all(|(id, _)| *id != first));
+    let second = spawn(&mut simulation);
+    assert!(!old_ids.contains(&second));
+    assert_ne!(second, first);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use balls::{
+    ball::{Ball, BallId, Spin, POINT_RADIUS},
+    collision::collidable::PhysicsConfig,
+    simulation::Simulation,
+};
+use common::{ball, simulation_with_balls};
+use legion::IntoQuery;
+use nalgebra::Vector2;
+
+fn cross(a: &Vector2<f64>, b: &Vector2<f64>) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+fn balls_and_spins(simulation: &Simulation) -> Vec<(Ball, f64)> {
+    let mut balls = <(&BallId, &Ball, &Spin)>::query()
+        .iter(&simulation.world)
+        .map(|(id, ball, spin)| (*id, *ball, spin.angular_velocity))
+        .collect::<Vec<_>>();
+    balls.sort_by_key(|(id, _, _)| *id);
+    balls
+        .into_iter()
+        .map(|(_, ball, angular_velocity)| (ball, angular_velocity))
+        .collect()
+}
+
+// Total of the orbital angular momentum around the origin and of the spins, discs having a moment
+// of inertia of m*r^2/2.
+fn angular_momentum(balls: &[(Ball, f64)]) -> f64 {
+    balls
+        .iter()
+        .map(|(ball, angular_velocity)| {
+            let radius = ball.radius.max(POINT_RADIUS);
+            ball.mass() * cross(&ball.position, &ball.velocity)
+                + ball.mass() * radius * radius / 2. * angular_velocity
+        })
+        .sum()
+}
+
+fn momentum(balls: &[(Ball, f64)]) -> Vector2<f64> {
+    balls
+        .iter()
+        .map(|(ball, _)| ball.mass() * ball.velocity)
+        .sum()
+}
+
+#[test]
+fn spinning_ball_transfers_spin() {
+    let mut simulation = simulation_with_balls(
+        &[
+            ball((150., 200.), (20., 0.), 10.),
+            ball((250., 200.), (0., 0.), 10.),
+        ],
+        400,
+        false,
+    );
+    simulation
+        .resources
+        .get_mut::<PhysicsConfig>()
+        .unwrap()
+        .ball_friction = 1.;
+    for (id, spin) in <(&BallId, &mut Spin)>::query().iter_mut(&mut simulation.world) {
+        if *id == BallId(0) {
+            spin.angular_velocity = 2.;
+        }
+    }
+    let initial = balls_and_spins(&simulation);
+
+    // Contact at t = 4.
+    for _ in 0..50 {
+        simulation.step();
+    }
+    let balls = balls_and_spins(&simulation);
+    let (hit, hit_spin) = balls[1];
+    assert!(hit_spin < 0., "{}", hit_spin);
+    assert!(hit.velocity.y > 0., "{}", hit.velocity);
+    assert!((momentum(&balls) - momentum(&initial)).norm() < 1e-9);
+    let expected = angular_momentum(&initial);
+    assert!(
+        (angular_momentum(&balls) - expected).abs() < 1e-9 * expected.abs(),
+        "{} != {}",
+        angular_momentum(&balls),
+        expected
+    );
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use balls::{
+    simulation::SimulationConfig,
+    stats::{scene_summary, world_stats},
+    world_gen::{GenerationConfig, SEED},
+};
+use common::{ball, simulation_with_balls};
+use nalgebra::Vector2;
+use std::f64::consts::PI;
+
+#[test]
+fn world_stats_of_known_balls() {
+    let simulation = simulation_with_balls(
+        &[
+            ball((50., 60.), (3., 4.), 10.),
+            ball((150., 100.), (0., 0.), 20.),
+        ],
+        200,
+        true,
+    );
+    let stats = world_stats(&simulation.world);
+    assert_eq!(stats.count, 2);
+    assert_eq!(
+        stats.bounds,
+        Some((Vector2::new(40., 50.), Vector2::new(170., 120.)))
+    );
+    assert!((stats.total_area - 500. * PI).abs() < 1e-9);
+    assert_eq!(stats.mean_radius, 15.);
+    assert_eq!(stats.max_radius, 20.);
+    assert_eq!(stats.mean_speed, 2.5);
+}
+
+#[test]
+fn world_stats_without_balls() {
+    let stats = world_stats(&simulation_with_balls(&[], 200, true).world);
+    assert_eq!(stats.count, 0);
+    assert_eq!(stats.bounds, None);
+    assert_eq!(stats.mean_radius, 0.);
+}
+
+#[test]
+fn scene_summary_of_known_balls() {
+    let simulation = simulation_with_balls(
+        &[
+            ball((50., 60.), (3., 4.), 10.),
+            ball((150., 100.), (0., 0.), 20.),
+        ],
+        200,
+        true,
+    );
+    let generation_config = GenerationConfig::builder().width(200).height(200).build();
+    let summary = scene_summary(
+        &simulation.world,
+        &generation_config,
+        &SimulationConfig::default(),
+        false,
+    );
+    assert_eq!((summary.balls, summary.walls), (2, 4));
+    assert_eq!((summary.world_width, summary.world_height), (200, 200));
+    assert_eq!(summary.seed, SEED);
+    assert_eq!(summary.time_delta, 0.1);
+    assert!(!summary.blur);
+    // Half of a mass of 100 times a squared speed of 25.
+    assert!((summary.kinetic_energy - 1250.).abs() < 1e-9);
+    assert_eq!(
+        summary.to_string(),
+        format!(
+            "2 balls, 4 walls, world 200x200, seed {:#x}, time delta 0.1, blur off, \
+             kinetic energy 1250.0",
+            SEED
+        )
+    );
+}
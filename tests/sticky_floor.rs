@@ -0,0 +1,75 @@
+mod common;
+
+use balls::{
+    ball::BallId,
+    integrator::{Acceleration, IntegratorKind},
+    simulation::SimulationConfig,
+    wall::Wall,
+};
+use common::{
+    ball, balls_by_id, headless_schedule_with, mark_ball, push_wall, simulation_with_balls, wall,
+    Hook,
+};
+use nalgebra::Vector2;
+
+#[test]
+fn balls_rest_on_a_sticky_floor() {
+    let balls = [
+        ball((50., 100.), (0., 0.), 10.),
+        ball((100., 60.), (0., -10.), 8.),
+        ball((150., 120.), (0., 20.), 12.),
+    ];
+    let mut simulation = simulation_with_balls(&balls, 200, false);
+    // A box whose floor, at y = 200, keeps none of the normal speed.
+    push_wall(
+        &mut simulation,
+        Wall {
+            restitution: 0.,
+            ..wall((0., 200.), (200., 200.))
+        },
+    );
+    push_wall(&mut simulation, wall((0., 0.), (0., 200.)));
+    push_wall(&mut simulation, wall((200., 0.), (200., 200.)));
+    push_wall(&mut simulation, wall((0., 0.), (200., 0.)));
+    // Gravity towards the floor.
+    for i in 0..balls.len() {
+        mark_ball(
+            &mut simulation,
+            BallId(i as u64),
+            Acceleration {
+                value: Vector2::new(0., 10.),
+            },
+        );
+    }
+    simulation.schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::BeforeCollision {
+            builder.add_system(balls::integrator::integrate_accelerations_system());
+        }
+    });
+    simulation.resources.insert(
+        SimulationConfig::builder()
+            .cap_frame_time(false)
+            .integrator(IntegratorKind::SemiImplicitEuler)
+            .build(),
+    );
+    for _ in 0..500 {
+        simulation.step();
+        for (id, ball) in balls_by_id(&simulation) {
+            assert!(
+                ball.position.y + ball.radius <= 200. + 1e-6,
+                "{:?} sank to {}",
+                id,
+                ball.position.y
+            );
+        }
+    }
+    // All of them reached the floor.
+    for (id, ball) in balls_by_id(&simulation) {
+        assert!(
+            ball.position.y + ball.radius > 200. - 1.,
+            "{:?} at {}",
+            id,
+            ball.position.y
+        );
+    }
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use balls::{
+    ball::Ball,
+    trace::{pick_ball, select_ball, TraceHistory},
+};
+use common::{ball, headless_schedule_with, simulation_with_balls, Hook};
+use legion::{EntityStore, IntoQuery};
+use nalgebra::Vector2;
+
+#[test]
+fn trace_has_one_point_per_step() {
+    let balls = [
+        ball((50., 100.), (10., 0.), 10.),
+        ball((150., 100.), (0., 10.), 10.),
+    ];
+    let mut simulation = simulation_with_balls(&balls, 200, true);
+    simulation.schedule = headless_schedule_with(|builder, hook| {
+        if hook == Hook::AfterAdvance {
+            builder.add_system(balls::trace::record_trace_system());
+        }
+    });
+    let selected = pick_ball(&simulation.world, Vector2::new(52., 101.)).unwrap();
+    select_ball(&mut simulation.world, Some(selected));
+    for _ in 0..20 {
+        simulation.step();
+    }
+    let entry = simulation.world.entry_ref(selected).unwrap();
+    let trace = entry.get_component::<TraceHistory>().unwrap();
+    assert_eq!(trace.points.len(), 20);
+    assert_eq!(
+        *trace.points.last().unwrap(),
+        entry.get_component::<Ball>().unwrap().position
+    );
+    // Only the selected ball is traced.
+    assert_eq!(<&TraceHistory>::query().iter(&simulation.world).count(), 1);
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use balls::ball::Trails;
+use common::{ball, simulation_with_balls};
+use legion::{IntoQuery, Schedule};
+
+const MAX_TRAIL_HISTORY: usize = 3;
+
+#[test]
+fn trails_of_previous_frames_are_bounded() {
+    let mut simulation = simulation_with_balls(&[ball((100., 100.), (10., 0.), 5.)], 400, false);
+    simulation.schedule = Schedule::builder()
+        .add_system(balls::advance::clear_trails_system(MAX_TRAIL_HISTORY))
+        .add_system(balls::collision::collision_system())
+        .add_system(balls::collision::collision_handle_system())
+        .add_system(balls::advance::advance_balls_system())
+        .add_system(balls::simulation::advance_time_system())
+        .build();
+
+    for step in 1..20 {
+        simulation.step();
+        let count = <&Trails>::query()
+            .iter(&simulation.world)
+            .next()
+            .unwrap()
+            .trails
+            .len();
+        // Without collisions, a ball leaves one trail per frame, and the one of the frame just
+        // advanced comes on top of the history.
+        assert_eq!(count, step.min(MAX_TRAIL_HISTORY + 1));
+    }
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use balls::{ball::Ball, simulation::Simulation};
+use common::{ball, balls_by_id, simulation_with_balls};
+use nalgebra::Vector2;
+
+// 5 s at the default time_delta of 0.1, well past the impact.
+const STEPS: usize = 50;
+
+// Two balls alone in a world without walls, so they only hit each other.
+fn two_body_simulation(ball0: Ball, ball1: Ball) -> Simulation {
+    let mut simulation = simulation_with_balls(&[ball0, ball1], 400, false);
+    for _ in 0..STEPS {
+        simulation.step();
+    }
+    simulation
+}
+
+fn velocities(simulation: &Simulation) -> (Vector2<f64>, Vector2<f64>) {
+    let balls = balls_by_id(simulation);
+    assert_eq!(balls.len(), 2);
+    (balls[0].1.velocity, balls[1].1.velocity)
+}
+
+#[test]
+fn equal_masses_swap_velocities() {
+    let simulation = two_body_simulation(
+        ball((150., 200.), (20., 0.), 10.),
+        ball((250., 200.), (-10., 0.), 10.),
+    );
+    let (v0, v1) = velocities(&simulation);
+    assert!((v0 - Vector2::new(-10., 0.)).norm() < 1e-9, "{}", v0);
+    assert!((v1 - Vector2::new(20., 0.)).norm() < 1e-9, "{}", v1);
+}
+
+#[test]
+fn unequal_masses_follow_elastic_formula() {
+    let ball0 = ball((100., 200.), (30., 0.), 10.);
+    let ball1 = ball((200., 210.), (0., 0.), 20.);
+    let simulation = two_body_simulation(ball0, ball1);
+    let (v0, v1) = velocities(&simulation);
+
+    // Contact when the centers are 30 apart: (30t - 100)^2 + 10^2 = 30^2.
+    let t = (100. - 800f64.sqrt()) / 30.;
+    let dx = ball0.position + ball0.velocity * t - ball1.position;
+    let dv = ball0.velocity - ball1.velocity;
+    let (m0, m1) = (ball0.mass(), ball1.mass());
+    let exchange = dv.dot(&dx) / dx.norm_squared() * dx;
+    let expected0 = ball0.velocity - 2. * m1 / (m0 + m1) * exchange;
+    let expected1 = ball1.velocity + 2. * m0 / (m0 + m1) * exchange;
+    assert!((v0 - expected0).norm() < 1e-6, "{} != {}", v0, expected0);
+    assert!((v1 - expected1).norm() < 1e-6, "{} != {}", v1, expected1);
+
+    // Momentum and kinetic energy are conserved.
+    let momentum = m0 * v0 + m1 * v1;
+    assert!((momentum - m0 * ball0.velocity).norm() < 1e-6);
+    let energy = m0 * v0.norm_squared() + m1 * v1.norm_squared();
+    let initial_energy = m0 * ball0.velocity.norm_squared();
+    assert!((energy - initial_energy).abs() < 1e-9 * initial_energy);
+}
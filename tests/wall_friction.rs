@@ -0,0 +1,34 @@
+mod common;
+
+use balls::wall::Wall;
+use common::{ball, balls_by_id, push_wall, simulation_with_balls, wall};
+
+// Angle between the wall and the velocity of a ball dropped onto a sloped wall, after the bounce.
+fn exit_angle(friction: f64) -> f64 {
+    let mut simulation = simulation_with_balls(&[ball((100., 250.), (0., -20.), 5.)], 400, false);
+    let sloped = Wall {
+        friction,
+        ..wall((0., 100.), (400., 300.))
+    };
+    push_wall(&mut simulation, sloped);
+    // The bounce is a little before 5 s.
+    simulation.run_for(8.);
+    let velocity = balls_by_id(&simulation)[0].1.velocity;
+    let tangent = (sloped.p1 - sloped.p0).normalize();
+    let normal = sloped.normal();
+    assert!(velocity.dot(&normal) > 0., "{}", velocity);
+    velocity.dot(&normal).atan2(velocity.dot(&tangent).abs())
+}
+
+#[test]
+fn friction_sends_the_ball_off_steeper() {
+    let frictionless = exit_angle(0.);
+    // Mirrors the incoming angle.
+    assert!(
+        (frictionless - 2f64.atan()).abs() < 1e-9,
+        "{}",
+        frictionless
+    );
+    let rough = exit_angle(0.8);
+    assert!(rough > frictionless + 0.1, "{} <= {}", rough, frictionless);
+}
@@ -0,0 +1,49 @@
+use balls::{
+    ball::Ball,
+    world_gen::{init_world, GenerationConfig},
+};
+use legion::{IntoQuery, World};
+
+fn generated_balls(config: GenerationConfig) -> Vec<Ball> {
+    let mut world = World::default();
+    init_world(&mut world, config);
+    <&Ball>::query().iter(&world).copied().collect()
+}
+
+// Checks every pair, unlike the grid of init_balls.
+fn assert_placed_apart(balls: &[Ball], config: &GenerationConfig) {
+    for (i, ball0) in balls.iter().enumerate() {
+        assert!(ball0.position.x >= ball0.radius);
+        assert!(ball0.position.x <= config.width as f64 - ball0.radius);
+        assert!(ball0.position.y >= ball0.radius);
+        assert!(ball0.position.y <= config.height as f64 - ball0.radius);
+        for ball1 in balls.iter().skip(i + 1) {
+            let distance = (ball0.position - ball1.position).norm();
+            assert!(distance > ball0.radius + ball1.radius);
+        }
+    }
+}
+
+#[test]
+fn generated_balls_do_not_overlap() {
+    let config = GenerationConfig::builder()
+        .width(3000)
+        .height(3000)
+        .n_balls(1000)
+        .build();
+    let balls = generated_balls(config);
+    assert_eq!(balls.len(), 1000);
+    assert_placed_apart(&balls, &config);
+}
+
+#[test]
+fn crowded_world_places_fewer_balls_without_overlaps() {
+    let config = GenerationConfig::builder()
+        .width(300)
+        .height(300)
+        .n_balls(1000)
+        .build();
+    let balls = generated_balls(config);
+    assert!(!balls.is_empty() && balls.len() < 1000, "{}", balls.len());
+    assert_placed_apart(&balls, &config);
+}